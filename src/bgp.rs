@@ -1,12 +1,17 @@
-extern crate hex;
-use nom::{be_u16, be_u8, IResult};
+use bgpsec::{parse_bgpsec_path, BgpsecPath};
+use nom::{be_u16, be_u32, be_u64, be_u8, ErrorKind, IResult};
 use num_traits::cast::FromPrimitive;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::fmt;
+use std::str::FromStr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 // https://www.iana.org/assignments/bgp-parameters/bgp-parameters.txt
 #[allow(non_camel_case_types)]
 #[derive(Debug, FromPrimitive)]
-enum BGPPathAttrTypes {
+pub enum BGPPathAttrTypes {
     // RFC 4271, page 17
     BGP_PATH_ATTR_ORIGIN = 1,
     BGP_PATH_ATTR_ASPATH = 2,
@@ -17,16 +22,88 @@ enum BGPPathAttrTypes {
     BGP_PATH_ATTR_AGGREGATOR = 7,
     // RFC 1997,
     BGP_PATH_ATTR_COMMUNITY = 8,
+    // RFC 4456
+    BGP_PATH_ATTR_ORIGINATOR_ID = 9,
+    BGP_PATH_ATTR_CLUSTER_LIST = 10,
     // rfc4760, page 3,
     BGP_PATH_ATTR_MP_REACH_NLRI = 14,
+    BGP_PATH_ATTR_MP_UNREACH_NLRI = 15,
+    // RFC 4360
+    BGP_PATH_ATTR_EXT_COMMUNITY = 16,
+    // RFC 6793
+    BGP_PATH_ATTR_AS4_PATH = 17,
+    BGP_PATH_ATTR_AS4_AGGREGATOR = 18,
+    // RFC 8092
+    BGP_PATH_ATTR_LARGE_COMMUNITY = 32,
+    // RFC 8205
+    BGP_PATH_ATTR_BGPSEC_PATH = 33,
+}
+
+// IANA name of a path attribute type code, where this crate knows it.
+pub fn path_attribute_name(code: u8) -> Option<&'static str> {
+    let name = match BGPPathAttrTypes::from_u8(code)? {
+        BGPPathAttrTypes::BGP_PATH_ATTR_ORIGIN => "ORIGIN",
+        BGPPathAttrTypes::BGP_PATH_ATTR_ASPATH => "AS_PATH",
+        BGPPathAttrTypes::BGP_PATH_ATTR_NEXTHOP => "NEXT_HOP",
+        BGPPathAttrTypes::BGP_PATH_ATTR_EXITDISC => "MULTI_EXIT_DISC",
+        BGPPathAttrTypes::BGP_PATH_ATTR_LOCALPREF => "LOCAL_PREF",
+        BGPPathAttrTypes::BGP_PATH_ATTR_ATOM_AGG => "ATOMIC_AGGREGATE",
+        BGPPathAttrTypes::BGP_PATH_ATTR_AGGREGATOR => "AGGREGATOR",
+        BGPPathAttrTypes::BGP_PATH_ATTR_COMMUNITY => "COMMUNITIES",
+        BGPPathAttrTypes::BGP_PATH_ATTR_ORIGINATOR_ID => "ORIGINATOR_ID",
+        BGPPathAttrTypes::BGP_PATH_ATTR_CLUSTER_LIST => "CLUSTER_LIST",
+        BGPPathAttrTypes::BGP_PATH_ATTR_MP_REACH_NLRI => "MP_REACH_NLRI",
+        BGPPathAttrTypes::BGP_PATH_ATTR_MP_UNREACH_NLRI => "MP_UNREACH_NLRI",
+        BGPPathAttrTypes::BGP_PATH_ATTR_EXT_COMMUNITY => "EXTENDED_COMMUNITIES",
+        BGPPathAttrTypes::BGP_PATH_ATTR_AS4_PATH => "AS4_PATH",
+        BGPPathAttrTypes::BGP_PATH_ATTR_AS4_AGGREGATOR => "AS4_AGGREGATOR",
+        BGPPathAttrTypes::BGP_PATH_ATTR_LARGE_COMMUNITY => "LARGE_COMMUNITY",
+        BGPPathAttrTypes::BGP_PATH_ATTR_BGPSEC_PATH => "BGPsec_PATH",
+    };
+    Some(name)
 }
 
 #[derive(Debug, PartialEq)]
-pub struct BGPPathAttribute {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BGPPathAttribute<'a> {
     pub flags: u8,
     pub code: u8,
     pub len: u16,
-    pub data: Vec<u8>,
+    // borrowed from the input buffer as parsed
+    pub data: Cow<'a, [u8]>,
+}
+
+// Width of the AS numbers carried in an AS_PATH. TABLE_DUMP and the older
+// BGP4MP subtypes use 2-octet ASNs, TABLE_DUMP_V2 always uses 4 (RFC 6396).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AsnSize {
+    Two,
+    Four,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AsPathSegmentType {
+    AS_SET = 1,
+    AS_SEQUENCE = 2,
+    // RFC 5065
+    AS_CONFED_SEQUENCE = 3,
+    AS_CONFED_SET = 4,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AsPathSegment {
+    pub segment_type: AsPathSegmentType,
+    pub asns: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AsPath {
+    pub segments: Vec<AsPathSegment>,
 }
 
 // Each AS path segment is
@@ -62,64 +139,742 @@ pub struct BGPPathAttribute {
 //    0, 0, 81, 35,
 //    0, 0, 13, 28
 
-fn parse_as_path(fmt: &mut fmt::Formatter, buffer: &Vec<u8>) {
-    let mut i = 0;
-    while i < buffer.len() {
-        match buffer[i] {
-            1 => {
-                write!(fmt, "AS_SET");
-                i += 1;
-                let asn_count = buffer[i];
-                write!(fmt, "[");
-                i += 1;
-                let mut j = 0;
-                while j < asn_count {
-                    if j != 0 {
-                        write!(fmt, " ");
-                    }
-                    write!(
-                        fmt,
-                        "{:02x}{:02x}{:02x}{:02x}",
-                        buffer[i],
-                        buffer[i + 1],
-                        buffer[i + 2],
-                        buffer[i + 3]
-                    );
-                    j += 1;
-                    i += 4;
+pub fn parse_asn(input: &[u8], asn_size: AsnSize) -> IResult<&[u8], u32> {
+    match asn_size {
+        AsnSize::Two => map!(input, be_u16, u32::from),
+        AsnSize::Four => be_u32(input),
+    }
+}
+
+fn parse_as_path_segment(input: &[u8], asn_size: AsnSize) -> IResult<&[u8], AsPathSegment> {
+    do_parse!(
+        input,
+        segment_type: map_opt!(be_u8, AsPathSegmentType::from_u8)
+            >> asn_count: be_u8
+            >> asns: count!(call!(parse_asn, asn_size), asn_count as usize)
+            >> (AsPathSegment { segment_type, asns })
+    )
+}
+
+// The attribute carries no segment count, so consume segments until the
+// attribute payload is exhausted.
+pub fn parse_as_path(mut input: &[u8], asn_size: AsnSize) -> IResult<&[u8], AsPath> {
+    let mut segments = Vec::new();
+    while !input.is_empty() {
+        let (rest, segment) = parse_as_path_segment(input, asn_size)?;
+        segments.push(segment);
+        input = rest;
+    }
+    Ok((input, AsPath { segments }))
+}
+
+impl fmt::Display for AsPathSegment {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        // Same notation as bgpdump/quagga: sets are braced and comma
+        // separated, confederation segments use () and [].
+        let (open, sep, close) = match self.segment_type {
+            AsPathSegmentType::AS_SET => ("{", ",", "}"),
+            AsPathSegmentType::AS_SEQUENCE => ("", " ", ""),
+            AsPathSegmentType::AS_CONFED_SEQUENCE => ("(", " ", ")"),
+            AsPathSegmentType::AS_CONFED_SET => ("[", ",", "]"),
+        };
+        fmt.write_str(open)?;
+        for (i, asn) in self.asns.iter().enumerate() {
+            if i != 0 {
+                fmt.write_str(sep)?;
+            }
+            write!(fmt, "{}", asn)?;
+        }
+        fmt.write_str(close)
+    }
+}
+
+impl fmt::Display for AsPath {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i != 0 {
+                fmt.write_str(" ")?;
+            }
+            write!(fmt, "{}", segment)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> BGPPathAttribute<'a> {
+    // A copy that no longer borrows the input buffer.
+    pub fn into_owned(self) -> BGPPathAttribute<'static> {
+        BGPPathAttribute { data: Cow::Owned(self.data.into_owned()), ..self }
+    }
+
+    pub fn as_path(&self, asn_size: AsnSize) -> Option<AsPath> {
+        match BGPPathAttrTypes::from_u8(self.code) {
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_ASPATH) => {
+                parse_as_path(&self.data, asn_size).ok().map(|r| r.1)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn bgpsec_path(&self) -> Option<BgpsecPath> {
+        match BGPPathAttrTypes::from_u8(self.code) {
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_BGPSEC_PATH) => {
+                parse_bgpsec_path(&self.data).ok().map(|r| r.1)
+            }
+            _ => None,
+        }
+    }
+}
+
+// A route carries either an AS_PATH or, between BGPsec speakers, a
+// BGPsec_PATH (RFC 8205, section 4.4); callers wanting "the path" should not
+// have to care which. Sessions without 4-octet AS support carry the real path
+// in AS4_PATH, which is merged back in as per RFC 6793, section 4.2.3.
+pub fn find_as_path(attrs: &[BGPPathAttribute], asn_size: AsnSize) -> Option<AsPath> {
+    let as_path = attrs.iter().filter_map(|attr| attr.as_path(asn_size)).next();
+    match as_path {
+        Some(path) => {
+            if asn_size == AsnSize::Two {
+                let as4_path = attrs.iter().filter_map(|attr| attr.as4_path()).next();
+                if let Some(as4_path) = as4_path {
+                    return Some(path.merge_as4_path(&as4_path));
+                }
+            }
+            Some(path)
+        }
+        None => attrs
+            .iter()
+            .filter_map(|attr| attr.bgpsec_path())
+            .map(|path| path.as_path())
+            .next(),
+    }
+}
+
+impl AsPath {
+    // Path length as used for route selection: a set counts as one hop and
+    // confederation segments do not count at all.
+    pub fn hop_count(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|s| match s.segment_type {
+                AsPathSegmentType::AS_SEQUENCE => s.asns.len(),
+                AsPathSegmentType::AS_SET => 1,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    // Whether asn appears anywhere in the path, sets included.
+    pub fn contains_asn(&self, asn: u32) -> bool {
+        self.segments.iter().any(|s| s.asns.contains(&asn))
+    }
+
+    // The originating AS, if the path ends in a sequence.
+    pub fn origin_asn(&self) -> Option<u32> {
+        match self.segments.last() {
+            Some(s) if s.segment_type == AsPathSegmentType::AS_SEQUENCE => s.asns.last().cloned(),
+            Some(s) if s.segment_type == AsPathSegmentType::AS_SET && s.asns.len() == 1 => {
+                s.asns.last().cloned()
+            }
+            _ => None,
+        }
+    }
+
+    pub fn merge_as4_path(&self, as4_path: &AsPath) -> AsPath {
+        let hop_count = self.hop_count();
+        let as4_hop_count = as4_path.hop_count();
+        if hop_count < as4_hop_count {
+            return self.clone();
+        }
+
+        // Keep the leading (most recent) hops of AS_PATH that were added by
+        // 2-octet-only speakers, then append AS4_PATH.
+        let mut keep = hop_count - as4_hop_count;
+        let mut segments = Vec::new();
+        for segment in &self.segments {
+            if keep == 0 {
+                break;
+            }
+            match segment.segment_type {
+                AsPathSegmentType::AS_SEQUENCE => {
+                    let n = keep.min(segment.asns.len());
+                    segments.push(AsPathSegment {
+                        segment_type: segment.segment_type,
+                        asns: segment.asns[..n].to_vec(),
+                    });
+                    keep -= n;
                 }
-                write!(fmt, "]");
-            }
-            2 => {
-                write!(fmt, "AS_SEQ:");
-                i += 1;
-                let asn_count = buffer[i];
-                i += 1;
-                let mut j = 0;
-                let mut aspath = String::with_capacity((asn_count * 4 + (asn_count - 1)) as usize);
-                while j < asn_count {
-                    if j != 0 {
-                        write!(fmt, " ");
+                AsPathSegmentType::AS_SET => {
+                    segments.push(segment.clone());
+                    keep -= 1;
+                }
+                _ => segments.push(segment.clone()),
+            }
+        }
+        for segment in &as4_path.segments {
+            let merge = match segments.last() {
+                Some(last) => {
+                    last.segment_type == AsPathSegmentType::AS_SEQUENCE
+                        && segment.segment_type == AsPathSegmentType::AS_SEQUENCE
+                }
+                None => false,
+            };
+            if merge {
+                let last = segments.len() - 1;
+                segments[last].asns.extend_from_slice(&segment.asns);
+            } else {
+                segments.push(segment.clone());
+            }
+        }
+        AsPath { segments }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Origin {
+    IGP = 0,
+    EGP = 1,
+    INCOMPLETE = 2,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Origin::IGP => fmt.write_str("IGP"),
+            Origin::EGP => fmt.write_str("EGP"),
+            Origin::INCOMPLETE => fmt.write_str("INCOMPLETE"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Aggregator {
+    pub asn: u32,
+    pub address: Ipv4Addr,
+}
+
+// RFC 1997
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Community(pub u32);
+
+pub const COMMUNITY_NO_EXPORT: u32 = 0xFFFF_FF01;
+pub const COMMUNITY_NO_ADVERTISE: u32 = 0xFFFF_FF02;
+pub const COMMUNITY_NO_EXPORT_SUBCONFED: u32 = 0xFFFF_FF03;
+
+impl Community {
+    pub fn asn(&self) -> u16 {
+        (self.0 >> 16) as u16
+    }
+
+    pub fn value(&self) -> u16 {
+        self.0 as u16
+    }
+}
+
+impl fmt::Display for Community {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        // well-known names as printed by bgpdump
+        match self.0 {
+            COMMUNITY_NO_EXPORT => fmt.write_str("no-export"),
+            COMMUNITY_NO_ADVERTISE => fmt.write_str("no-advertise"),
+            COMMUNITY_NO_EXPORT_SUBCONFED => fmt.write_str("local-AS"),
+            _ => write!(fmt, "{}:{}", self.asn(), self.value()),
+        }
+    }
+}
+
+// RFC 4360: a type octet, a subtype octet for the types used here, and six
+// octets split between a global and a local administrator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ExtendedCommunity(pub u64);
+
+impl ExtendedCommunity {
+    pub fn type_high(&self) -> u8 {
+        (self.0 >> 56) as u8
+    }
+
+    pub fn subtype(&self) -> u8 {
+        (self.0 >> 48) as u8
+    }
+
+    // ("rt" or "soo", global administrator, local administrator) for the
+    // route target and route origin communities of the 2-octet AS, IPv4
+    // address and 4-octet AS types (RFC 4360, RFC 5668), transitive or not.
+    pub fn parts(&self) -> Option<(&'static str, String, u32)> {
+        let label = match self.subtype() {
+            0x02 => "rt",
+            0x03 => "soo",
+            _ => return None,
+        };
+        let value = self.0 & 0xffff_ffff_ffff;
+        match self.type_high() & !0x40 {
+            0x00 => Some((label, (value >> 32).to_string(), value as u32)),
+            0x01 => Some((label, Ipv4Addr::from((value >> 16) as u32).to_string(), value as u16 as u32)),
+            0x02 => Some((label, (value >> 16).to_string(), value as u16 as u32)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ExtendedCommunity {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.parts() {
+            Some((label, global, local)) => write!(fmt, "{}:{}:{}", label, global, local),
+            None => write!(fmt, "0x{:016x}", self.0),
+        }
+    }
+}
+
+// RFC 8092
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LargeCommunity {
+    pub global_admin: u32,
+    pub local_data_1: u32,
+    pub local_data_2: u32,
+}
+
+impl fmt::Display for LargeCommunity {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}:{}:{}", self.global_admin, self.local_data_1, self.local_data_2)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Afi {
+    IPv4 = 1,
+    IPv6 = 2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Prefix {
+    pub addr: IpAddr,
+    pub len: u8,
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}/{}", self.addr, self.len)
+    }
+}
+
+impl Prefix {
+    pub fn afi(&self) -> Afi {
+        match self.addr {
+            IpAddr::V4(_) => Afi::IPv4,
+            IpAddr::V6(_) => Afi::IPv6,
+        }
+    }
+
+    // Build a prefix from the truncated address octets used in NLRI and
+    // TABLE_DUMP_V2 RIB records.
+    pub fn from_octets(afi: Afi, octets: &[u8], len: u8) -> Option<Prefix> {
+        let addr = match afi {
+            Afi::IPv4 if len <= 32 && octets.len() <= 4 => {
+                let mut buf = [0u8; 4];
+                buf[..octets.len()].copy_from_slice(octets);
+                IpAddr::V4(Ipv4Addr::from(buf))
+            }
+            Afi::IPv6 if len <= 128 && octets.len() <= 16 => {
+                let mut buf = [0u8; 16];
+                buf[..octets.len()].copy_from_slice(octets);
+                IpAddr::V6(Ipv6Addr::from(buf))
+            }
+            _ => return None,
+        };
+        Some(Prefix { addr, len })
+    }
+
+    // True if other is this prefix or one of its more-specifics.
+    pub fn contains(&self, other: &Prefix) -> bool {
+        if other.len < self.len {
+            return false;
+        }
+        match (self.addr, other.addr) {
+            (IpAddr::V4(a), IpAddr::V4(b)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.len)).unwrap_or(0);
+                u32::from(a) & mask == u32::from(b) & mask
+            }
+            (IpAddr::V6(a), IpAddr::V6(b)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.len)).unwrap_or(0);
+                u128::from(a) & mask == u128::from(b) & mask
+            }
+            _ => false,
+        }
+    }
+
+    // The prefix of length len covering this one; the prefix itself if it is
+    // no longer than len.
+    pub fn supernet(&self, len: u8) -> Prefix {
+        if len >= self.len {
+            return *self;
+        }
+        let addr = match self.addr {
+            IpAddr::V4(a) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(len)).unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(a) & mask))
+            }
+            IpAddr::V6(a) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(len)).unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(a) & mask))
+            }
+        };
+        Prefix { addr, len }
+    }
+}
+
+// "192.0.2.0/24", "2001:db8::/32"; a bare address is a host route.
+impl FromStr for Prefix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Prefix, String> {
+        let bad = || format!("Bad prefix '{}'", s);
+        let (addr, len) = match s.split_once('/') {
+            Some((addr, len)) => (addr, Some(len)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| bad())?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let len = match len {
+            Some(len) => len.parse::<u8>().map_err(|_| bad())?,
+            None => max,
+        };
+        if len > max {
+            return Err(bad());
+        }
+        // host bits would never compare equal to a decoded prefix
+        let prefix = Prefix { addr, len: max }.supernet(len);
+        if prefix.addr != addr {
+            return Err(format!("Bad prefix '{}': host bits are set, did you mean {}?", s, prefix));
+        }
+        Ok(prefix)
+    }
+}
+
+pub fn parse_prefix(input: &[u8], afi: Afi) -> IResult<&[u8], Prefix> {
+    do_parse!(
+        input,
+        len: be_u8
+            >> octets: take!(u32::from(len).div_ceil(8))
+            >> prefix: expr_opt!(Prefix::from_octets(afi, octets, len))
+            >> (prefix)
+    )
+}
+
+// NLRI and withdrawn routes are a bare sequence of prefixes filling their
+// enclosing field.
+pub fn parse_nlri(mut input: &[u8], afi: Afi) -> IResult<&[u8], Vec<Prefix>> {
+    let mut prefixes = Vec::new();
+    while !input.is_empty() {
+        let (rest, prefix) = parse_prefix(input, afi)?;
+        prefixes.push(prefix);
+        input = rest;
+    }
+    Ok((input, prefixes))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MpReachNlri {
+    pub afi: u16,
+    pub safi: u8,
+    pub next_hop: IpAddr,
+    pub link_local_next_hop: Option<Ipv6Addr>,
+    pub nlri: Vec<Prefix>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MpUnreachNlri {
+    pub afi: u16,
+    pub safi: u8,
+    pub withdrawn_routes: Vec<Prefix>,
+}
+
+fn parse_next_hop(input: &[u8]) -> IResult<&[u8], (IpAddr, Option<Ipv6Addr>)> {
+    do_parse!(
+        input,
+        len: verify!(be_u8, |l: u8| l == 4 || l == 16 || l == 32)
+            >> next_hop: take!(len)
+            >> ({
+                let mut buf = [0u8; 16];
+                match len {
+                    4 => (IpAddr::V4(Ipv4Addr::new(next_hop[0], next_hop[1], next_hop[2], next_hop[3])), None),
+                    16 => {
+                        buf.copy_from_slice(next_hop);
+                        (IpAddr::V6(Ipv6Addr::from(buf)), None)
+                    }
+                    _ => {
+                        let mut link_local = [0u8; 16];
+                        buf.copy_from_slice(&next_hop[..16]);
+                        link_local.copy_from_slice(&next_hop[16..]);
+                        (IpAddr::V6(Ipv6Addr::from(buf)), Some(Ipv6Addr::from(link_local)))
                     }
-                    write!(
-                        fmt,
-                        "{:02x}{:02x}{:02x}{:02x}",
-                        buffer[i],
-                        buffer[i + 1],
-                        buffer[i + 2],
-                        buffer[i + 3]
-                    );
-                    j += 1;
-                    i += 4;
                 }
+            })
+    )
+}
+
+fn parse_mp_reach_nlri(input: &[u8]) -> IResult<&[u8], MpReachNlri> {
+    // TABLE_DUMP_V2 RIB entries carry an abbreviated MP_REACH_NLRI holding
+    // only the next hop (RFC 6396, section 4.3.4).
+    if !input.is_empty() && input[0] as usize + 1 == input.len() {
+        let (rest, (next_hop, link_local_next_hop)) = parse_next_hop(input)?;
+        let afi = match next_hop {
+            IpAddr::V4(_) => Afi::IPv4,
+            IpAddr::V6(_) => Afi::IPv6,
+        };
+        return Ok((
+            rest,
+            MpReachNlri {
+                afi: afi as u16,
+                safi: 1,
+                next_hop,
+                link_local_next_hop,
+                nlri: Vec::new(),
+            },
+        ));
+    }
+
+    do_parse!(
+        input,
+        afi: be_u16
+            >> safi: be_u8
+            >> next_hop: parse_next_hop
+            >> _reserved: be_u8
+            >> family: expr_opt!(Afi::from_u16(afi))
+            >> nlri: call!(parse_nlri, family)
+            >> (MpReachNlri {
+                afi,
+                safi,
+                next_hop: next_hop.0,
+                link_local_next_hop: next_hop.1,
+                nlri
+            })
+    )
+}
+
+fn parse_mp_unreach_nlri(input: &[u8]) -> IResult<&[u8], MpUnreachNlri> {
+    do_parse!(
+        input,
+        afi: be_u16
+            >> safi: be_u8
+            >> family: expr_opt!(Afi::from_u16(afi))
+            >> withdrawn_routes: call!(parse_nlri, family)
+            >> (MpUnreachNlri {
+                afi,
+                safi,
+                withdrawn_routes
+            })
+    )
+}
+
+fn parse_aggregator(input: &[u8]) -> IResult<&[u8], Aggregator> {
+    // 2-octet or 4-octet ASN; the attribute length tells which.
+    let asn_size = if input.len() == 8 { AsnSize::Four } else { AsnSize::Two };
+    do_parse!(
+        input,
+        asn: call!(parse_asn, asn_size)
+            >> address: be_u32
+            >> (Aggregator {
+                asn,
+                address: Ipv4Addr::from(address)
+            })
+    )
+}
+
+named!(parse_communities<Vec<Community> >,
+    many0!(complete!(map!(be_u32, Community)))
+);
+
+named!(parse_extended_communities<Vec<ExtendedCommunity> >,
+    many0!(complete!(map!(be_u64, ExtendedCommunity)))
+);
+
+named!(parse_cluster_list<Vec<Ipv4Addr> >,
+    many0!(complete!(map!(be_u32, Ipv4Addr::from)))
+);
+
+named!(parse_large_communities<Vec<LargeCommunity> >,
+    many0!(complete!(do_parse!(
+        global_admin: be_u32 >>
+        local_data_1: be_u32 >>
+        local_data_2: be_u32 >>
+        (LargeCommunity { global_admin, local_data_1, local_data_2 })
+    )))
+);
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PathAttributeValue {
+    Origin(Origin),
+    AsPath(AsPath),
+    NextHop(Ipv4Addr),
+    MultiExitDisc(u32),
+    LocalPref(u32),
+    AtomicAggregate,
+    Aggregator(Aggregator),
+    Communities(Vec<Community>),
+    OriginatorId(Ipv4Addr),
+    ClusterList(Vec<Ipv4Addr>),
+    MpReachNlri(MpReachNlri),
+    MpUnreachNlri(MpUnreachNlri),
+    ExtendedCommunities(Vec<ExtendedCommunity>),
+    As4Path(AsPath),
+    As4Aggregator(Aggregator),
+    LargeCommunities(Vec<LargeCommunity>),
+    BgpsecPath(BgpsecPath),
+    Unknown,
+}
+
+impl<'a> BGPPathAttribute<'a> {
+    pub fn value(&self, asn_size: AsnSize) -> Result<PathAttributeValue, String> {
+        let data = &self.data[..];
+        let result = match BGPPathAttrTypes::from_u8(self.code) {
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_ORIGIN) => {
+                map!(data, map_opt!(be_u8, Origin::from_u8), PathAttributeValue::Origin)
+            }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_ASPATH) => {
+                map!(data, call!(parse_as_path, asn_size), PathAttributeValue::AsPath)
+            }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_NEXTHOP) => map!(data, be_u32, |a| {
+                PathAttributeValue::NextHop(Ipv4Addr::from(a))
+            }),
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_EXITDISC) => {
+                map!(data, be_u32, PathAttributeValue::MultiExitDisc)
+            }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_LOCALPREF) => {
+                map!(data, be_u32, PathAttributeValue::LocalPref)
+            }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_ATOM_AGG) => {
+                Ok((data, PathAttributeValue::AtomicAggregate))
+            }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_AGGREGATOR) => {
+                map!(data, parse_aggregator, PathAttributeValue::Aggregator)
+            }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_COMMUNITY) => {
+                map!(data, parse_communities, PathAttributeValue::Communities)
+            }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_ORIGINATOR_ID) => map!(data, be_u32, |a| {
+                PathAttributeValue::OriginatorId(Ipv4Addr::from(a))
+            }),
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_CLUSTER_LIST) => {
+                map!(data, parse_cluster_list, PathAttributeValue::ClusterList)
+            }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_MP_REACH_NLRI) => {
+                map!(data, parse_mp_reach_nlri, PathAttributeValue::MpReachNlri)
+            }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_MP_UNREACH_NLRI) => {
+                map!(data, parse_mp_unreach_nlri, PathAttributeValue::MpUnreachNlri)
+            }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_EXT_COMMUNITY) => {
+                map!(data, parse_extended_communities, PathAttributeValue::ExtendedCommunities)
             }
-            _ => {
-                write!(fmt, "AS_UNKNOWN");
-                i += 1;
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_AS4_PATH) => {
+                map!(data, call!(parse_as_path, AsnSize::Four), PathAttributeValue::As4Path)
             }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_AS4_AGGREGATOR) => {
+                map!(data, parse_aggregator, PathAttributeValue::As4Aggregator)
+            }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_LARGE_COMMUNITY) => {
+                map!(data, parse_large_communities, PathAttributeValue::LargeCommunities)
+            }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_BGPSEC_PATH) => {
+                map!(data, parse_bgpsec_path, PathAttributeValue::BgpsecPath)
+            }
+            None => Ok((data, PathAttributeValue::Unknown)),
+        };
+        match result {
+            Ok((_, value)) => Ok(value),
+            Err(e) => Err(format!("Bad attribute {} ({} bytes): {}", self.code, self.len, e)),
+        }
+    }
+
+    fn as4_path(&self) -> Option<AsPath> {
+        match self.value(AsnSize::Four) {
+            Ok(PathAttributeValue::As4Path(path)) => Some(path),
+            _ => None,
         }
     }
 }
+
+// The decoded attributes of a single route, as needed by the output formats.
+// Attributes that fail to decode are left out.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RouteAttributes {
+    pub origin: Option<Origin>,
+    pub as_path: Option<AsPath>,
+    pub next_hop: Option<Ipv4Addr>,
+    pub med: Option<u32>,
+    pub local_pref: Option<u32>,
+    pub atomic_aggregate: bool,
+    pub aggregator: Option<Aggregator>,
+    pub communities: Vec<Community>,
+    pub extended_communities: Vec<ExtendedCommunity>,
+    pub large_communities: Vec<LargeCommunity>,
+    pub originator_id: Option<Ipv4Addr>,
+    pub cluster_list: Vec<Ipv4Addr>,
+    pub mp_reach_nlri: Option<MpReachNlri>,
+    pub mp_unreach_nlri: Option<MpUnreachNlri>,
+}
+
+impl RouteAttributes {
+    pub fn new(attrs: &[BGPPathAttribute], asn_size: AsnSize) -> RouteAttributes {
+        let mut route = RouteAttributes {
+            as_path: find_as_path(attrs, asn_size),
+            ..Default::default()
+        };
+        let mut as4_aggregator = None;
+        for attr in attrs {
+            match attr.value(asn_size) {
+                Ok(PathAttributeValue::Origin(origin)) => route.origin = Some(origin),
+                Ok(PathAttributeValue::NextHop(next_hop)) => route.next_hop = Some(next_hop),
+                Ok(PathAttributeValue::MultiExitDisc(med)) => route.med = Some(med),
+                Ok(PathAttributeValue::LocalPref(local_pref)) => {
+                    route.local_pref = Some(local_pref)
+                }
+                Ok(PathAttributeValue::AtomicAggregate) => route.atomic_aggregate = true,
+                Ok(PathAttributeValue::Aggregator(aggregator)) => {
+                    route.aggregator = Some(aggregator)
+                }
+                Ok(PathAttributeValue::As4Aggregator(aggregator)) => {
+                    as4_aggregator = Some(aggregator)
+                }
+                Ok(PathAttributeValue::Communities(communities)) => {
+                    route.communities = communities
+                }
+                Ok(PathAttributeValue::ExtendedCommunities(communities)) => {
+                    route.extended_communities = communities
+                }
+                Ok(PathAttributeValue::LargeCommunities(communities)) => {
+                    route.large_communities = communities
+                }
+                Ok(PathAttributeValue::OriginatorId(id)) => route.originator_id = Some(id),
+                Ok(PathAttributeValue::ClusterList(list)) => route.cluster_list = list,
+                Ok(PathAttributeValue::MpReachNlri(mp)) => route.mp_reach_nlri = Some(mp),
+                Ok(PathAttributeValue::MpUnreachNlri(mp)) => route.mp_unreach_nlri = Some(mp),
+                _ => {}
+            }
+        }
+        // RFC 6793: AS4_AGGREGATOR replaces an AGGREGATOR naming AS_TRANS.
+        if let (Some(aggregator), Some(as4_aggregator)) = (route.aggregator, as4_aggregator) {
+            if aggregator.asn == AS_TRANS {
+                route.aggregator = Some(as4_aggregator);
+            }
+        }
+        route
+    }
+}
+
+pub const AS_TRANS: u32 = 23456;
+
 //
 //TABLE_DUMP2|1278892800|B|
 //
@@ -135,50 +890,61 @@ fn parse_as_path(fmt: &mut fmt::Formatter, buffer: &Vec<u8>) {
 //NAG|
 //|
 
-impl fmt::Display for BGPPathAttribute {
+// A path attribute printed with its AS_PATH decoded for the ASN width of
+// the record it came from, see BGPPathAttribute::display.
+pub struct PathAttributeDisplay<'r, 'a: 'r> {
+    attr: &'r BGPPathAttribute<'a>,
+    asn_size: Option<AsnSize>,
+}
+
+impl<'a> BGPPathAttribute<'a> {
+    // For printing with the AS_PATH decoded; the width comes from the
+    // record (BGP4MPMessage::asn_size, AsnSize::Four for TABLE_DUMP_V2).
+    pub fn display(&self, asn_size: AsnSize) -> PathAttributeDisplay<'_, 'a> {
+        PathAttributeDisplay { attr: self, asn_size: Some(asn_size) }
+    }
+}
+
+impl<'r, 'a> fmt::Display for PathAttributeDisplay<'r, 'a> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        //let flags = format!("{:02x}", self.flags);
-        match BGPPathAttrTypes::from_u8(self.code) {
-            Some(BGPPathAttrTypes::BGP_PATH_ATTR_ORIGIN) => match self.data[0] {
-                0 => {
-                    write!(fmt, "IGP");
-                }
-                1 => {
-                    write!(fmt, "EGP");
-                }
-                2 => {
-                    write!(fmt, "INCOMPLETE");
-                }
-                _ => {
-                    write!(fmt, "UNKNOWN_ORIGIN");
-                }
+        let attr = self.attr;
+        match BGPPathAttrTypes::from_u8(attr.code) {
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_ORIGIN) => match attr.data.first() {
+                Some(0) => write!(fmt, "IGP"),
+                Some(1) => write!(fmt, "EGP"),
+                Some(2) => write!(fmt, "INCOMPLETE"),
+                _ => write!(fmt, "UNKNOWN_ORIGIN"),
             },
-            Some(BGPPathAttrTypes::BGP_PATH_ATTR_ASPATH) => {
-                write!(fmt, "BGP_PATH_ATTR_ASPATH");
-                parse_as_path(fmt, &self.data);
-                //        let data  = format!("{:?}", self.data);
-            }
-            Some(BGPPathAttrTypes::BGP_PATH_ATTR_NEXTHOP) => {
-                write!(fmt, "BGP_PATH_ATTR_NEXTHOP");
-            }
-            Some(BGPPathAttrTypes::BGP_PATH_ATTR_EXITDISC) => {
-                write!(fmt, "BGP_PATH_ATTR_EXITDISC");
-            }
-            Some(BGPPathAttrTypes::BGP_PATH_ATTR_ATOM_AGG) => {
-                write!(fmt, "BGP_PATH_ATTR_ATOM_AGG");
-            }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_ASPATH) => match self.asn_size {
+                Some(asn_size) => match attr.as_path(asn_size) {
+                    Some(path) => write!(fmt, "BGP_PATH_ATTR_ASPATH {}", path),
+                    None => write!(fmt, "BGP_PATH_ATTR_ASPATH AS_UNKNOWN"),
+                },
+                None => write!(fmt, "BGP_PATH_ATTR_ASPATH 0x{}", hex::encode(&attr.data)),
+            },
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_NEXTHOP) => write!(fmt, "BGP_PATH_ATTR_NEXTHOP"),
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_EXITDISC) => write!(fmt, "BGP_PATH_ATTR_EXITDISC"),
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_ATOM_AGG) => write!(fmt, "BGP_PATH_ATTR_ATOM_AGG"),
             Some(BGPPathAttrTypes::BGP_PATH_ATTR_AGGREGATOR) => {
-                write!(fmt, "BGP_PATH_ATTR_AGGREGATOR");
+                write!(fmt, "BGP_PATH_ATTR_AGGREGATOR")
             }
             Some(BGPPathAttrTypes::BGP_PATH_ATTR_COMMUNITY) => {
-                write!(fmt, "BGP_PATH_ATTR_COMMUNITY");
-            }
-            _ => {
-                write!(fmt, "Unhandled attr type: {}", self.code);
+                write!(fmt, "BGP_PATH_ATTR_COMMUNITY")
             }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_BGPSEC_PATH) => match attr.bgpsec_path() {
+                Some(path) => write!(fmt, "BGP_PATH_ATTR_BGPSEC_PATH {}", path.as_path()),
+                None => write!(fmt, "BGP_PATH_ATTR_BGPSEC_PATH AS_UNKNOWN"),
+            },
+            _ => write!(fmt, "Unhandled attr type: {}", attr.code),
         }
+    }
+}
 
-        Ok(())
+// Without the record the ASN width is unknown, so an AS_PATH is printed as
+// its raw bytes; use display() to have it decoded.
+impl<'a> fmt::Display for BGPPathAttribute<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        PathAttributeDisplay { attr: self, asn_size: None }.fmt(fmt)
     }
 }
 
@@ -186,9 +952,7 @@ pub fn read_path_attr_length(input: &[u8], flags: u8) -> IResult<&[u8], u16> {
     if flags & 0x10 == 0x10 {
         be_u16(input)
     } else {
-        let tmp = be_u8(input);
-        let tmp2 = tmp.unwrap();
-        Ok((tmp2.0, tmp2.1 as u16))
+        map!(input, be_u8, u16::from)
     }
 }
 
@@ -196,30 +960,17 @@ pub fn read_path_attr_length(input: &[u8], flags: u8) -> IResult<&[u8], u16> {
 // I have a length to read.
 // that byte range may have multiple attributes, each to be parsed
 
-fn parse_bgp_attr_payload(input: &[u8], code: u8, len: u16) -> IResult<&[u8], Vec<u8>> {
-    let len = len as usize;
-    Ok((&input[len..], input[0..len].to_vec()))
-    //    match BGPPathAttrTypes::from_u8(code) {
-    //        Some(BGPPathAttrTypes::BGP_PATH_ATTR_ORIGIN) => {
-    //            Ok( (&input[len..], vec![&input[0..len]]) );
-    //        }
-    //        Some(BGPPathAttrTypes::BGP_PATH_ATTR_ASPATH) => {
-    //            Ok( (&input[len..], vec![&input[0..len]]) );
-    //        }
-    //        Some(BGPPathAttrTypes::BGP_PATH_ATTR_NEXTHOP) => {
-    //            Ok( (&input[len..], vec![&input[0..len]]) );
-    //        }
-    //    }
-    //    //Err("No matching attr code".to_string());
-}
-
-fn parse_bgp_path_attr(input: &[u8], length: usize) -> IResult<&[u8], BGPPathAttribute> {
+fn parse_bgp_attr_payload(input: &[u8], len: u16) -> IResult<&[u8], Cow<'_, [u8]>> {
+    map!(input, take!(len), Cow::Borrowed)
+}
+
+fn parse_bgp_path_attr(input: &[u8]) -> IResult<&[u8], BGPPathAttribute<'_>> {
     do_parse!(
         input,
         flags: be_u8
             >> code: be_u8
             >> len: call!(read_path_attr_length, flags)
-            >> data: call!(parse_bgp_attr_payload, code, len)
+            >> data: call!(parse_bgp_attr_payload, len)
             >> (BGPPathAttribute {
                 flags,
                 code,
@@ -232,13 +983,12 @@ fn parse_bgp_path_attr(input: &[u8], length: usize) -> IResult<&[u8], BGPPathAtt
 pub fn parse_bgp_path_attrs(
     mut input: &[u8],
     length: u16,
-) -> IResult<&[u8], Vec<BGPPathAttribute>> {
+) -> IResult<&[u8], Vec<BGPPathAttribute<'_>>> {
     // pull precisely 'length' bytes out of 'input'
     let length = length as usize;
 
     let mut total_length = 0;
 
-    let mut res;
     let mut results: Vec<BGPPathAttribute> = Vec::with_capacity(16);
 
     // this is a bit of a pain, but the protocol doesn't define how many attrs
@@ -246,25 +996,107 @@ pub fn parse_bgp_path_attrs(
     // the number of octets that will be consumed by M attrs. So, loop until
     // that many bytes are consumed.
     while total_length < length {
-        res = parse_bgp_path_attr(input, length);
-        let tmp = res;
-        match tmp {
-            Ok(v) => {
-                let bytes_read = input.len() - v.0.len();
-                total_length += bytes_read;
+        let (rest, attr) = parse_bgp_path_attr(input)?;
+        total_length += input.len() - rest.len();
+        results.push(attr);
+        input = rest;
+    }
+
+    if total_length != length {
+        return Err(nom::Err::Error(error_position!(input, ErrorKind::LengthValue)));
+    }
+
+    Ok((input, results))
+}
+
+// A borrowed view of a run of path attributes, e.g. those of a RIB entry,
+// that parses them one at a time and only as far as asked: finding the
+// AS_PATH for a route's origin stops there and decodes nothing else, and
+// walking it allocates nothing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathAttributes<'a> {
+    data: &'a [u8],
+}
 
-                results.push(v.1);
-                input = v.0;
+pub struct PathAttributeIter<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for PathAttributeIter<'a> {
+    type Item = Result<BGPPathAttribute<'a>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        match parse_bgp_path_attr(self.data) {
+            Ok((rest, attr)) => {
+                self.data = rest;
+                Some(Ok(attr))
+            }
+            Err(e) => {
+                // the attributes after a bad one cannot be found
+                self.data = &[];
+                Some(Err(format!("Bad path attribute: {}", e)))
             }
-            Err(e) => panic!("Bad parse on BGP data; {}", e),
         }
     }
+}
 
-    Ok((input, results))
+impl<'a> PathAttributes<'a> {
+    pub fn new(data: &'a [u8]) -> PathAttributes<'a> {
+        PathAttributes { data }
+    }
+
+    pub fn iter(&self) -> PathAttributeIter<'a> {
+        PathAttributeIter { data: self.data }
+    }
+
+    // The first attribute of the given type, if any comes before one that
+    // fails to parse.
+    pub fn get(&self, code: BGPPathAttrTypes) -> Option<BGPPathAttribute<'a>> {
+        let code = code as u8;
+        self.iter().map_while(Result::ok).find(|attr| attr.code == code)
+    }
+
+    // As find_as_path, decoding only the path attributes.
+    pub fn as_path(&self, asn_size: AsnSize) -> Option<AsPath> {
+        let as_path = self.get(BGPPathAttrTypes::BGP_PATH_ATTR_ASPATH);
+        match as_path.and_then(|attr| attr.as_path(asn_size)) {
+            Some(path) => {
+                if asn_size == AsnSize::Two {
+                    let as4_path = self.get(BGPPathAttrTypes::BGP_PATH_ATTR_AS4_PATH);
+                    if let Some(as4_path) = as4_path.and_then(|attr| attr.as4_path()) {
+                        return Some(path.merge_as4_path(&as4_path));
+                    }
+                }
+                Some(path)
+            }
+            None => self
+                .get(BGPPathAttrTypes::BGP_PATH_ATTR_BGPSEC_PATH)
+                .and_then(|attr| attr.bgpsec_path())
+                .map(|path| path.as_path()),
+        }
+    }
+
+    // Every attribute, as parse_bgp_path_attrs gives them.
+    pub fn to_vec(&self) -> Result<Vec<BGPPathAttribute<'a>>, String> {
+        self.iter().collect()
+    }
+}
+
+impl<'a> IntoIterator for PathAttributes<'a> {
+    type Item = Result<BGPPathAttribute<'a>, String>;
+    type IntoIter = PathAttributeIter<'a>;
+
+    fn into_iter(self) -> PathAttributeIter<'a> {
+        self.iter()
+    }
 }
 
 
 #[test]
+#[allow(clippy::vec_init_then_push)]
 fn parse_good_test() {
     
 //00 01        <-- peer index
@@ -284,15 +1116,16 @@ fn parse_good_test() {
     
     let mut res = Vec::new();
     res.push(
-        BGPPathAttribute{ flags: 0x40, code: 0x01, len: 0x01, data: vec![0x00] } );
-    res.push( BGPPathAttribute{ flags: 0x50, code: 0x02, len: 0x16, data: vec![0x02, 0x05, 0x00, 0x00, 0xa4, 0x7d, 0x00, 0x00, 0xa3, 0xed, 0x00, 0x00, 0xa3, 0x95, 0x00, 0x00, 0x51, 0x23, 0x00, 0x00, 0x0d, 0x1c] } );
-    res.push( BGPPathAttribute{ flags: 0x40, code: 0x03, len: 0x04, data: vec![0x5b, 0x67, 0x18, 0x02] } );
+        BGPPathAttribute{ flags: 0x40, code: 0x01, len: 0x01, data: Cow::Owned(vec![0x00]) } );
+    res.push( BGPPathAttribute{ flags: 0x50, code: 0x02, len: 0x16, data: Cow::Owned(vec![0x02, 0x05, 0x00, 0x00, 0xa4, 0x7d, 0x00, 0x00, 0xa3, 0xed, 0x00, 0x00, 0xa3, 0x95, 0x00, 0x00, 0x51, 0x23, 0x00, 0x00, 0x0d, 0x1c]) } );
+    res.push( BGPPathAttribute{ flags: 0x40, code: 0x03, len: 0x04, data: Cow::Owned(vec![0x5b, 0x67, 0x18, 0x02]) } );
 
     //assert_eq!( result, (CompleteByteSlice(b""), res) );
     assert_eq!( result, res );
 }
 
 #[test]
+#[allow(clippy::vec_init_then_push)]
 fn parse_good_test_long_buffer() {
 
 //00 01        <-- peer index
@@ -314,13 +1147,81 @@ fn parse_good_test_long_buffer() {
 
     let mut res = Vec::new();
     res.push(
-        BGPPathAttribute{ flags: 0x40, code: 0x01, len: 0x01, data: vec![0x00] } );
-    res.push( BGPPathAttribute{ flags: 0x50, code: 0x02, len: 0x16, data: vec![0x02, 0x05, 0x00, 0x00, 0xa4, 0x7d, 0x00, 0x00, 0xa3, 0xed, 0x00, 0x00, 0xa3, 0x95, 0x00, 0x00, 0x51, 0x23, 0x00, 0x00, 0x0d, 0x1c] } );
-    res.push( BGPPathAttribute{ flags: 0x40, code: 0x03, len: 0x04, data: vec![0x5b, 0x67, 0x18, 0x02] } );
+        BGPPathAttribute{ flags: 0x40, code: 0x01, len: 0x01, data: Cow::Owned(vec![0x00]) } );
+    res.push( BGPPathAttribute{ flags: 0x50, code: 0x02, len: 0x16, data: Cow::Owned(vec![0x02, 0x05, 0x00, 0x00, 0xa4, 0x7d, 0x00, 0x00, 0xa3, 0xed, 0x00, 0x00, 0xa3, 0x95, 0x00, 0x00, 0x51, 0x23, 0x00, 0x00, 0x0d, 0x1c]) } );
+    res.push( BGPPathAttribute{ flags: 0x40, code: 0x03, len: 0x04, data: Cow::Owned(vec![0x5b, 0x67, 0x18, 0x02]) } );
 
 
     //assert_eq!( result, (CompleteByteSlice(b""), res) );
     assert_eq!( tmp.1, res );
 }
 
+#[test]
+fn find_as_path_bgpsec_test() {
+    // No AS_PATH, only a BGPsec_PATH with a single AS 65000 (pCount 2) and an
+    // empty signature block.
+    let data = hex::decode("000802000000fde8000301").unwrap();
+    let attrs = vec![
+        BGPPathAttribute{ flags: 0x40, code: 0x01, len: 0x01, data: Cow::Owned(vec![0x00]) },
+        BGPPathAttribute{ flags: 0x90, code: 0x21, len: data.len() as u16, data: Cow::Owned(data) },
+    ];
+
+    let path = find_as_path(&attrs, AsnSize::Two).unwrap();
+    assert_eq!(format!("{}", path), "65000 65000");
 
+    // 2-octet AS_PATH: AS_SEQUENCE 3257 701, AS_SET {80, 81}
+    let attrs = vec![
+        BGPPathAttribute{ flags: 0x40, code: 0x02, len: 0x0c, data: Cow::Owned(hex::decode("02020cb902bd010200500051").unwrap()) },
+    ];
+    let path = find_as_path(&attrs, AsnSize::Two).unwrap();
+    assert_eq!(format!("{}", path), "3257 701 {80,81}");
+    assert_eq!(attrs[0].display(AsnSize::Two).to_string(), "BGP_PATH_ATTR_ASPATH 3257 701 {80,81}");
+    assert_eq!(attrs[0].display(AsnSize::Four).to_string(), "BGP_PATH_ATTR_ASPATH AS_UNKNOWN");
+    assert_eq!(attrs[0].to_string(), "BGP_PATH_ATTR_ASPATH 0x02020cb902bd010200500051");
+}
+
+#[test]
+fn prefix_contains_test() {
+    let covering: Prefix = "192.0.2.0/23".parse().unwrap();
+    assert!(covering.contains(&"192.0.3.128/25".parse().unwrap()));
+    assert!(covering.contains(&covering));
+    assert!(!covering.contains(&"192.0.4.0/24".parse().unwrap()));
+    assert!(!covering.contains(&"192.0.0.0/16".parse().unwrap()));
+    assert!(!covering.contains(&"2001:db8::/32".parse().unwrap()));
+    assert!("0.0.0.0/0".parse::<Prefix>().unwrap().contains(&"10.0.0.1".parse().unwrap()));
+    assert!("::/0".parse::<Prefix>().unwrap().contains(&"2001:db8::/32".parse().unwrap()));
+    assert!("192.0.2.0/33".parse::<Prefix>().is_err());
+    assert!("192.0.2.7/24".parse::<Prefix>().is_err());
+    assert!("2001:db8::1/32".parse::<Prefix>().is_err());
+
+    let host: Prefix = "192.0.3.129".parse().unwrap();
+    assert_eq!(host.supernet(23), covering);
+    assert!(host.supernet(0).contains(&host));
+    assert_eq!(covering.supernet(24), covering);
+}
+
+#[test]
+fn path_attributes_test() {
+    // ORIGIN, AS_PATH (extended length, 4-octet ASNs), NEXT_HOP
+    let buffer = hex::decode("400101005002001602050000a47d0000a3ed0000a3950000512300000d1c4003045b671802").unwrap();
+    let attrs = PathAttributes::new(&buffer);
+
+    assert_eq!(attrs.as_path(AsnSize::Four).unwrap().origin_asn(), Some(3356));
+    let next_hop = attrs.get(BGPPathAttrTypes::BGP_PATH_ATTR_NEXTHOP).unwrap();
+    assert_eq!(&next_hop.data[..], &[0x5b, 0x67, 0x18, 0x02]);
+    assert!(attrs.get(BGPPathAttrTypes::BGP_PATH_ATTR_LOCALPREF).is_none());
+    assert_eq!(attrs.to_vec().unwrap(), parse_bgp_path_attrs(&buffer, buffer.len() as u16).unwrap().1);
+
+    // cut short in the NEXT_HOP: the attributes before it are still there
+    let attrs = PathAttributes::new(&buffer[..buffer.len() - 2]);
+    assert!(attrs.as_path(AsnSize::Four).is_some());
+    assert_eq!(attrs.iter().filter(|a| a.is_err()).count(), 1);
+    assert!(attrs.to_vec().is_err());
+
+    // an AS_PATH that does not decode gives way to the BGPsec_PATH, as in
+    // find_as_path
+    let buffer = hex::decode(concat!("40020302010c", "9021000b", "000802000000fde8000301")).unwrap();
+    let attrs = PathAttributes::new(&buffer);
+    assert_eq!(attrs.as_path(AsnSize::Four).unwrap().to_string(), "65000 65000");
+    assert_eq!(attrs.as_path(AsnSize::Four), find_as_path(&attrs.to_vec().unwrap(), AsnSize::Four));
+}
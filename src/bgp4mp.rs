@@ -0,0 +1,344 @@
+use bgp::{parse_asn, parse_bgp_path_attrs, parse_nlri, Afi, AsnSize, BGPPathAttribute, Prefix};
+use mrt::MRTRecord;
+use nom::{be_u128, be_u16, be_u32, be_u8, IResult};
+use num_traits::cast::FromPrimitive;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BGP4MPSubtypes {
+    BGP4MP_STATE_CHANGE = 0,
+    BGP4MP_MESSAGE = 1,
+    BGP4MP_MESSAGE_AS4 = 4,
+    BGP4MP_STATE_CHANGE_AS4 = 5,
+    BGP4MP_MESSAGE_LOCAL = 6,
+    BGP4MP_MESSAGE_AS4_LOCAL = 7,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BGPState {
+    Idle = 1,
+    Connect = 2,
+    Active = 3,
+    OpenSent = 4,
+    OpenConfirm = 5,
+    Established = 6,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BGPMessageTypes {
+    OPEN = 1,
+    UPDATE = 2,
+    NOTIFICATION = 3,
+    KEEPALIVE = 4,
+    // RFC 2918
+    ROUTE_REFRESH = 5,
+}
+
+//        0                   1                   2                   3
+//        0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |         Peer AS Number        |        Local AS Number        |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |        Interface Index        |        Address Family         |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |                      Peer IP Address (variable)               |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |                      Local IP Address (variable)              |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |            Old State          |          New State            |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+//                   Figure 8: BGP4MP_STATE_CHANGE Subtype
+//
+// BGP4MP_MESSAGE is the same up to the addresses, followed by the complete
+// BGP message. The _AS4 subtypes widen both AS numbers to 4 octets.
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BGP4MPPeer {
+    pub peer_asn: u32,
+    pub local_asn: u32,
+    pub interface_index: u16,
+    pub afi: u16,
+    pub peer_address: IpAddr,
+    pub local_address: IpAddr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BGP4MPStateChange {
+    pub peer: BGP4MPPeer,
+    pub old_state: u16,
+    pub new_state: u16,
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BGP4MPMessage<'a> {
+    pub peer: BGP4MPPeer,
+    // width of the ASNs inside the BGP message's AS_PATH
+    pub asn_size: AsnSize,
+    pub message: BGPMessage<'a>,
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BGPOpen<'a> {
+    pub version: u8,
+    pub my_asn: u16,
+    pub hold_time: u16,
+    pub bgp_id: Ipv4Addr,
+    pub opt_params: Cow<'a, [u8]>,
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BGPUpdate<'a> {
+    pub withdrawn_routes: Vec<Prefix>,
+    pub path_attributes: Vec<BGPPathAttribute<'a>>,
+    pub nlri: Vec<Prefix>,
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BGPNotification<'a> {
+    pub error_code: u8,
+    pub error_subcode: u8,
+    pub data: Cow<'a, [u8]>,
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BGPMessage<'a> {
+    Open(BGPOpen<'a>),
+    Update(BGPUpdate<'a>),
+    Notification(BGPNotification<'a>),
+    Keepalive,
+    RouteRefresh,
+    Unknown(u8),
+}
+
+impl BGP4MPPeer {
+    // The address family of the session's addresses, None for an AFI this
+    // does not know.
+    pub fn address_family(&self) -> Option<Afi> {
+        Afi::from_u16(self.afi)
+    }
+}
+
+impl<'a> BGP4MPMessage<'a> {
+    // A copy of the message that no longer borrows the input buffer.
+    pub fn into_owned(self) -> BGP4MPMessage<'static> {
+        let message = match self.message {
+            BGPMessage::Open(open) => BGPMessage::Open(BGPOpen {
+                opt_params: Cow::Owned(open.opt_params.into_owned()),
+                ..open
+            }),
+            BGPMessage::Update(update) => BGPMessage::Update(BGPUpdate {
+                path_attributes: update.path_attributes.into_iter().map(|a| a.into_owned()).collect(),
+                ..update
+            }),
+            BGPMessage::Notification(notification) => BGPMessage::Notification(BGPNotification {
+                data: Cow::Owned(notification.data.into_owned()),
+                ..notification
+            }),
+            BGPMessage::Keepalive => BGPMessage::Keepalive,
+            BGPMessage::RouteRefresh => BGPMessage::RouteRefresh,
+            BGPMessage::Unknown(code) => BGPMessage::Unknown(code),
+        };
+        BGP4MPMessage { peer: self.peer, asn_size: self.asn_size, message }
+    }
+}
+
+fn parse_address(input: &[u8], afi: u16) -> IResult<&[u8], IpAddr> {
+    match Afi::from_u16(afi) {
+        Some(Afi::IPv6) => map!(input, be_u128, |a| IpAddr::V6(Ipv6Addr::from(a))),
+        _ => map!(input, be_u32, |a| IpAddr::V4(Ipv4Addr::from(a))),
+    }
+}
+
+fn parse_bgp4mp_peer(input: &[u8], asn_size: AsnSize) -> IResult<&[u8], BGP4MPPeer> {
+    do_parse!(
+        input,
+        peer_asn: call!(parse_asn, asn_size)
+            >> local_asn: call!(parse_asn, asn_size)
+            >> interface_index: be_u16
+            >> afi: be_u16
+            >> peer_address: call!(parse_address, afi)
+            >> local_address: call!(parse_address, afi)
+            >> (BGP4MPPeer {
+                peer_asn,
+                local_asn,
+                interface_index,
+                afi,
+                peer_address,
+                local_address
+            })
+    )
+}
+
+fn parse_bgp4mp_state_change(input: &[u8], asn_size: AsnSize) -> IResult<&[u8], BGP4MPStateChange> {
+    do_parse!(
+        input,
+        peer: call!(parse_bgp4mp_peer, asn_size)
+            >> old_state: be_u16
+            >> new_state: be_u16
+            >> (BGP4MPStateChange {
+                peer,
+                old_state,
+                new_state
+            })
+    )
+}
+
+named!(parse_bgp_open<BGPOpen>,
+    do_parse!(
+        version:        be_u8  >>
+        my_asn:         be_u16 >>
+        hold_time:      be_u16 >>
+        bgp_id:         be_u32 >>
+        opt_params_len: be_u8  >>
+        opt_params:     take!(opt_params_len) >>
+    (BGPOpen {
+        version,
+        my_asn,
+        hold_time,
+        bgp_id: Ipv4Addr::from(bgp_id),
+        opt_params: Cow::Borrowed(opt_params)
+    })
+    )
+);
+
+// UPDATE withdrawn routes and NLRI are always IPv4 (RFC 4271); other
+// families travel in MP_REACH_NLRI/MP_UNREACH_NLRI.
+named!(parse_bgp_update<BGPUpdate>,
+    do_parse!(
+        withdrawn_len:    be_u16 >>
+        withdrawn_routes: flat_map!(take!(withdrawn_len), call!(parse_nlri, Afi::IPv4)) >>
+        attr_len:         be_u16 >>
+        path_attributes:  flat_map!(take!(attr_len), call!(parse_bgp_path_attrs, attr_len)) >>
+        nlri:             call!(parse_nlri, Afi::IPv4) >>
+    (BGPUpdate { withdrawn_routes, path_attributes, nlri })
+    )
+);
+
+named!(parse_bgp_notification<BGPNotification>,
+    do_parse!(
+        error_code:    be_u8 >>
+        error_subcode: be_u8 >>
+        data:          call!(nom::rest) >>
+    (BGPNotification { error_code, error_subcode, data: Cow::Borrowed(data) })
+    )
+);
+
+fn parse_bgp_message_body(input: &[u8], message_type: u8) -> IResult<&[u8], BGPMessage<'_>> {
+    match BGPMessageTypes::from_u8(message_type) {
+        Some(BGPMessageTypes::OPEN) => map!(input, parse_bgp_open, BGPMessage::Open),
+        Some(BGPMessageTypes::UPDATE) => map!(input, parse_bgp_update, BGPMessage::Update),
+        Some(BGPMessageTypes::NOTIFICATION) => {
+            map!(input, parse_bgp_notification, BGPMessage::Notification)
+        }
+        Some(BGPMessageTypes::KEEPALIVE) => Ok((input, BGPMessage::Keepalive)),
+        Some(BGPMessageTypes::ROUTE_REFRESH) => Ok((&input[input.len()..], BGPMessage::RouteRefresh)),
+        None => Ok((&input[input.len()..], BGPMessage::Unknown(message_type))),
+    }
+}
+
+//        0                   1                   2                   3
+//        0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |                                                               |
+//       +                                                               +
+//       |                                                               |
+//       +                                                               +
+//       |                           Marker                              |
+//       +                                                               +
+//       |                                                               |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |          Length               |      Type     |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// RFC 4271, section 4.1; the length includes this 19 octet header.
+
+pub const BGP_HEADER_LENGTH: u16 = 19;
+
+named!(pub parse_bgp_message<BGPMessage>,
+    do_parse!(
+        _marker:      take!(16) >>
+        length:       verify!(be_u16, |l: u16| l >= BGP_HEADER_LENGTH) >>
+        message_type: be_u8 >>
+        message:      flat_map!(take!(length - BGP_HEADER_LENGTH), call!(parse_bgp_message_body, message_type)) >>
+        (message)
+    )
+);
+
+fn parse_bgp4mp_message(input: &[u8], asn_size: AsnSize) -> IResult<&[u8], BGP4MPMessage<'_>> {
+    do_parse!(
+        input,
+        peer: call!(parse_bgp4mp_peer, asn_size)
+            >> message: parse_bgp_message
+            >> (BGP4MPMessage {
+                peer,
+                asn_size,
+                message
+            })
+    )
+}
+
+pub fn parse_bgp4mp(subtype: u16, body: &[u8]) -> Result<MRTRecord<'_>, String> {
+    let result = match BGP4MPSubtypes::from_u16(subtype) {
+        Some(BGP4MPSubtypes::BGP4MP_STATE_CHANGE) => {
+            parse_bgp4mp_state_change(body, AsnSize::Two).map(|r| MRTRecord::BGP4MPStateChange(r.1))
+        }
+        Some(BGP4MPSubtypes::BGP4MP_STATE_CHANGE_AS4) => parse_bgp4mp_state_change(body, AsnSize::Four)
+            .map(|r| MRTRecord::BGP4MPStateChange(r.1)),
+        Some(BGP4MPSubtypes::BGP4MP_MESSAGE) | Some(BGP4MPSubtypes::BGP4MP_MESSAGE_LOCAL) => {
+            parse_bgp4mp_message(body, AsnSize::Two).map(|r| MRTRecord::BGP4MPMessage(r.1))
+        }
+        Some(BGP4MPSubtypes::BGP4MP_MESSAGE_AS4) | Some(BGP4MPSubtypes::BGP4MP_MESSAGE_AS4_LOCAL) => {
+            parse_bgp4mp_message(body, AsnSize::Four).map(|r| MRTRecord::BGP4MPMessage(r.1))
+        }
+        None => return Ok(MRTRecord::Unsupported),
+    };
+    result.map_err(|e| e.to_string())
+}
+
+#[test]
+fn parse_bgp4mp_update_test() {
+    // BGP4MP_MESSAGE_AS4, peer AS 65000, local AS 65001, IPv4 peer 192.0.2.1
+    let buffer = hex::decode(concat!(
+        "0000fde8", "0000fde9", "0000", "0001", "c0000201", "c0000202",
+        "ffffffffffffffffffffffffffffffff", "0033", "02",
+        "0004", "18c63364",                       // withdrawn 198.51.100.0/24
+        "0014",
+        "40010100",                               // ORIGIN IGP
+        "4002060201" , "0000fde8",                // AS_PATH 65000
+        "400304c0000201",                         // NEXT_HOP 192.0.2.1
+        "18cb0071"                                // NLRI 203.0.113.0/24
+    ))
+    .unwrap();
+
+    let record = parse_bgp4mp(4, &buffer).unwrap();
+    let msg = match record {
+        MRTRecord::BGP4MPMessage(msg) => msg,
+        _ => panic!("expected BGP4MP message"),
+    };
+    assert_eq!(msg.peer.peer_asn, 65000);
+    assert_eq!(msg.peer.peer_address, IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)));
+    let update = match msg.message {
+        BGPMessage::Update(update) => update,
+        _ => panic!("expected UPDATE"),
+    };
+    assert_eq!(format!("{}", update.withdrawn_routes[0]), "198.51.100.0/24");
+    assert_eq!(update.path_attributes.len(), 3);
+    assert_eq!(format!("{}", update.nlri[0]), "203.0.113.0/24");
+}
@@ -0,0 +1,767 @@
+use bgp::{AsnSize, BGPPathAttribute, PathAttributeValue, Prefix, RouteAttributes};
+use bgp4mp::{BGP4MPMessage, BGP4MPPeer, BGP4MPStateChange, BGPMessage, BGPState};
+use mrt::{MRTHeader, MRTRecord, MRTType, PeerEntry, RibEntry};
+use num_traits::cast::FromPrimitive;
+use route::{routes, Route, RouteKind};
+use std::io::{self, Write};
+use std::net::IpAddr;
+
+// Output compatible with `bgpdump -m`, one line per route:
+//
+// TABLE_DUMP|992216782|B|193.148.15.85|3257|3.0.0.0/8|3257 701 80|IGP|193.148.15.85|0|0||NAG||
+// TABLE_DUMP2|1278892800|B|91.103.24.2|42109|0.0.0.0/0|42109 41965 41877 20771 3356|IGP|91.103.24.2|0|0||NAG||
+// BGP4MP|1272931207|A|195.66.224.175|8330|41.223.152.0/22|8330 3356 36944|IGP|195.66.224.175|0|0|3356:3|NAG||
+// BGP4MP|1272931207|W|195.66.224.175|8330|193.149.4.0/22
+// BGP4MP|1272931229|STATE|195.66.224.32|13030|3|1
+//
+// Route fields after the prefix are: AS path, origin, next hop, local pref,
+// MED, communities, atomic aggregate (AG/NAG) and aggregator.
+//
+// `bgpdump -M` prints the same lines with the time as MM/DD/YY HH:MM:SS.
+//
+// The default `bgpdump` output is one block per record (per RIB entry for
+// TABLE_DUMP_V2), each followed by a blank line:
+//
+// TIME: 05/04/10 00:00:07
+// TYPE: BGP4MP/MESSAGE/Update
+// FROM: 195.66.224.175 AS8330
+// TO: 195.66.225.222 AS6447
+// ORIGIN: IGP
+// ASPATH: 8330 3356 36944
+// NEXT_HOP: 195.66.224.175
+// COMMUNITY: 3356:3
+// ANNOUNCE
+//   41.223.152.0/22
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeFormat {
+    // seconds since the epoch
+    Unix,
+    // MM/DD/YY HH:MM:SS, UTC
+    Human,
+}
+
+// MM/DD/YY HH:MM:SS in UTC, as bgpdump prints it.
+pub fn format_time(ts: u32) -> String {
+    let days = i64::from(ts / 86400);
+    let secs = ts % 86400;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:02}/{:02}/{:02} {:02}:{:02}:{:02}",
+        month,
+        day,
+        year % 100,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+fn timestamp(header: &MRTHeader, format: TimeFormat) -> String {
+    let time = match format {
+        TimeFormat::Unix => header.timestamp.to_string(),
+        TimeFormat::Human => format_time(header.timestamp),
+    };
+    match header.microsecond_timestamp {
+        Some(us) => format!("{}.{:06}", time, us),
+        None => time,
+    }
+}
+
+// bgpdump's names for the BGP FSM states.
+pub fn state_name(state: u16) -> &'static str {
+    match BGPState::from_u16(state) {
+        Some(BGPState::Idle) => "Idle",
+        Some(BGPState::Connect) => "Connect",
+        Some(BGPState::Active) => "Active",
+        Some(BGPState::OpenSent) => "Opensent",
+        Some(BGPState::OpenConfirm) => "Openconfirm",
+        Some(BGPState::Established) => "Established",
+        None => "Unknown",
+    }
+}
+
+fn bgp4mp_label(header: &MRTHeader) -> &'static str {
+    match MRTType::from_u16(header.mrt_type) {
+        Some(MRTType::BGP4MP_ET) => "BGP4MP_ET",
+        _ => "BGP4MP",
+    }
+}
+
+pub fn communities(route: &RouteAttributes) -> String {
+    let mut out: Vec<String> = route.communities.iter().map(|c| c.to_string()).collect();
+    out.extend(route.large_communities.iter().map(|c| c.to_string()));
+    out.join(" ")
+}
+
+// Everything after the prefix, including the trailing '|'.
+fn route_fields(route: &Route) -> String {
+    let attrs = &route.attributes;
+    let as_path = match attrs.as_path {
+        Some(ref path) => path.to_string(),
+        None => String::new(),
+    };
+    let origin = match attrs.origin {
+        Some(origin) => origin.to_string(),
+        None => "IGP".to_string(),
+    };
+    let aggregator = match attrs.aggregator {
+        Some(ref agg) => format!("{} {}", agg.asn, agg.address),
+        None => String::new(),
+    };
+    format!(
+        "{}|{}|{}|{}|{}|{}|{}|{}|",
+        as_path,
+        origin,
+        route.next_hop_or_unspecified(),
+        attrs.local_pref.unwrap_or(0),
+        attrs.med.unwrap_or(0),
+        communities(attrs),
+        if attrs.atomic_aggregate { "AG" } else { "NAG" },
+        aggregator
+    )
+}
+
+fn invalid_data(e: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+// Write the `bgpdump -m` lines for one record. TABLE_DUMP_V2 RIB records
+// need the peers from the preceding PEER_INDEX_TABLE.
+pub fn write_oneline<W: Write>(
+    out: &mut W,
+    header: &MRTHeader,
+    record: &MRTRecord,
+    peers: &[PeerEntry],
+) -> io::Result<()> {
+    write_lines(out, TimeFormat::Unix, header, record, peers)
+}
+
+// As write_oneline, with the time formatted as `bgpdump -M` does.
+pub fn write_oneline_human<W: Write>(
+    out: &mut W,
+    header: &MRTHeader,
+    record: &MRTRecord,
+    peers: &[PeerEntry],
+) -> io::Result<()> {
+    write_lines(out, TimeFormat::Human, header, record, peers)
+}
+
+fn write_lines<W: Write>(
+    out: &mut W,
+    format: TimeFormat,
+    header: &MRTHeader,
+    record: &MRTRecord,
+    peers: &[PeerEntry],
+) -> io::Result<()> {
+    let time = timestamp(header, format);
+    let label = match *record {
+        MRTRecord::TableDumpIPv4(_) | MRTRecord::TableDumpIPv6(_) => "TABLE_DUMP",
+        MRTRecord::RibIPv4Unicast(_) | MRTRecord::RibIPv6Unicast(_) => "TABLE_DUMP2",
+        MRTRecord::BGP4MPStateChange(ref state) => {
+            // BGP4MP|<time>|STATE|<peer address>|<peer AS>|<old>|<new>
+            return writeln!(
+                out,
+                "{}|{}|STATE|{}|{}|{}|{}",
+                bgp4mp_label(header),
+                time,
+                state.peer.peer_address,
+                state.peer.peer_asn,
+                state.old_state,
+                state.new_state
+            );
+        }
+        _ => bgp4mp_label(header),
+    };
+
+    for route in routes(header, record, peers).map_err(invalid_data)? {
+        write!(
+            out,
+            "{}|{}|{}|{}|{}|{}",
+            label,
+            time,
+            route.kind.code(),
+            route.peer_address,
+            route.peer_asn,
+            route.prefix
+        )?;
+        match route.kind {
+            RouteKind::Withdraw => writeln!(out)?,
+            _ => writeln!(out, "|{}", route_fields(&route))?,
+        }
+    }
+    Ok(())
+}
+
+fn mp_reach_label(afi: u16, safi: u8) -> String {
+    let family = match afi {
+        1 => "IPv4",
+        2 => "IPv6",
+        _ => return format!("AFI {} SAFI {}", afi, safi),
+    };
+    match safi {
+        1 => format!("{} Unicast", family),
+        2 => format!("{} Multicast", family),
+        _ => format!("{} SAFI {}", family, safi),
+    }
+}
+
+// The attribute lines of a verbose block, in bgpdump's order.
+fn write_attributes<W: Write>(
+    out: &mut W,
+    attrs: &[BGPPathAttribute],
+    asn_size: AsnSize,
+) -> io::Result<()> {
+    let route = RouteAttributes::new(attrs, asn_size);
+    if let Some(origin) = route.origin {
+        writeln!(out, "ORIGIN: {}", origin)?;
+    }
+    if let Some(ref path) = route.as_path {
+        writeln!(out, "ASPATH: {}", path)?;
+    }
+    if let Some(next_hop) = route.next_hop {
+        writeln!(out, "NEXT_HOP: {}", next_hop)?;
+    }
+    if let Some(med) = route.med {
+        writeln!(out, "MULTI_EXIT_DISC: {}", med)?;
+    }
+    if let Some(local_pref) = route.local_pref {
+        writeln!(out, "LOCAL_PREF: {}", local_pref)?;
+    }
+    if route.atomic_aggregate {
+        writeln!(out, "ATOMIC_AGGREGATE")?;
+    }
+    if let Some(ref agg) = route.aggregator {
+        writeln!(out, "AGGREGATOR: AS{} {}", agg.asn, agg.address)?;
+    }
+    if let Some(id) = route.originator_id {
+        writeln!(out, "ORIGINATOR_ID: {}", id)?;
+    }
+    if !route.cluster_list.is_empty() {
+        write!(out, "CLUSTER_LIST:")?;
+        for id in &route.cluster_list {
+            write!(out, " {}", id)?;
+        }
+        writeln!(out)?;
+    }
+    for attr in attrs {
+        if let Ok(PathAttributeValue::Unknown) = attr.value(asn_size) {
+            write!(out, "UNKNOWN_ATTR({}, {}, {}):", attr.flags, attr.code, attr.len)?;
+            for byte in attr.data.iter() {
+                write!(out, " {:02x}", byte)?;
+            }
+            writeln!(out)?;
+        }
+    }
+    // bgpdump lists the multiprotocol prefixes under their attribute, not
+    // in the ANNOUNCE and WITHDRAW blocks
+    if let Some(ref mp) = route.mp_reach_nlri {
+        writeln!(out, "MP_REACH_NLRI({})", mp_reach_label(mp.afi, mp.safi))?;
+        writeln!(out, "NEXT_HOP: {}", mp.next_hop)?;
+        if let Some(link_local) = mp.link_local_next_hop {
+            writeln!(out, "NEXT_HOP: {}", link_local)?;
+        }
+        write_prefixes(out, &mp.nlri)?;
+    }
+    if let Some(ref mp) = route.mp_unreach_nlri {
+        writeln!(out, "MP_UNREACH_NLRI({})", mp_reach_label(mp.afi, mp.safi))?;
+        write_prefixes(out, &mp.withdrawn_routes)?;
+    }
+    if !route.communities.is_empty() {
+        write!(out, "COMMUNITY:")?;
+        for community in &route.communities {
+            write!(out, " {}", community)?;
+        }
+        writeln!(out)?;
+    }
+    for attr in attrs {
+        match attr.value(asn_size) {
+            Ok(PathAttributeValue::As4Path(path)) => writeln!(out, "AS4_PATH: {}", path)?,
+            Ok(PathAttributeValue::As4Aggregator(agg)) => {
+                writeln!(out, "AS4_AGGREGATOR: AS{} {}", agg.asn, agg.address)?
+            }
+            _ => {}
+        }
+    }
+    if !route.large_communities.is_empty() {
+        write!(out, "LARGE_COMMUNITY:")?;
+        for community in &route.large_communities {
+            write!(out, " {}", community)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn write_prefixes<W: Write>(out: &mut W, prefixes: &[Prefix]) -> io::Result<()> {
+    for prefix in prefixes {
+        writeln!(out, "  {}", prefix)?;
+    }
+    Ok(())
+}
+
+// The WITHDRAW or ANNOUNCE block of an UPDATE, if it has prefixes outside
+// the multiprotocol attributes.
+fn write_prefix_block<W: Write>(out: &mut W, label: &str, prefixes: &[Prefix]) -> io::Result<()> {
+    if prefixes.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "{}", label)?;
+    write_prefixes(out, prefixes)
+}
+
+// The TABLE_DUMP fields shared by the IPv4 and IPv6 records.
+struct TableDumpFields {
+    view_number: u16,
+    sequence_number: u16,
+    prefix: Prefix,
+    status: u8,
+    originated_time: u32,
+    peer_address: IpAddr,
+    peer_asn: u16,
+}
+
+fn write_verbose_table_dump<W: Write>(
+    out: &mut W,
+    r: &TableDumpFields,
+    attrs: Result<Vec<BGPPathAttribute>, String>,
+) -> io::Result<()> {
+    let attrs = attrs.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    writeln!(out, "VIEW: {}", r.view_number)?;
+    writeln!(out, "SEQUENCE: {}", r.sequence_number)?;
+    writeln!(out, "PREFIX: {}", r.prefix)?;
+    writeln!(out, "FROM:{} AS{}", r.peer_address, r.peer_asn)?;
+    writeln!(out, "ORIGINATED: {}", format_time(r.originated_time))?;
+    write_attributes(out, &attrs, AsnSize::Two)?;
+    writeln!(out, "STATUS: 0x{:x}", r.status)
+}
+
+fn write_verbose_rib<W: Write>(
+    out: &mut W,
+    time: &str,
+    afi: &str,
+    prefix: &Prefix,
+    sequence_number: u32,
+    entries: &[RibEntry],
+    peers: &[PeerEntry],
+) -> io::Result<()> {
+    let write_record_lines = |out: &mut W| {
+        writeln!(out, "TYPE: TABLE_DUMP_V2/{}_UNICAST", afi)?;
+        writeln!(out, "PREFIX: {}", prefix)?;
+        writeln!(out, "SEQUENCE: {}", sequence_number)
+    };
+    write_record_lines(out)?;
+    for (i, entry) in entries.iter().enumerate() {
+        // bgpdump gives each RIB entry a block of its own, repeating the
+        // record's TIME, TYPE, PREFIX and SEQUENCE
+        if i > 0 {
+            writeln!(out)?;
+            writeln!(out, "TIME: {}", time)?;
+            write_record_lines(out)?;
+        }
+        let peer = peers.get(entry.peer_index as usize).ok_or_else(|| {
+            invalid_data(format!("peer index {} not in PEER_INDEX_TABLE", entry.peer_index))
+        })?;
+        writeln!(out, "FROM: {} AS{}", peer.peer_address, peer.peer_asn)?;
+        writeln!(out, "ORIGINATED: {}", format_time(entry.originated_timestamp))?;
+        write_attributes(out, &entry.bgp_path_attrs, AsnSize::Four)?;
+    }
+    Ok(())
+}
+
+fn write_verbose_peers<W: Write>(out: &mut W, peer: &BGP4MPPeer) -> io::Result<()> {
+    writeln!(out, "FROM: {} AS{}", peer.peer_address, peer.peer_asn)?;
+    writeln!(out, "TO: {} AS{}", peer.local_address, peer.local_asn)
+}
+
+fn write_verbose_message<W: Write>(
+    out: &mut W,
+    header: &MRTHeader,
+    msg: &BGP4MPMessage,
+) -> io::Result<()> {
+    let label = bgp4mp_label(header);
+    match msg.message {
+        BGPMessage::Update(ref update) => {
+            writeln!(out, "TYPE: {}/MESSAGE/Update", label)?;
+            write_verbose_peers(out, &msg.peer)?;
+            write_attributes(out, &update.path_attributes, msg.asn_size)?;
+            write_prefix_block(out, "WITHDRAW", &update.withdrawn_routes)?;
+            write_prefix_block(out, "ANNOUNCE", &update.nlri)
+        }
+        BGPMessage::Open(ref open) => {
+            writeln!(out, "TYPE: {}/MESSAGE/Open", label)?;
+            write_verbose_peers(out, &msg.peer)?;
+            writeln!(out, "VERSION: {}", open.version)?;
+            writeln!(out, "AS: {}", open.my_asn)?;
+            writeln!(out, "HOLD_TIME: {}", open.hold_time)?;
+            writeln!(out, "ID: {}", open.bgp_id)?;
+            writeln!(out, "OPT_PARM_LEN: {}", open.opt_params.len())
+        }
+        BGPMessage::Notification(ref notification) => {
+            writeln!(out, "TYPE: {}/MESSAGE/Notify", label)?;
+            write_verbose_peers(out, &msg.peer)?;
+            writeln!(out, "ERROR CODE: {}", notification.error_code)?;
+            writeln!(out, "SUB ERROR: {}", notification.error_subcode)
+        }
+        BGPMessage::Keepalive => {
+            writeln!(out, "TYPE: {}/MESSAGE/Keepalive", label)?;
+            write_verbose_peers(out, &msg.peer)
+        }
+        BGPMessage::RouteRefresh => {
+            writeln!(out, "TYPE: {}/MESSAGE/Route_Refresh", label)?;
+            write_verbose_peers(out, &msg.peer)
+        }
+        BGPMessage::Unknown(kind) => {
+            writeln!(out, "TYPE: {}/MESSAGE/Unknown({})", label, kind)?;
+            write_verbose_peers(out, &msg.peer)
+        }
+    }
+}
+
+fn write_verbose_state_change<W: Write>(
+    out: &mut W,
+    header: &MRTHeader,
+    state: &BGP4MPStateChange,
+) -> io::Result<()> {
+    writeln!(out, "TYPE: {}/STATE_CHANGE", bgp4mp_label(header))?;
+    writeln!(out, "PEER: {} AS{}", state.peer.peer_address, state.peer.peer_asn)?;
+    writeln!(
+        out,
+        "STATE: {}/{}",
+        state_name(state.old_state),
+        state_name(state.new_state)
+    )
+}
+
+// Write the default, multi-line `bgpdump` block for one record.
+pub fn write_verbose<W: Write>(
+    out: &mut W,
+    header: &MRTHeader,
+    record: &MRTRecord,
+    peers: &[PeerEntry],
+) -> io::Result<()> {
+    let time = timestamp(header, TimeFormat::Human);
+    match *record {
+        // Nothing to show for these; bgpdump skips them too.
+        MRTRecord::PeerIndexTable(_) | MRTRecord::Unsupported => return Ok(()),
+        _ => writeln!(out, "TIME: {}", time)?,
+    }
+    match *record {
+        MRTRecord::TableDumpIPv4(ref r) => {
+            writeln!(out, "TYPE: TABLE_DUMP/INET")?;
+            let fields = TableDumpFields {
+                view_number: r.view_number,
+                sequence_number: r.sequence_number,
+                prefix: r.prefix(),
+                status: r.status,
+                originated_time: r.originated_time,
+                peer_address: IpAddr::V4(r.peer_address),
+                peer_asn: r.peer_asn,
+            };
+            write_verbose_table_dump(out, &fields, r.attributes())?
+        }
+        MRTRecord::TableDumpIPv6(ref r) => {
+            writeln!(out, "TYPE: TABLE_DUMP/INET6")?;
+            let fields = TableDumpFields {
+                view_number: r.view_number,
+                sequence_number: r.sequence_number,
+                prefix: r.prefix(),
+                status: r.status,
+                originated_time: r.originated_time,
+                peer_address: IpAddr::V6(r.peer_address),
+                peer_asn: r.peer_asn,
+            };
+            write_verbose_table_dump(out, &fields, r.attributes())?
+        }
+        MRTRecord::RibIPv4Unicast(ref r) => write_verbose_rib(
+            out,
+            &time,
+            "IPV4",
+            &r.prefix(),
+            r.sequence_number,
+            &r.rib_entries,
+            peers,
+        )?,
+        MRTRecord::RibIPv6Unicast(ref r) => write_verbose_rib(
+            out,
+            &time,
+            "IPV6",
+            &r.prefix(),
+            r.sequence_number,
+            &r.rib_entries,
+            peers,
+        )?,
+        MRTRecord::BGP4MPMessage(ref msg) => write_verbose_message(out, header, msg)?,
+        MRTRecord::BGP4MPStateChange(ref state) => {
+            write_verbose_state_change(out, header, state)?
+        }
+        MRTRecord::PeerIndexTable(_) | MRTRecord::Unsupported => {}
+    }
+    writeln!(out)
+}
+
+#[test]
+fn write_oneline_table_dump_test() {
+    use fixtures::table_dump;
+    use mrt::{parse_mrt_body, split_mrt_record};
+
+    // The record behind the bgpdump sample above.
+    let buffer = table_dump();
+
+    let (rest, header, body) = split_mrt_record(&buffer).unwrap();
+    assert!(rest.is_empty());
+    let record = parse_mrt_body(&header, body).unwrap();
+
+    let mut out = Vec::new();
+    write_oneline(&mut out, &header, &record, &[]).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "TABLE_DUMP|992216782|B|193.148.15.85|3257|3.0.0.0/8|3257 701 80|IGP|193.148.15.85|0|0||NAG||\n"
+    );
+}
+
+#[test]
+fn write_verbose_table_dump_test() {
+    use fixtures::table_dump;
+    use mrt::{parse_mrt_body, split_mrt_record};
+
+    let buffer = table_dump();
+
+    let (_, header, body) = split_mrt_record(&buffer).unwrap();
+    let record = parse_mrt_body(&header, body).unwrap();
+
+    let mut out = Vec::new();
+    write_verbose(&mut out, &header, &record, &[]).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        concat!(
+            "TIME: 06/10/01 23:46:22\n",
+            "TYPE: TABLE_DUMP/INET\n",
+            "VIEW: 0\n",
+            "SEQUENCE: 0\n",
+            "PREFIX: 3.0.0.0/8\n",
+            "FROM:193.148.15.85 AS3257\n",
+            "ORIGINATED: 06/10/01 21:10:28\n",
+            "ORIGIN: IGP\n",
+            "ASPATH: 3257 701 80\n",
+            "NEXT_HOP: 193.148.15.85\n",
+            "STATUS: 0x1\n",
+            "\n"
+        )
+    );
+
+    let mut out = Vec::new();
+    write_oneline_human(&mut out, &header, &record, &[]).unwrap();
+    assert!(String::from_utf8(out).unwrap().starts_with("TABLE_DUMP|06/10/01 23:46:22|B|"));
+}
+
+#[test]
+fn format_time_test() {
+    assert_eq!(format_time(0), "01/01/70 00:00:00");
+    assert_eq!(format_time(951_782_400), "02/29/00 00:00:00");
+    assert_eq!(format_time(1_272_931_207), "05/04/10 00:00:07");
+}
+
+// The output of writer for records given as (type, subtype, body in hex),
+// all timestamped as the BGP4MP samples at the top.
+#[cfg(test)]
+fn write_records(
+    writer: fn(&mut Vec<u8>, &MRTHeader, &MRTRecord, &[PeerEntry]) -> io::Result<()>,
+    records: &[(u16, u16, &str)],
+) -> String {
+    use fixtures::{encode_records, header};
+    use mrt::MRTReader;
+
+    let records: Vec<_> = records
+        .iter()
+        .map(|&(mrt_type, subtype, body)| (header(1_272_931_207, mrt_type, subtype), body))
+        .collect();
+    let dump = encode_records(&records);
+    let mut out = Vec::new();
+    let mut peers = Vec::new();
+    for entry in MRTReader::new(&dump) {
+        let (header, record) = entry.unwrap();
+        writer(&mut out, &header, &record, &peers).unwrap();
+        if let MRTRecord::PeerIndexTable(table) = record {
+            peers = table.peer_entries;
+        }
+    }
+    String::from_utf8(out).unwrap()
+}
+
+// A PEER_INDEX_TABLE of 192.0.2.1 AS64496 and 2001:db8::1 AS4200000000, and
+// a RIB record for 198.51.100.0/24 with an entry from each: the first with
+// every attribute -m shows, an AS_SET in the path and a well-known and a
+// large community, the second with a 4-octet peer ASN.
+#[cfg(test)]
+const TABLE_DUMP_V2_RECORDS: &[(u16, u16, &str)] = &[
+    (13, 1, concat!(
+        "c00002fe", "0000", "0002",
+        "02", "c0000201", "c0000201", "0000fbf0",
+        "03", "c0000202", "20010db8000000000000000000000001", "fa56ea00"
+    )),
+    (13, 2, concat!(
+        "00000000", "18", "c63364", "0002",
+        "0000", "4c2ab000", "0058",
+        "40010100", "40021402020000fbf0fa56ea0001020000fbff0000fc00", "400304c0000201",
+        "8004040000000a", "40050400000064", "400600", "c007080000fbffc0000209",
+        "c00808fbf00001ffffff01", "c0200c0000fbf00000000100000002",
+        "0001", "4c2ab000", "0014",
+        "40010102", "4002060201fa56ea00", "400304c0000202"
+    )),
+];
+
+// BGP4MP_MESSAGE_AS4 UPDATE from 192.0.2.1 AS4200000000 withdrawing
+// 203.0.113.0/24 and, by MP_UNREACH_NLRI, 2001:db8:2::/48, and announcing
+// 198.51.100.0/24 and, by MP_REACH_NLRI, 2001:db8:1::/48 via 2001:db8::1.
+#[cfg(test)]
+const BGP4MP_AS4_UPDATE: (u16, u16, &str) = (16, 4, concat!(
+    "fa56ea00", "0000fbf1", "0000", "0001", "c0000201", "c00002fe",
+    "ffffffffffffffffffffffffffffffff", "006c", "02",
+    "0004", "18cb0071",
+    "004d",
+    "40010100", "40020a0202fa56ea000000fbf4", "400304c0000201",
+    "900e001c0002011020010db8000000000000000000000001003020010db80001",
+    "900f000a0002013020010db80002",
+    "c00804fbf40064",
+    "18c63364"
+));
+
+// BGP4MP_MESSAGE UPDATE from 192.0.2.1 AS64496 with 2-octet ASNs: AS_TRANS
+// in the AS_PATH and AGGREGATOR, the real ones in AS4_PATH and
+// AS4_AGGREGATOR.
+#[cfg(test)]
+const BGP4MP_UPDATE: (u16, u16, &str) = (16, 1, concat!(
+    "fbf0", "fbf1", "0000", "0001", "c0000201", "c00002fe",
+    "ffffffffffffffffffffffffffffffff", "0055", "02",
+    "0000",
+    "003a",
+    "40010100", "4002080203fbf05ba0fbf4", "400304c0000201", "400600",
+    "c007065ba0c0000209", "c0110a0202fa56ea000000fbf4", "c01208fa56ea00c0000209",
+    "18c63364"
+));
+
+// BGP4MP_STATE_CHANGE of 192.0.2.1 AS64496 from Active to Idle
+#[cfg(test)]
+const BGP4MP_STATE_CHANGE: (u16, u16, &str) =
+    (16, 0, concat!("fbf0", "fbf1", "0000", "0001", "c0000201", "c00002fe", "0003", "0001"));
+
+#[test]
+fn write_oneline_table_dump_v2_test() {
+    assert_eq!(
+        write_records(write_oneline, TABLE_DUMP_V2_RECORDS),
+        concat!(
+            "TABLE_DUMP2|1272931207|B|192.0.2.1|64496|198.51.100.0/24|64496 4200000000 {64511,64512}|IGP|192.0.2.1|100|10|64496:1 no-export 64496:1:2|AG|64511 192.0.2.9|\n",
+            "TABLE_DUMP2|1272931207|B|2001:db8::1|4200000000|198.51.100.0/24|4200000000|INCOMPLETE|192.0.2.2|0|0||NAG||\n"
+        )
+    );
+}
+
+#[test]
+fn write_oneline_bgp4mp_test() {
+    assert_eq!(
+        write_records(write_oneline, &[BGP4MP_AS4_UPDATE, BGP4MP_UPDATE, BGP4MP_STATE_CHANGE]),
+        concat!(
+            "BGP4MP|1272931207|W|192.0.2.1|4200000000|203.0.113.0/24\n",
+            "BGP4MP|1272931207|W|192.0.2.1|4200000000|2001:db8:2::/48\n",
+            "BGP4MP|1272931207|A|192.0.2.1|4200000000|198.51.100.0/24|4200000000 64500|IGP|192.0.2.1|0|0|64500:100|NAG||\n",
+            "BGP4MP|1272931207|A|192.0.2.1|4200000000|2001:db8:1::/48|4200000000 64500|IGP|2001:db8::1|0|0|64500:100|NAG||\n",
+            "BGP4MP|1272931207|A|192.0.2.1|64496|198.51.100.0/24|64496 4200000000 64500|IGP|192.0.2.1|0|0||AG|4200000000 192.0.2.9|\n",
+            "BGP4MP|1272931207|STATE|192.0.2.1|64496|3|1\n"
+        )
+    );
+}
+
+#[test]
+fn write_verbose_rib_test() {
+    assert_eq!(
+        write_records(write_verbose, TABLE_DUMP_V2_RECORDS),
+        concat!(
+            "TIME: 05/04/10 00:00:07\n",
+            "TYPE: TABLE_DUMP_V2/IPV4_UNICAST\n",
+            "PREFIX: 198.51.100.0/24\n",
+            "SEQUENCE: 0\n",
+            "FROM: 192.0.2.1 AS64496\n",
+            "ORIGINATED: 06/30/10 02:46:24\n",
+            "ORIGIN: IGP\n",
+            "ASPATH: 64496 4200000000 {64511,64512}\n",
+            "NEXT_HOP: 192.0.2.1\n",
+            "MULTI_EXIT_DISC: 10\n",
+            "LOCAL_PREF: 100\n",
+            "ATOMIC_AGGREGATE\n",
+            "AGGREGATOR: AS64511 192.0.2.9\n",
+            "COMMUNITY: 64496:1 no-export\n",
+            "LARGE_COMMUNITY: 64496:1:2\n",
+            "\n",
+            "TIME: 05/04/10 00:00:07\n",
+            "TYPE: TABLE_DUMP_V2/IPV4_UNICAST\n",
+            "PREFIX: 198.51.100.0/24\n",
+            "SEQUENCE: 0\n",
+            "FROM: 2001:db8::1 AS4200000000\n",
+            "ORIGINATED: 06/30/10 02:46:24\n",
+            "ORIGIN: INCOMPLETE\n",
+            "ASPATH: 4200000000\n",
+            "NEXT_HOP: 192.0.2.2\n",
+            "\n"
+        )
+    );
+}
+
+#[test]
+fn write_verbose_bgp4mp_test() {
+    // BGP4MP_MESSAGE_AS4 OPEN from AS64496, hold time 180
+    const OPEN: (u16, u16, &str) = (16, 4, concat!(
+        "fa56ea00", "0000fbf1", "0000", "0001", "c0000201", "c00002fe",
+        "ffffffffffffffffffffffffffffffff", "001d", "01",
+        "04", "fbf0", "00b4", "c0000201", "00"
+    ));
+    assert_eq!(
+        write_records(write_verbose, &[BGP4MP_AS4_UPDATE, OPEN, BGP4MP_STATE_CHANGE]),
+        concat!(
+            "TIME: 05/04/10 00:00:07\n",
+            "TYPE: BGP4MP/MESSAGE/Update\n",
+            "FROM: 192.0.2.1 AS4200000000\n",
+            "TO: 192.0.2.254 AS64497\n",
+            "ORIGIN: IGP\n",
+            "ASPATH: 4200000000 64500\n",
+            "NEXT_HOP: 192.0.2.1\n",
+            "MP_REACH_NLRI(IPv6 Unicast)\n",
+            "NEXT_HOP: 2001:db8::1\n",
+            "  2001:db8:1::/48\n",
+            "MP_UNREACH_NLRI(IPv6 Unicast)\n",
+            "  2001:db8:2::/48\n",
+            "COMMUNITY: 64500:100\n",
+            "WITHDRAW\n",
+            "  203.0.113.0/24\n",
+            "ANNOUNCE\n",
+            "  198.51.100.0/24\n",
+            "\n",
+            "TIME: 05/04/10 00:00:07\n",
+            "TYPE: BGP4MP/MESSAGE/Open\n",
+            "FROM: 192.0.2.1 AS4200000000\n",
+            "TO: 192.0.2.254 AS64497\n",
+            "VERSION: 4\n",
+            "AS: 64496\n",
+            "HOLD_TIME: 180\n",
+            "ID: 192.0.2.1\n",
+            "OPT_PARM_LEN: 0\n",
+            "\n",
+            "TIME: 05/04/10 00:00:07\n",
+            "TYPE: BGP4MP/STATE_CHANGE\n",
+            "PEER: 192.0.2.1 AS64496\n",
+            "STATE: Active/Idle\n",
+            "\n"
+        )
+    );
+}
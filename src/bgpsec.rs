@@ -0,0 +1,231 @@
+use bgp::{AsPath, AsPathSegment, AsPathSegmentType};
+use nom::{be_u16, be_u32, be_u8, IResult};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// RFC 8205, section 3
+//
+//       +-----------------------------------------------+
+//       | Secure_Path                                   |
+//       +-----------------------------------------------+
+//       | Sequence of one or two Signature_Blocks       |
+//       +-----------------------------------------------+
+//
+// Secure_Path:
+//       +------------------------------------------------------+
+//       | Secure_Path Length                       (2 octets)  |
+//       +------------------------------------------------------+
+//       | One or More Secure_Path Segments         (variable)  |
+//       +------------------------------------------------------+
+//
+// Secure_Path Segment:
+//       +------------------------------------------------------+
+//       | pCount                                    (1 octet)  |
+//       +------------------------------------------------------+
+//       | Confed_Segment flag (1 bit) | Unassigned flags (7 bits)
+//       +------------------------------------------------------+
+//       | AS Number                                (4 octets)  |
+//       +------------------------------------------------------+
+//
+// Signature_Block:
+//       +---------------------------------------------+
+//       | Signature_Block Length         (2 octets)   |
+//       +---------------------------------------------+
+//       | Algorithm Suite Identifier     (1 octet)    |
+//       +---------------------------------------------+
+//       | Sequence of Signature_Segments (variable)   |
+//       +---------------------------------------------+
+//
+// Signature_Segment:
+//       +---------------------------------------------+
+//       | Subject Key Identifier (SKI)  (20 octets)   |
+//       +---------------------------------------------+
+//       | Signature Length              (2 octets)    |
+//       +---------------------------------------------+
+//       | Signature                     (variable)    |
+//       +---------------------------------------------+
+//
+// Both length fields include the length field itself.
+
+const CONFED_SEGMENT_FLAG: u8 = 0x80;
+const SECURE_PATH_SEGMENT_LENGTH: u16 = 6;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SecurePathSegment {
+    pub pcount: u8,
+    pub confed_segment: bool,
+    pub asn: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SignatureSegment {
+    pub ski: [u8; 20],
+    pub signature: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SignatureBlock {
+    pub algorithm_suite: u8,
+    pub signature_segments: Vec<SignatureSegment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BgpsecPath {
+    pub secure_path: Vec<SecurePathSegment>,
+    pub signature_blocks: Vec<SignatureBlock>,
+}
+
+named!(parse_secure_path_segment<SecurePathSegment>,
+    do_parse!(
+        pcount: be_u8  >>
+        flags:  be_u8  >>
+        asn:    be_u32 >>
+        (SecurePathSegment { pcount, confed_segment: flags & CONFED_SEGMENT_FLAG != 0, asn })
+    )
+);
+
+named!(parse_secure_path<Vec<SecurePathSegment> >,
+    do_parse!(
+        length:   verify!(be_u16, |l: u16| l >= 2 && (l - 2).is_multiple_of(SECURE_PATH_SEGMENT_LENGTH)) >>
+        segments: count!(parse_secure_path_segment, ((length - 2) / SECURE_PATH_SEGMENT_LENGTH) as usize) >>
+        (segments)
+    )
+);
+
+named!(parse_signature_segment<SignatureSegment>,
+    do_parse!(
+        ski:       take!(20) >>
+        sig_len:   be_u16    >>
+        signature: take!(sig_len) >>
+        (SignatureSegment {
+            ski: {
+                let mut buf = [0u8; 20];
+                buf.copy_from_slice(ski);
+                buf
+            },
+            signature: signature.to_vec(),
+        })
+    )
+);
+
+fn parse_signature_segments(mut input: &[u8]) -> IResult<&[u8], Vec<SignatureSegment>> {
+    let mut segments = Vec::new();
+    while !input.is_empty() {
+        let (rest, segment) = parse_signature_segment(input)?;
+        segments.push(segment);
+        input = rest;
+    }
+    Ok((input, segments))
+}
+
+named!(parse_signature_block<SignatureBlock>,
+    do_parse!(
+        length:             verify!(be_u16, |l: u16| l >= 3) >>
+        algorithm_suite:    be_u8 >>
+        signature_segments: flat_map!(take!(length - 3), parse_signature_segments) >>
+        (SignatureBlock { algorithm_suite, signature_segments })
+    )
+);
+
+pub fn parse_bgpsec_path(input: &[u8]) -> IResult<&[u8], BgpsecPath> {
+    let (mut input, secure_path) = parse_secure_path(input)?;
+
+    // "one or two" signature blocks, filling the rest of the attribute.
+    let mut signature_blocks = Vec::with_capacity(2);
+    while !input.is_empty() {
+        let (rest, block) = parse_signature_block(input)?;
+        signature_blocks.push(block);
+        input = rest;
+    }
+
+    Ok((input, BgpsecPath { secure_path, signature_blocks }))
+}
+
+impl BgpsecPath {
+    // RFC 8205, section 4.4: an AS appears pCount times in the equivalent
+    // AS_PATH (pCount 0 being a transparent route server), and segments with
+    // the Confed_Segment flag form an AS_CONFED_SEQUENCE.
+    pub fn as_path(&self) -> AsPath {
+        let mut segments: Vec<AsPathSegment> = Vec::new();
+        for segment in &self.secure_path {
+            let segment_type = if segment.confed_segment {
+                AsPathSegmentType::AS_CONFED_SEQUENCE
+            } else {
+                AsPathSegmentType::AS_SEQUENCE
+            };
+            let extend = match segments.last() {
+                Some(last) => last.segment_type == segment_type,
+                None => false,
+            };
+            if !extend {
+                segments.push(AsPathSegment { segment_type, asns: Vec::new() });
+            }
+            let last = segments.len() - 1;
+            for _ in 0..segment.pcount {
+                segments[last].asns.push(segment.asn);
+            }
+        }
+        segments.retain(|s| !s.asns.is_empty());
+        AsPath { segments }
+    }
+}
+
+impl fmt::Display for SignatureBlock {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "suite {}:", self.algorithm_suite)?;
+        for segment in &self.signature_segments {
+            write!(fmt, " SKI {}", hex::encode(segment.ski))?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn parse_bgpsec_path_test() {
+    // Secure_Path: length 20 (2 + 3 * 6)
+    //   01 00 0000fde8   <-- AS 65000, pCount 1
+    //   02 00 0000fde9   <-- AS 65001, pCount 2 (prepended)
+    //   01 80 0000fdea   <-- AS 65002, confed segment
+    // Signature_Block: length 28 (2 + 1 + 20 + 2 + 3)
+    //   01               <-- algorithm suite
+    //   SKI (20 octets), signature length 3, signature
+    let buffer = hex::decode(concat!(
+        "0014",
+        "01000000fde8",
+        "02000000fde9",
+        "01800000fdea",
+        "001c",
+        "01",
+        "000102030405060708090a0b0c0d0e0f10111213",
+        "0003",
+        "aabbcc"
+    ))
+    .unwrap();
+
+    let (rest, path) = parse_bgpsec_path(&buffer).unwrap();
+    assert!(rest.is_empty());
+
+    assert_eq!(path.secure_path.len(), 3);
+    assert_eq!(
+        path.secure_path[2],
+        SecurePathSegment { pcount: 1, confed_segment: true, asn: 65002 }
+    );
+    assert_eq!(path.signature_blocks.len(), 1);
+    assert_eq!(path.signature_blocks[0].algorithm_suite, 1);
+    assert_eq!(path.signature_blocks[0].signature_segments[0].ski[19], 0x13);
+    assert_eq!(path.signature_blocks[0].signature_segments[0].signature, vec![0xaa, 0xbb, 0xcc]);
+
+    assert_eq!(format!("{}", path.as_path()), "65000 65001 65001 (65002)");
+}
+
+#[test]
+fn parse_bgpsec_path_bad_length_test() {
+    // Secure_Path length not a multiple of the segment size
+    let buffer = hex::decode("000501000000").unwrap();
+    assert!(parse_bgpsec_path(&buffer).is_err());
+}
@@ -0,0 +1,306 @@
+use arrow_array::builder::{ListBuilder, StringBuilder, UInt32Builder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+#[cfg(feature = "parquet")]
+use parquet::arrow::ArrowWriter;
+#[cfg(feature = "parquet")]
+use parquet::basic::Compression;
+#[cfg(feature = "parquet")]
+use parquet::file::properties::WriterProperties;
+use route::Route;
+#[cfg(feature = "parquet")]
+use std::io::Write;
+use std::sync::Arc;
+
+// Arrow RecordBatches of routes (see route::Route), one row per route, and
+// a Parquet writer for them. The columns are:
+//
+//   timestamp              UInt32
+//   microsecond_timestamp  UInt32, null unless the record is _ET
+//   type                   Utf8: B (RIB), A (announce) or W (withdraw)
+//   peer_ip                Utf8
+//   peer_asn               UInt32
+//   prefix                 Utf8, "address/length"
+//   as_path                List<UInt32>, the ASNs of every segment in order
+//   as_path_text           Utf8, bgpdump notation keeping AS sets etc.
+//   origin_asn             UInt32
+//   origin                 Utf8: IGP, EGP or INCOMPLETE
+//   next_hop               Utf8
+//   communities            List<Utf8>, standard then large, "65535:666"
+//   local_pref             UInt32
+//   med                    UInt32
+//
+// Columns other than timestamp, type, peer_ip, peer_asn and prefix are null
+// when the route does not carry the attribute (always, for withdrawals).
+
+pub fn route_schema() -> SchemaRef {
+    let list_of = |data_type| DataType::List(Arc::new(Field::new("item", data_type, true)));
+    Arc::new(Schema::new(vec![
+        Field::new("timestamp", DataType::UInt32, false),
+        Field::new("microsecond_timestamp", DataType::UInt32, true),
+        Field::new("type", DataType::Utf8, false),
+        Field::new("peer_ip", DataType::Utf8, false),
+        Field::new("peer_asn", DataType::UInt32, false),
+        Field::new("prefix", DataType::Utf8, false),
+        Field::new("as_path", list_of(DataType::UInt32), true),
+        Field::new("as_path_text", DataType::Utf8, true),
+        Field::new("origin_asn", DataType::UInt32, true),
+        Field::new("origin", DataType::Utf8, true),
+        Field::new("next_hop", DataType::Utf8, true),
+        Field::new("communities", list_of(DataType::Utf8), true),
+        Field::new("local_pref", DataType::UInt32, true),
+        Field::new("med", DataType::UInt32, true),
+    ]))
+}
+
+// Accumulates routes into a RecordBatch with route_schema().
+pub struct RouteBatchBuilder {
+    timestamp: UInt32Builder,
+    microsecond_timestamp: UInt32Builder,
+    kind: StringBuilder,
+    peer_ip: StringBuilder,
+    peer_asn: UInt32Builder,
+    prefix: StringBuilder,
+    as_path: ListBuilder<UInt32Builder>,
+    as_path_text: StringBuilder,
+    origin_asn: UInt32Builder,
+    origin: StringBuilder,
+    next_hop: StringBuilder,
+    communities: ListBuilder<StringBuilder>,
+    local_pref: UInt32Builder,
+    med: UInt32Builder,
+    len: usize,
+}
+
+impl Default for RouteBatchBuilder {
+    fn default() -> Self {
+        RouteBatchBuilder::new()
+    }
+}
+
+impl RouteBatchBuilder {
+    pub fn new() -> RouteBatchBuilder {
+        RouteBatchBuilder {
+            timestamp: UInt32Builder::new(),
+            microsecond_timestamp: UInt32Builder::new(),
+            kind: StringBuilder::new(),
+            peer_ip: StringBuilder::new(),
+            peer_asn: UInt32Builder::new(),
+            prefix: StringBuilder::new(),
+            as_path: ListBuilder::new(UInt32Builder::new()),
+            as_path_text: StringBuilder::new(),
+            origin_asn: UInt32Builder::new(),
+            origin: StringBuilder::new(),
+            next_hop: StringBuilder::new(),
+            communities: ListBuilder::new(StringBuilder::new()),
+            local_pref: UInt32Builder::new(),
+            med: UInt32Builder::new(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, route: &Route) {
+        let attrs = &route.attributes;
+        self.timestamp.append_value(route.timestamp);
+        self.microsecond_timestamp.append_option(route.microsecond_timestamp);
+        self.kind.append_value(route.kind.code());
+        self.peer_ip.append_value(route.peer_address.to_string());
+        self.peer_asn.append_value(route.peer_asn);
+        self.prefix.append_value(route.prefix.to_string());
+        match attrs.as_path {
+            Some(ref path) => {
+                for segment in &path.segments {
+                    self.as_path.values().append_slice(&segment.asns);
+                }
+                self.as_path.append(true);
+                self.as_path_text.append_value(path.to_string());
+            }
+            None => {
+                self.as_path.append(false);
+                self.as_path_text.append_null();
+            }
+        }
+        self.origin_asn.append_option(route.origin_asn());
+        self.origin.append_option(attrs.origin.map(|o| o.to_string()));
+        self.next_hop.append_option(route.next_hop().map(|a| a.to_string()));
+        let communities = route.communities();
+        if communities.is_empty() {
+            self.communities.append(false);
+        } else {
+            for community in communities {
+                self.communities.values().append_value(community);
+            }
+            self.communities.append(true);
+        }
+        self.local_pref.append_option(attrs.local_pref);
+        self.med.append_option(attrs.med);
+        self.len += 1;
+    }
+
+    // The batch of the routes pushed so far; the builder is left empty.
+    pub fn finish(&mut self) -> RecordBatch {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.timestamp.finish()),
+            Arc::new(self.microsecond_timestamp.finish()),
+            Arc::new(self.kind.finish()),
+            Arc::new(self.peer_ip.finish()),
+            Arc::new(self.peer_asn.finish()),
+            Arc::new(self.prefix.finish()),
+            Arc::new(self.as_path.finish()),
+            Arc::new(self.as_path_text.finish()),
+            Arc::new(self.origin_asn.finish()),
+            Arc::new(self.origin.finish()),
+            Arc::new(self.next_hop.finish()),
+            Arc::new(self.communities.finish()),
+            Arc::new(self.local_pref.finish()),
+            Arc::new(self.med.finish()),
+        ];
+        self.len = 0;
+        RecordBatch::try_new(route_schema(), columns)
+            .expect("columns built to match route_schema")
+    }
+}
+
+pub fn routes_to_batch(routes: &[Route]) -> RecordBatch {
+    let mut builder = RouteBatchBuilder::new();
+    for route in routes {
+        builder.push(route);
+    }
+    builder.finish()
+}
+
+// Routes buffered into each RecordBatch handed to the Parquet writer.
+#[cfg(feature = "parquet")]
+const PARQUET_BATCH_SIZE: usize = 64 * 1024;
+
+// Writes routes to a Snappy-compressed Parquet file with route_schema().
+#[cfg(feature = "parquet")]
+pub struct ParquetRouteWriter<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    builder: RouteBatchBuilder,
+}
+
+#[cfg(feature = "parquet")]
+impl<W: Write + Send> ParquetRouteWriter<W> {
+    pub fn new(out: W) -> Result<ParquetRouteWriter<W>, String> {
+        let props = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+        let writer =
+            ArrowWriter::try_new(out, route_schema(), Some(props)).map_err(|e| e.to_string())?;
+        Ok(ParquetRouteWriter { writer, builder: RouteBatchBuilder::new() })
+    }
+
+    pub fn write(&mut self, routes: &[Route]) -> Result<(), String> {
+        for route in routes {
+            self.builder.push(route);
+            if self.builder.len() >= PARQUET_BATCH_SIZE {
+                self.flush_batch()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush_batch(&mut self) -> Result<(), String> {
+        if self.builder.is_empty() {
+            return Ok(());
+        }
+        let batch = self.builder.finish();
+        self.writer.write(&batch).map_err(|e| e.to_string())
+    }
+
+    // Write any buffered routes and the Parquet footer.
+    pub fn close(mut self) -> Result<(), String> {
+        self.flush_batch()?;
+        self.writer.close().map(|_| ()).map_err(|e| e.to_string())
+    }
+}
+
+#[test]
+fn routes_to_batch_test() {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::UInt32Type;
+    use arrow_array::Array;
+    use bgp::{AsPath, AsPathSegment, AsPathSegmentType, Community, Prefix, RouteAttributes};
+    use route::RouteKind;
+
+    let announce = Route {
+        timestamp: 1_272_931_207,
+        microsecond_timestamp: None,
+        kind: RouteKind::Announce,
+        peer_address: "195.66.224.175".parse().unwrap(),
+        peer_asn: 8330,
+        prefix: Prefix { addr: "41.223.152.0".parse().unwrap(), len: 22 },
+        attributes: RouteAttributes {
+            as_path: Some(AsPath {
+                segments: vec![AsPathSegment {
+                    segment_type: AsPathSegmentType::AS_SEQUENCE,
+                    asns: vec![8330, 3356, 36944],
+                }],
+            }),
+            communities: vec![Community(0x0d1c_0003)],
+            ..Default::default()
+        },
+    };
+    let withdraw = Route {
+        kind: RouteKind::Withdraw,
+        attributes: RouteAttributes::default(),
+        ..announce.clone()
+    };
+
+    let batch = routes_to_batch(&[announce, withdraw]);
+    assert_eq!(batch.num_rows(), 2);
+    assert_eq!(batch.schema(), route_schema());
+
+    let as_path = batch.column_by_name("as_path").unwrap().as_list::<i32>();
+    let asns = as_path.value(0);
+    assert_eq!(asns.as_primitive::<UInt32Type>().values(), &[8330, 3356, 36944]);
+    assert!(as_path.is_null(1));
+
+    let origin_asn = batch.column_by_name("origin_asn").unwrap().as_primitive::<UInt32Type>();
+    assert_eq!(origin_asn.value(0), 36944);
+    assert!(origin_asn.is_null(1));
+
+    let communities = batch.column_by_name("communities").unwrap().as_list::<i32>();
+    assert_eq!(communities.value(0).as_string::<i32>().value(0), "3356:3");
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn parquet_round_trip_test() {
+    use bgp::{Prefix, RouteAttributes};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use route::RouteKind;
+    use std::fs::{self, File};
+
+    let route = Route {
+        timestamp: 1_272_931_207,
+        microsecond_timestamp: Some(5),
+        kind: RouteKind::Withdraw,
+        peer_address: "2001:db8::1".parse().unwrap(),
+        peer_asn: 65000,
+        prefix: Prefix { addr: "2001:db8:1::".parse().unwrap(), len: 48 },
+        attributes: RouteAttributes::default(),
+    };
+
+    let path = std::env::temp_dir().join(format!("mrt-parser-{}.parquet", std::process::id()));
+    let mut writer = ParquetRouteWriter::new(File::create(&path).unwrap()).unwrap();
+    writer.write(&[route.clone(), route]).unwrap();
+    writer.close().unwrap();
+
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+        .unwrap()
+        .build()
+        .unwrap();
+    let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 2);
+    assert_eq!(batches[0].schema(), route_schema());
+}
@@ -0,0 +1,166 @@
+#[cfg(feature = "bzip2")]
+use bzip2::bufread::MultiBzDecoder;
+#[cfg(feature = "gzip")]
+use flate2::bufread::MultiGzDecoder;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+#[cfg(feature = "xz")]
+use xz2::bufread::XzDecoder;
+#[cfg(feature = "zstd")]
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+// MRT archives come raw or compressed in any of several formats (RouteViews
+// uses bzip2, RIPE RIS gzip). The format is told from the first bytes of the
+// input; each decompressor is behind a cargo feature of the same name (gzip
+// being on by default), and input needing a disabled one is an error.
+//
+// Collectors append gzip members to a file as they go, and `cat a.gz b.gz`
+// gives the same shape, so every member (or bzip2 stream, xz stream or zstd
+// frame) is read, not just the first.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    // The format starting with these bytes; anything unrecognised is taken
+    // to be raw MRT.
+    pub fn detect(magic: &[u8]) -> Compression {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if magic.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Compression::None => "raw",
+            Compression::Gzip => "gzip",
+            Compression::Bzip2 => "bzip2",
+            Compression::Xz => "xz",
+            Compression::Zstd => "zstd",
+        }
+    }
+}
+
+fn unsupported(compression: Compression) -> io::Error {
+    let name = compression.name();
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} compressed input needs the \"{}\" feature", name, name),
+    )
+}
+
+// Wrap input in the decompressor its first bytes call for.
+pub fn decompress<'a, R: BufRead + 'a>(mut input: R) -> io::Result<Box<dyn Read + 'a>> {
+    let compression = Compression::detect(input.fill_buf()?);
+    match compression {
+        Compression::None => Ok(Box::new(input)),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Ok(Box::new(MultiGzDecoder::new(input))),
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => Ok(Box::new(MultiBzDecoder::new(input))),
+        #[cfg(feature = "xz")]
+        Compression::Xz => Ok(Box::new(XzDecoder::new_multi_decoder(input))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Box::new(ZstdDecoder::with_buffer(input)?)),
+        #[allow(unreachable_patterns)]
+        _ => Err(unsupported(compression)),
+    }
+}
+
+// The whole of a possibly compressed input, decompressed, ready for
+// mrt::MRTReader.
+pub struct Decompressed {
+    pub data: Vec<u8>,
+    // A decoding error once some data had come out, e.g. a truncated last
+    // member or junk after the last one. The data stops there rather than
+    // the whole input being lost.
+    pub warning: Option<String>,
+}
+
+pub fn read_all<R: BufRead>(input: R) -> io::Result<Decompressed> {
+    let mut data = Vec::new();
+    match decompress(input)?.read_to_end(&mut data) {
+        Ok(_) => Ok(Decompressed { data, warning: None }),
+        Err(ref e) if !data.is_empty() => {
+            let warning = Some(format!("input ends in data that does not decompress: {}", e));
+            Ok(Decompressed { data, warning })
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// Read and decompress the file at path, or stdin for "-".
+pub fn read_file(path: &str) -> Result<Decompressed, String> {
+    if path == "-" {
+        let stdin = io::stdin();
+        return read_all(stdin.lock()).map_err(|e| format!("{}: {}", path, e));
+    }
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    read_all(BufReader::new(file)).map_err(|e| format!("{}: {}", path, e))
+}
+
+#[test]
+fn read_all_test() {
+    use fixtures::table_dump;
+
+    // TABLE_DUMP record, raw and compressed
+    let raw = table_dump();
+    assert_eq!(Compression::detect(&raw), Compression::None);
+    assert_eq!(read_all(&raw[..]).unwrap().data, raw);
+
+    #[cfg(feature = "gzip")]
+    {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+        let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&raw).unwrap();
+        let compressed = gz.finish().unwrap();
+        assert_eq!(Compression::detect(&compressed), Compression::Gzip);
+        assert_eq!(read_all(&compressed[..]).unwrap().data, raw);
+
+        // cat a.gz b.gz, then junk
+        let mut members = [&compressed[..], &compressed[..], b"junk"].concat();
+        let decompressed = read_all(&members[..]).unwrap();
+        assert_eq!(decompressed.data, [&raw[..], &raw[..]].concat());
+        assert!(decompressed.warning.is_some());
+        members.truncate(members.len() - 4);
+        assert!(read_all(&members[..]).unwrap().warning.is_none());
+    }
+    #[cfg(feature = "bzip2")]
+    {
+        let mut bz = bzip2::bufread::BzEncoder::new(&raw[..], bzip2::Compression::default());
+        let mut compressed = Vec::new();
+        bz.read_to_end(&mut compressed).unwrap();
+        assert_eq!(Compression::detect(&compressed), Compression::Bzip2);
+        let streams = [&compressed[..], &compressed[..]].concat();
+        assert_eq!(read_all(&streams[..]).unwrap().data, [&raw[..], &raw[..]].concat());
+    }
+    #[cfg(feature = "xz")]
+    {
+        let mut xz = xz2::bufread::XzEncoder::new(&raw[..], 6);
+        let mut compressed = Vec::new();
+        xz.read_to_end(&mut compressed).unwrap();
+        assert_eq!(Compression::detect(&compressed), Compression::Xz);
+        assert_eq!(read_all(&compressed[..]).unwrap().data, raw);
+    }
+    #[cfg(feature = "zstd")]
+    {
+        let compressed = zstd::encode_all(&raw[..], 0).unwrap();
+        assert_eq!(Compression::detect(&compressed), Compression::Zstd);
+        assert_eq!(read_all(&compressed[..]).unwrap().data, raw);
+    }
+}
@@ -0,0 +1,240 @@
+use bgp::{AsnSize, BGPPathAttrTypes, BGPPathAttribute, Prefix, RouteAttributes};
+use encode::{encode_as_path, encode_rib_mp_reach_nlri};
+use mrt::{
+    MRTHeader, MRTRecord, MRTTableDumpV2IPv4Unicast, MRTTableDumpV2IPv6Unicast,
+    MRTTableDumpV2PeerIndex, MRTType, PeerEntry, RibEntry, TableDumpV2Subtypes, PEER_TYPE_IPV6,
+};
+use num_traits::cast::FromPrimitive;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr};
+
+// Converts a TABLE_DUMP (v1) dump, one record per peer and prefix, into the
+// TABLE_DUMP_V2 form: a PEER_INDEX_TABLE followed by one RIB_IPV4_UNICAST or
+// RIB_IPV6_UNICAST record per prefix holding an entry per peer.
+//
+// TABLE_DUMP carries no peer BGP IDs or collector ID, so those are written as
+// 0.0.0.0. The attributes are rewritten as TABLE_DUMP_V2 requires: AS_PATH
+// and AGGREGATOR get 4-octet ASNs, with any AS4_PATH and AS4_AGGREGATOR
+// merged in and dropped, and MP_REACH_NLRI is cut down to its next hop.
+//
+// Peers are only known once the whole dump has been seen, so every route is
+// held in memory until finish().
+
+#[derive(Default)]
+pub struct TableDumpV2Converter {
+    timestamp: Option<u32>,
+    peers: Vec<PeerEntry>,
+    peer_indexes: HashMap<(IpAddr, u16), u16>,
+    ribs: BTreeMap<Prefix, Vec<RibEntry<'static>>>,
+}
+
+// The TABLE_DUMP_V2 form of a TABLE_DUMP route's attributes, or an error if
+// the AS_PATH or AGGREGATOR cannot be decoded to widen its ASNs.
+fn convert_attributes(
+    attrs: Vec<BGPPathAttribute>,
+) -> Result<Vec<BGPPathAttribute<'static>>, String> {
+    let route = RouteAttributes::new(&attrs, AsnSize::Two);
+    let mut converted = Vec::with_capacity(attrs.len());
+    for mut attr in attrs {
+        match BGPPathAttrTypes::from_u8(attr.code) {
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_ASPATH) => {
+                let path = route.as_path.as_ref().ok_or("Undecodable AS_PATH")?;
+                attr.data = Cow::Owned(encode_as_path(path, AsnSize::Four)?);
+            }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_AGGREGATOR) => {
+                let aggregator = route.aggregator.ok_or("Undecodable AGGREGATOR")?;
+                let mut data = aggregator.asn.to_be_bytes().to_vec();
+                data.extend_from_slice(&aggregator.address.octets());
+                attr.data = Cow::Owned(data);
+            }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_MP_REACH_NLRI) => {
+                if let Some(ref mp) = route.mp_reach_nlri {
+                    attr.data = Cow::Owned(encode_rib_mp_reach_nlri(mp));
+                }
+            }
+            Some(BGPPathAttrTypes::BGP_PATH_ATTR_AS4_PATH)
+            | Some(BGPPathAttrTypes::BGP_PATH_ATTR_AS4_AGGREGATOR) => continue,
+            _ => {}
+        }
+        attr.len = attr.data.len() as u16;
+        converted.push(attr.into_owned());
+    }
+    Ok(converted)
+}
+
+impl TableDumpV2Converter {
+    pub fn new() -> TableDumpV2Converter {
+        TableDumpV2Converter::default()
+    }
+
+    fn peer_index(&mut self, peer_address: IpAddr, peer_asn: u16) -> Result<u16, String> {
+        if let Some(&index) = self.peer_indexes.get(&(peer_address, peer_asn)) {
+            return Ok(index);
+        }
+        if self.peers.len() == usize::from(u16::MAX) {
+            return Err("More than 65535 peers".to_string());
+        }
+        let index = self.peers.len() as u16;
+        self.peers.push(PeerEntry {
+            peer_type: if peer_address.is_ipv6() { PEER_TYPE_IPV6 } else { 0 },
+            peer_bgp_id: Ipv4Addr::from(0),
+            peer_address,
+            peer_asn: u32::from(peer_asn),
+        });
+        self.peer_indexes.insert((peer_address, peer_asn), index);
+        Ok(index)
+    }
+
+    // Add one TABLE_DUMP record; anything else is an error. The output
+    // records carry the timestamp of the first record added.
+    pub fn add(&mut self, header: &MRTHeader, record: &MRTRecord) -> Result<(), String> {
+        let (prefix, peer_address, peer_asn, originated_time, attrs) = match *record {
+            MRTRecord::TableDumpIPv4(ref r) => (
+                r.prefix(),
+                IpAddr::V4(r.peer_address),
+                r.peer_asn,
+                r.originated_time,
+                r.attributes()?,
+            ),
+            MRTRecord::TableDumpIPv6(ref r) => (
+                r.prefix(),
+                IpAddr::V6(r.peer_address),
+                r.peer_asn,
+                r.originated_time,
+                r.attributes()?,
+            ),
+            _ => return Err("not a TABLE_DUMP record".to_string()),
+        };
+        let bgp_path_attrs = convert_attributes(attrs)?;
+        let peer_index = self.peer_index(peer_address, peer_asn)?;
+        self.timestamp.get_or_insert(header.timestamp);
+        self.ribs.entry(prefix).or_default().push(RibEntry {
+            peer_index,
+            originated_timestamp: originated_time,
+            attr_length: 0,
+            bgp_path_attrs,
+        });
+        Ok(())
+    }
+
+    // The TABLE_DUMP_V2 records, IPv4 prefixes before IPv6 and each family in
+    // address order, with sequence numbers counting from 0. Length and count
+    // fields are left for the encoder to fill in.
+    pub fn finish(self) -> Vec<(MRTHeader, MRTRecord<'static>)> {
+        let timestamp = self.timestamp.unwrap_or(0);
+        let header = |subtype: TableDumpV2Subtypes| MRTHeader {
+            timestamp,
+            mrt_type: MRTType::TABLE_DUMP_V2 as u16,
+            mrt_subtype: subtype as u16,
+            length: 0,
+            microsecond_timestamp: None,
+        };
+
+        let mut records = Vec::with_capacity(self.ribs.len() + 1);
+        records.push((
+            header(TableDumpV2Subtypes::PEER_INDEX_TABLE),
+            MRTRecord::PeerIndexTable(MRTTableDumpV2PeerIndex {
+                collector_bgp_id: Ipv4Addr::from(0),
+                view_name_length: 0,
+                view_name: Cow::Owned(Vec::new()),
+                peer_count: self.peers.len() as u16,
+                peer_entries: self.peers,
+            }),
+        ));
+        for (sequence_number, (prefix, rib_entries)) in self.ribs.into_iter().enumerate() {
+            let sequence_number = sequence_number as u32;
+            let octets = usize::from(prefix.len.div_ceil(8));
+            let entry_count = rib_entries.len() as u16;
+            records.push(match prefix.addr {
+                IpAddr::V4(addr) => (
+                    header(TableDumpV2Subtypes::RIB_IPV4_UNICAST),
+                    MRTRecord::RibIPv4Unicast(MRTTableDumpV2IPv4Unicast {
+                        sequence_number,
+                        prefix_length: prefix.len,
+                        prefix: Cow::Owned(addr.octets()[..octets].to_vec()),
+                        entry_count,
+                        rib_entries,
+                    }),
+                ),
+                IpAddr::V6(addr) => (
+                    header(TableDumpV2Subtypes::RIB_IPV6_UNICAST),
+                    MRTRecord::RibIPv6Unicast(MRTTableDumpV2IPv6Unicast {
+                        sequence_number,
+                        prefix_length: prefix.len,
+                        prefix: Cow::Owned(addr.octets()[..octets].to_vec()),
+                        entry_count,
+                        rib_entries,
+                    }),
+                ),
+            });
+        }
+        records
+    }
+}
+
+#[test]
+fn table_dump_v2_converter_test() {
+    use encode::encode_record;
+    use fixtures::{encode_records, header, table_dump};
+    use mrt::{parse_mrt_body, split_mrt_record, MRTReader};
+    use route::routes;
+
+    // 3.0.0.0/8 from two peers, the second with an AGGREGATOR; 4.0.0.0/8
+    // from the first peer again
+    let table_dump_header = header(0x3b24_06ce, 12, 1);
+    let mut buffer = table_dump();
+    buffer.extend(encode_records(&[
+        (table_dump_header.clone(), concat!(
+            "0000", "0001", "03000000", "08", "01", "3b23e244", "c0000201", "0001", "001b",
+            "40010100", "4002040201", "0001", "400304c0000201", "c00706", "0001", "c0000201"
+        )),
+        (table_dump_header.clone(), concat!(
+            "0000", "0002", "04000000", "08", "01", "3b23e244", "c1940f55", "0cb9", "0016",
+            "40010100", "40020802030cb902bd0050", "400304c1940f55"
+        )),
+    ]));
+
+    let mut converter = TableDumpV2Converter::new();
+    let mut expected = Vec::new();
+    for item in MRTReader::new(&buffer) {
+        let (header, record) = item.unwrap();
+        expected.extend(routes(&header, &record, &[]).unwrap());
+        converter.add(&header, &record).unwrap();
+    }
+
+    let mut output = Vec::new();
+    for (header, record) in converter.finish() {
+        output.extend(encode_record(&header, &record).unwrap());
+    }
+
+    let mut peers = Vec::new();
+    let mut converted = Vec::new();
+    let mut input = &output[..];
+    while !input.is_empty() {
+        let (rest, header, body) = split_mrt_record(input).unwrap();
+        let record = parse_mrt_body(&header, body).unwrap();
+        if let MRTRecord::PeerIndexTable(ref index) = record {
+            assert_eq!(index.peer_count, 2);
+            peers = index.peer_entries.clone();
+        }
+        converted.extend(routes(&header, &record, &peers).unwrap());
+        input = rest;
+    }
+
+    // the same routes, now grouped by prefix
+    let key = |r: &::route::Route| (r.prefix, r.peer_address, r.attributes.clone());
+    let mut expected: Vec<_> = expected.iter().map(key).collect();
+    expected.sort_by_key(|k| k.0);
+    let converted: Vec<_> = converted.iter().map(key).collect();
+    assert_eq!(converted, expected);
+    assert_eq!(converted[1].2.aggregator.map(|a| a.asn), Some(1));
+
+    // an AS_PATH segment of one ASN cut short after its first octet
+    let buffer = encode_records(&[(table_dump_header, concat!(
+        "0000", "0000", "03000000", "08", "01", "3b23e244", "c1940f55", "0cb9", "000a",
+        "40010100", "40020302010c"
+    ))]);
+    let (header, record) = MRTReader::new(&buffer).next().unwrap().unwrap();
+    assert_eq!(TableDumpV2Converter::new().add(&header, &record), Err("Undecodable AS_PATH".to_string()));
+}
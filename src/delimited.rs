@@ -0,0 +1,195 @@
+use mrt::Provenance;
+use route::Route;
+use std::io::{self, Write};
+
+// CSV/TSV output: one row per route (see route::Route), with a header row
+// naming the columns. Values holding the delimiter, a double quote or a line
+// break are quoted as in RFC 4180, e.g. an AS path ending in an AS set:
+//
+// timestamp,peer_ip,peer_asn,prefix,as_path
+// 1272931207,195.66.224.175,8330,41.223.152.0/22,"8330 3356 {36944,36945}"
+//
+// Attributes a route does not carry are left empty. The file, offset and
+// collector columns, where the route's record came from, are only written
+// when asked for.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Timestamp,
+    // B (RIB), A (announce) or W (withdraw)
+    Type,
+    PeerIp,
+    PeerAsn,
+    Prefix,
+    AsPath,
+    OriginAsn,
+    Origin,
+    NextHop,
+    // standard then large communities, space separated
+    Communities,
+    LocalPref,
+    Med,
+    File,
+    Offset,
+    Collector,
+}
+
+const FIELDS: [(&str, Field); 15] = [
+    ("timestamp", Field::Timestamp),
+    ("type", Field::Type),
+    ("peer_ip", Field::PeerIp),
+    ("peer_asn", Field::PeerAsn),
+    ("prefix", Field::Prefix),
+    ("as_path", Field::AsPath),
+    ("origin_asn", Field::OriginAsn),
+    ("origin", Field::Origin),
+    ("next_hop", Field::NextHop),
+    ("communities", Field::Communities),
+    ("local_pref", Field::LocalPref),
+    ("med", Field::Med),
+    ("file", Field::File),
+    ("offset", Field::Offset),
+    ("collector", Field::Collector),
+];
+
+impl Field {
+    pub fn from_name(name: &str) -> Option<Field> {
+        FIELDS.iter().find(|f| f.0 == name).map(|f| f.1)
+    }
+
+    pub fn name(&self) -> &'static str {
+        FIELDS.iter().find(|f| f.1 == *self).map(|f| f.0).unwrap_or("")
+    }
+
+    fn value(&self, route: &Route, source: Option<&Provenance>) -> String {
+        let attrs = &route.attributes;
+        match *self {
+            Field::Timestamp => match route.microsecond_timestamp {
+                Some(us) => format!("{}.{:06}", route.timestamp, us),
+                None => route.timestamp.to_string(),
+            },
+            Field::Type => route.kind.code().to_string(),
+            Field::PeerIp => route.peer_address.to_string(),
+            Field::PeerAsn => route.peer_asn.to_string(),
+            Field::Prefix => route.prefix.to_string(),
+            Field::AsPath => attrs.as_path.as_ref().map(|p| p.to_string()).unwrap_or_default(),
+            Field::OriginAsn => route.origin_asn().map(|a| a.to_string()).unwrap_or_default(),
+            Field::Origin => attrs.origin.map(|o| o.to_string()).unwrap_or_default(),
+            Field::NextHop => route.next_hop().map(|a| a.to_string()).unwrap_or_default(),
+            Field::Communities => route.communities().join(" "),
+            Field::LocalPref => attrs.local_pref.map(|v| v.to_string()).unwrap_or_default(),
+            Field::Med => attrs.med.map(|v| v.to_string()).unwrap_or_default(),
+            Field::File => source.map(|s| s.file.clone()).unwrap_or_default(),
+            Field::Offset => source.map(|s| s.offset.to_string()).unwrap_or_default(),
+            Field::Collector => source.and_then(|s| s.collector.clone()).unwrap_or_default(),
+        }
+    }
+}
+
+// The fields written when none are asked for: all but those of the source.
+pub fn default_fields() -> Vec<Field> {
+    let source = [Field::File, Field::Offset, Field::Collector];
+    FIELDS.iter().map(|f| f.1).filter(|f| !source.contains(f)).collect()
+}
+
+// Parse a comma separated list of field names, e.g. "prefix,as_path".
+pub fn parse_fields(spec: &str) -> Result<Vec<Field>, String> {
+    spec.split(',')
+        .map(|name| {
+            let name = name.trim();
+            Field::from_name(name).ok_or_else(|| {
+                let known: Vec<&str> = FIELDS.iter().map(|f| f.0).collect();
+                format!("Unknown field '{}', expected one of: {}", name, known.join(", "))
+            })
+        })
+        .collect()
+}
+
+fn quote(value: &str, delimiter: char) -> String {
+    if value.contains([delimiter, '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn write_row<W: Write>(out: &mut W, delimiter: char, values: &[String]) -> io::Result<()> {
+    let row: Vec<String> = values.iter().map(|v| quote(v, delimiter)).collect();
+    writeln!(out, "{}", row.join(&delimiter.to_string()))
+}
+
+pub fn write_header<W: Write>(out: &mut W, delimiter: char, fields: &[Field]) -> io::Result<()> {
+    let names: Vec<String> = fields.iter().map(|f| f.name().to_string()).collect();
+    write_row(out, delimiter, &names)
+}
+
+pub fn write_routes<W: Write>(
+    out: &mut W,
+    delimiter: char,
+    fields: &[Field],
+    source: Option<&Provenance>,
+    routes: &[Route],
+) -> io::Result<()> {
+    for route in routes {
+        let values: Vec<String> = fields.iter().map(|f| f.value(route, source)).collect();
+        write_row(out, delimiter, &values)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn write_routes_quoting_test() {
+    use bgp::{AsPath, AsPathSegment, AsPathSegmentType, Community, Prefix, RouteAttributes};
+    use route::RouteKind;
+
+    let route = Route {
+        timestamp: 1_272_931_207,
+        microsecond_timestamp: Some(42),
+        kind: RouteKind::Announce,
+        peer_address: "195.66.224.175".parse().unwrap(),
+        peer_asn: 8330,
+        prefix: Prefix { addr: "41.223.152.0".parse().unwrap(), len: 22 },
+        attributes: RouteAttributes {
+            as_path: Some(AsPath {
+                segments: vec![
+                    AsPathSegment { segment_type: AsPathSegmentType::AS_SEQUENCE, asns: vec![8330] },
+                    AsPathSegment { segment_type: AsPathSegmentType::AS_SET, asns: vec![3356, 174] },
+                ],
+            }),
+            communities: vec![Community(0x0d1c_0003)],
+            ..Default::default()
+        },
+    };
+
+    let fields = parse_fields("timestamp,type,prefix,as_path,origin_asn,communities").unwrap();
+    let mut out = Vec::new();
+    write_header(&mut out, ',', &fields).unwrap();
+    write_routes(&mut out, ',', &fields, None, std::slice::from_ref(&route)).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        concat!(
+            "timestamp,type,prefix,as_path,origin_asn,communities\n",
+            "1272931207.000042,A,41.223.152.0/22,\"8330 {3356,174}\",,3356:3\n"
+        )
+    );
+
+    let mut out = Vec::new();
+    write_routes(&mut out, '\t', &fields, None, std::slice::from_ref(&route)).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "1272931207.000042\tA\t41.223.152.0/22\t8330 {3356,174}\t\t3356:3\n"
+    );
+
+    let source = Provenance {
+        file: "updates.20100503.0000.gz".to_string(),
+        offset: 1234,
+        collector: None,
+    };
+    let fields = parse_fields("file,offset,prefix").unwrap();
+    let mut out = Vec::new();
+    write_routes(&mut out, ',', &fields, Some(&source), &[route]).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "updates.20100503.0000.gz,1234,41.223.152.0/22\n");
+    assert!(!default_fields().contains(&Field::File));
+
+    assert!(parse_fields("prefix,bogus").is_err());
+}
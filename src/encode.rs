@@ -0,0 +1,503 @@
+use bgp::{AsPath, AsnSize, BGPPathAttribute, MpReachNlri, MpUnreachNlri, Prefix};
+use bgp4mp::{
+    BGP4MPMessage, BGP4MPPeer, BGP4MPStateChange, BGP4MPSubtypes, BGPMessage, BGPMessageTypes,
+    BGPNotification, BGPOpen, BGPUpdate, BGP_HEADER_LENGTH,
+};
+use mrt::{
+    has_extended_timestamp, MRTHeader, MRTRecord, MRTTableDumpIPv4, MRTTableDumpIPv6,
+    MRTTableDumpV2IPv4Unicast, MRTTableDumpV2IPv6Unicast, MRTTableDumpV2PeerIndex, PeerEntry,
+    RibEntry, PEER_TYPE_AS4, PEER_TYPE_IPV6,
+};
+use num_traits::cast::FromPrimitive;
+use std::io::{self, Write};
+use std::net::IpAddr;
+
+// Serializes records back to MRT wire format, the inverse of mrt::parse_mrt_body
+// and friends. Length and count fields (attr_length, peer_count, entry_count,
+// the header length, ...) are recomputed from the data rather than copied,
+// so a record can be edited, e.g. RIB entries dropped, before writing it.
+//
+// The header's type and subtype pick the layout of the body: the AS number
+// width of BGP4MP records and whether an _ET microsecond timestamp follows
+// the common header. Records the parser does not decode (MRTRecord::
+// Unsupported, ROUTE-REFRESH and unknown BGP messages) keep no body and
+// cannot be encoded.
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn checked_u16(value: usize, what: &str) -> Result<u16, String> {
+    if value > usize::from(u16::MAX) {
+        return Err(format!("{} of {} does not fit in 16 bits", what, value));
+    }
+    Ok(value as u16)
+}
+
+fn put_address(out: &mut Vec<u8>, addr: IpAddr) {
+    match addr {
+        IpAddr::V4(a) => out.extend_from_slice(&a.octets()),
+        IpAddr::V6(a) => out.extend_from_slice(&a.octets()),
+    }
+}
+
+fn put_asn(out: &mut Vec<u8>, asn: u32, four_octet: bool) -> Result<(), String> {
+    if four_octet {
+        put_u32(out, asn);
+    } else if asn > u32::from(u16::MAX) {
+        return Err(format!("AS{} needs a 4-octet AS subtype", asn));
+    } else {
+        put_u16(out, asn as u16);
+    }
+    Ok(())
+}
+
+// The common header, with the length of a body of body_len octets.
+pub fn encode_header(out: &mut Vec<u8>, header: &MRTHeader, body_len: usize) {
+    let extended = has_extended_timestamp(header.mrt_type);
+    let length = body_len as u32 + if extended { 4 } else { 0 };
+    put_u32(out, header.timestamp);
+    put_u16(out, header.mrt_type);
+    put_u16(out, header.mrt_subtype);
+    put_u32(out, length);
+    if extended {
+        put_u32(out, header.microsecond_timestamp.unwrap_or(0));
+    }
+}
+
+// One attribute. The extended length flag (0x10) is set when the data does
+// not fit a one octet length, and otherwise left as it was parsed.
+pub fn encode_path_attribute(out: &mut Vec<u8>, attr: &BGPPathAttribute) -> Result<(), String> {
+    let len = checked_u16(attr.data.len(), "path attribute length")?;
+    let flags = if len > 0xff { attr.flags | 0x10 } else { attr.flags };
+    out.push(flags);
+    out.push(attr.code);
+    if flags & 0x10 == 0x10 {
+        put_u16(out, len);
+    } else {
+        out.push(len as u8);
+    }
+    out.extend_from_slice(&attr.data);
+    Ok(())
+}
+
+// Attributes in the form parse_bgp_path_attrs reads, without the length.
+pub fn encode_path_attrs(attrs: &[BGPPathAttribute]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    for attr in attrs {
+        encode_path_attribute(&mut out, attr)?;
+    }
+    Ok(out)
+}
+
+// An AS_PATH or AS4_PATH payload with ASNs of the given width. Segments
+// longer than 255 ASNs are split, as the count is a single octet.
+pub fn encode_as_path(path: &AsPath, asn_size: AsnSize) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    for segment in &path.segments {
+        for asns in segment.asns.chunks(255) {
+            out.push(segment.segment_type as u8);
+            out.push(asns.len() as u8);
+            for &asn in asns {
+                put_asn(&mut out, asn, asn_size == AsnSize::Four)?;
+            }
+        }
+    }
+    Ok(out)
+}
+
+// The abbreviated MP_REACH_NLRI payload of TABLE_DUMP_V2 RIB entries: only
+// the next hop length and next hop(s) (RFC 6396, section 4.3.4).
+pub fn encode_rib_mp_reach_nlri(mp: &MpReachNlri) -> Vec<u8> {
+    let mut next_hop = Vec::new();
+    put_address(&mut next_hop, mp.next_hop);
+    if let Some(link_local) = mp.link_local_next_hop {
+        next_hop.extend_from_slice(&link_local.octets());
+    }
+    let mut out = vec![next_hop.len() as u8];
+    out.extend_from_slice(&next_hop);
+    out
+}
+
+// A complete MP_REACH_NLRI payload as carried in BGP UPDATEs (RFC 4760, 3).
+pub fn encode_mp_reach_nlri(mp: &MpReachNlri) -> Vec<u8> {
+    let mut out = Vec::new();
+    put_u16(&mut out, mp.afi);
+    out.push(mp.safi);
+    out.extend_from_slice(&encode_rib_mp_reach_nlri(mp));
+    out.push(0);
+    for prefix in &mp.nlri {
+        encode_prefix(&mut out, prefix);
+    }
+    out
+}
+
+// An MP_UNREACH_NLRI payload (RFC 4760, 4).
+pub fn encode_mp_unreach_nlri(mp: &MpUnreachNlri) -> Vec<u8> {
+    let mut out = Vec::new();
+    put_u16(&mut out, mp.afi);
+    out.push(mp.safi);
+    for prefix in &mp.withdrawn_routes {
+        encode_prefix(&mut out, prefix);
+    }
+    out
+}
+
+// A prefix as in NLRI: the length in bits, then only the octets it covers.
+pub fn encode_prefix(out: &mut Vec<u8>, prefix: &Prefix) {
+    let octets = usize::from(prefix.len.div_ceil(8));
+    out.push(prefix.len);
+    match prefix.addr {
+        IpAddr::V4(a) => out.extend_from_slice(&a.octets()[..octets.min(4)]),
+        IpAddr::V6(a) => out.extend_from_slice(&a.octets()[..octets.min(16)]),
+    }
+}
+
+fn encode_table_dump_ipv4(out: &mut Vec<u8>, r: &MRTTableDumpIPv4) -> Result<(), String> {
+    put_u16(out, r.view_number);
+    put_u16(out, r.sequence_number);
+    out.extend_from_slice(&r.prefix.octets());
+    out.push(r.prefix_length);
+    out.push(r.status);
+    put_u32(out, r.originated_time);
+    out.extend_from_slice(&r.peer_address.octets());
+    put_u16(out, r.peer_asn);
+    put_u16(out, checked_u16(r.as_path.len(), "TABLE_DUMP attribute length")?);
+    out.extend_from_slice(&r.as_path);
+    Ok(())
+}
+
+fn encode_table_dump_ipv6(out: &mut Vec<u8>, r: &MRTTableDumpIPv6) -> Result<(), String> {
+    put_u16(out, r.view_number);
+    put_u16(out, r.sequence_number);
+    out.extend_from_slice(&r.prefix.octets());
+    out.push(r.prefix_length);
+    out.push(r.status);
+    put_u32(out, r.originated_time);
+    out.extend_from_slice(&r.peer_address.octets());
+    put_u16(out, r.peer_asn);
+    put_u16(out, checked_u16(r.as_path.len(), "TABLE_DUMP attribute length")?);
+    out.extend_from_slice(&r.as_path);
+    Ok(())
+}
+
+// The peer type bits follow the address and AS number actually written; a
+// 4-octet AS is kept when the entry was parsed with one.
+fn encode_peer_entry(out: &mut Vec<u8>, peer: &PeerEntry) {
+    let mut peer_type = peer.peer_type & PEER_TYPE_AS4;
+    if peer.peer_asn > u32::from(u16::MAX) {
+        peer_type |= PEER_TYPE_AS4;
+    }
+    if peer.peer_address.is_ipv6() {
+        peer_type |= PEER_TYPE_IPV6;
+    }
+    out.push(peer_type);
+    out.extend_from_slice(&peer.peer_bgp_id.octets());
+    put_address(out, peer.peer_address);
+    if peer_type & PEER_TYPE_AS4 != 0 {
+        put_u32(out, peer.peer_asn);
+    } else {
+        put_u16(out, peer.peer_asn as u16);
+    }
+}
+
+fn encode_peer_index(out: &mut Vec<u8>, r: &MRTTableDumpV2PeerIndex) -> Result<(), String> {
+    out.extend_from_slice(&r.collector_bgp_id.octets());
+    put_u16(out, checked_u16(r.view_name.len(), "view name length")?);
+    out.extend_from_slice(&r.view_name);
+    put_u16(out, checked_u16(r.peer_entries.len(), "peer count")?);
+    for peer in &r.peer_entries {
+        encode_peer_entry(out, peer);
+    }
+    Ok(())
+}
+
+fn encode_rib_entry(out: &mut Vec<u8>, entry: &RibEntry) -> Result<(), String> {
+    let attrs = encode_path_attrs(&entry.bgp_path_attrs)?;
+    put_u16(out, entry.peer_index);
+    put_u32(out, entry.originated_timestamp);
+    put_u16(out, checked_u16(attrs.len(), "RIB entry attribute length")?);
+    out.extend_from_slice(&attrs);
+    Ok(())
+}
+
+fn encode_rib(
+    out: &mut Vec<u8>,
+    sequence_number: u32,
+    prefix_length: u8,
+    prefix: &[u8],
+    entries: &[RibEntry],
+) -> Result<(), String> {
+    put_u32(out, sequence_number);
+    out.push(prefix_length);
+    out.extend_from_slice(prefix);
+    put_u16(out, checked_u16(entries.len(), "RIB entry count")?);
+    for entry in entries {
+        encode_rib_entry(out, entry)?;
+    }
+    Ok(())
+}
+
+fn encode_rib_ipv4(out: &mut Vec<u8>, r: &MRTTableDumpV2IPv4Unicast) -> Result<(), String> {
+    encode_rib(out, r.sequence_number, r.prefix_length, &r.prefix, &r.rib_entries)
+}
+
+fn encode_rib_ipv6(out: &mut Vec<u8>, r: &MRTTableDumpV2IPv6Unicast) -> Result<(), String> {
+    encode_rib(out, r.sequence_number, r.prefix_length, &r.prefix, &r.rib_entries)
+}
+
+fn encode_bgp4mp_peer(out: &mut Vec<u8>, peer: &BGP4MPPeer, as4: bool) -> Result<(), String> {
+    if peer.peer_address.is_ipv6() != peer.local_address.is_ipv6() {
+        return Err("BGP4MP peer and local addresses are of different families".to_string());
+    }
+    put_asn(out, peer.peer_asn, as4)?;
+    put_asn(out, peer.local_asn, as4)?;
+    put_u16(out, peer.interface_index);
+    // the AFI decides the address width when parsing, so follow the addresses
+    put_u16(out, if peer.peer_address.is_ipv6() { 2 } else { 1 });
+    put_address(out, peer.peer_address);
+    put_address(out, peer.local_address);
+    Ok(())
+}
+
+fn encode_state_change(out: &mut Vec<u8>, r: &BGP4MPStateChange, as4: bool) -> Result<(), String> {
+    encode_bgp4mp_peer(out, &r.peer, as4)?;
+    put_u16(out, r.old_state);
+    put_u16(out, r.new_state);
+    Ok(())
+}
+
+fn encode_open(out: &mut Vec<u8>, open: &BGPOpen) -> Result<(), String> {
+    if open.opt_params.len() > 0xff {
+        return Err("OPEN optional parameters longer than 255 octets".to_string());
+    }
+    out.push(open.version);
+    put_u16(out, open.my_asn);
+    put_u16(out, open.hold_time);
+    out.extend_from_slice(&open.bgp_id.octets());
+    out.push(open.opt_params.len() as u8);
+    out.extend_from_slice(&open.opt_params);
+    Ok(())
+}
+
+fn encode_ipv4_nlri(prefixes: &[Prefix]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    for prefix in prefixes {
+        if !prefix.addr.is_ipv4() {
+            return Err(format!("{} outside MP_REACH_NLRI/MP_UNREACH_NLRI", prefix));
+        }
+        encode_prefix(&mut out, prefix);
+    }
+    Ok(out)
+}
+
+fn encode_update(out: &mut Vec<u8>, update: &BGPUpdate) -> Result<(), String> {
+    let withdrawn = encode_ipv4_nlri(&update.withdrawn_routes)?;
+    let attrs = encode_path_attrs(&update.path_attributes)?;
+    put_u16(out, checked_u16(withdrawn.len(), "withdrawn routes length")?);
+    out.extend_from_slice(&withdrawn);
+    put_u16(out, checked_u16(attrs.len(), "path attribute length")?);
+    out.extend_from_slice(&attrs);
+    out.extend_from_slice(&encode_ipv4_nlri(&update.nlri)?);
+    Ok(())
+}
+
+fn encode_notification(out: &mut Vec<u8>, notification: &BGPNotification) {
+    out.push(notification.error_code);
+    out.push(notification.error_subcode);
+    out.extend_from_slice(&notification.data);
+}
+
+// A complete BGP message: marker, length, type and body (RFC 4271, 4.1).
+pub fn encode_bgp_message(out: &mut Vec<u8>, message: &BGPMessage) -> Result<(), String> {
+    let mut body = Vec::new();
+    let message_type = match *message {
+        BGPMessage::Open(ref open) => {
+            encode_open(&mut body, open)?;
+            BGPMessageTypes::OPEN
+        }
+        BGPMessage::Update(ref update) => {
+            encode_update(&mut body, update)?;
+            BGPMessageTypes::UPDATE
+        }
+        BGPMessage::Notification(ref notification) => {
+            encode_notification(&mut body, notification);
+            BGPMessageTypes::NOTIFICATION
+        }
+        BGPMessage::Keepalive => BGPMessageTypes::KEEPALIVE,
+        BGPMessage::RouteRefresh => {
+            return Err("ROUTE-REFRESH bodies are not kept and cannot be encoded".to_string())
+        }
+        BGPMessage::Unknown(code) => {
+            return Err(format!("BGP message type {} cannot be encoded", code))
+        }
+    };
+    let length = usize::from(BGP_HEADER_LENGTH) + body.len();
+    out.extend_from_slice(&[0xff; 16]);
+    put_u16(out, checked_u16(length, "BGP message length")?);
+    out.push(message_type as u8);
+    out.extend_from_slice(&body);
+    Ok(())
+}
+
+fn encode_bgp4mp_message(out: &mut Vec<u8>, msg: &BGP4MPMessage, as4: bool) -> Result<(), String> {
+    encode_bgp4mp_peer(out, &msg.peer, as4)?;
+    encode_bgp_message(out, &msg.message)
+}
+
+fn bgp4mp_as4(header: &MRTHeader) -> Result<bool, String> {
+    match BGP4MPSubtypes::from_u16(header.mrt_subtype) {
+        Some(BGP4MPSubtypes::BGP4MP_STATE_CHANGE)
+        | Some(BGP4MPSubtypes::BGP4MP_MESSAGE)
+        | Some(BGP4MPSubtypes::BGP4MP_MESSAGE_LOCAL) => Ok(false),
+        Some(BGP4MPSubtypes::BGP4MP_STATE_CHANGE_AS4)
+        | Some(BGP4MPSubtypes::BGP4MP_MESSAGE_AS4)
+        | Some(BGP4MPSubtypes::BGP4MP_MESSAGE_AS4_LOCAL) => Ok(true),
+        None => Err(format!("Unknown BGP4MP subtype {}", header.mrt_subtype)),
+    }
+}
+
+// The record body alone, laid out as the header's type and subtype require.
+pub fn encode_body(header: &MRTHeader, record: &MRTRecord) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    match *record {
+        MRTRecord::TableDumpIPv4(ref r) => encode_table_dump_ipv4(&mut out, r)?,
+        MRTRecord::TableDumpIPv6(ref r) => encode_table_dump_ipv6(&mut out, r)?,
+        MRTRecord::PeerIndexTable(ref r) => encode_peer_index(&mut out, r)?,
+        MRTRecord::RibIPv4Unicast(ref r) => encode_rib_ipv4(&mut out, r)?,
+        MRTRecord::RibIPv6Unicast(ref r) => encode_rib_ipv6(&mut out, r)?,
+        MRTRecord::BGP4MPStateChange(ref r) => encode_state_change(&mut out, r, bgp4mp_as4(header)?)?,
+        MRTRecord::BGP4MPMessage(ref r) => encode_bgp4mp_message(&mut out, r, bgp4mp_as4(header)?)?,
+        MRTRecord::Unsupported => return Err(format!("{}: record body was not decoded", header)),
+    }
+    Ok(out)
+}
+
+// A whole record, header included, ready to be appended to an MRT file.
+pub fn encode_record(header: &MRTHeader, record: &MRTRecord) -> Result<Vec<u8>, String> {
+    let body = encode_body(header, record)?;
+    let mut out = Vec::with_capacity(16 + body.len());
+    encode_header(&mut out, header, body.len());
+    out.extend_from_slice(&body);
+    Ok(out)
+}
+
+// Writes records one after another to an MRT stream.
+pub struct MRTWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> MRTWriter<W> {
+    pub fn new(out: W) -> MRTWriter<W> {
+        MRTWriter { out }
+    }
+
+    // Records that cannot be encoded give an InvalidInput error and nothing
+    // is written.
+    pub fn write_record(&mut self, header: &MRTHeader, record: &MRTRecord) -> io::Result<()> {
+        let bytes = encode_record(header, record)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        self.out.write_all(&bytes)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+#[test]
+fn encode_table_dump_round_trip_test() {
+    use bgp::parse_bgp_path_attrs;
+    use fixtures::table_dump;
+    use mrt::{parse_mrt_body, parse_mrt_table_dump_ipv4, split_mrt_record};
+    use std::borrow::Cow;
+
+    let buffer = table_dump();
+
+    let (_, header, body) = split_mrt_record(&buffer).unwrap();
+    let record = parse_mrt_body(&header, body).unwrap();
+    assert_eq!(encode_record(&header, &record).unwrap(), buffer);
+
+    let (_, dump) = parse_mrt_table_dump_ipv4(body).unwrap();
+    let attrs = dump.attributes().unwrap();
+    let encoded = encode_path_attrs(&attrs).unwrap();
+    assert_eq!(&encoded[..], &dump.as_path[..]);
+    assert_eq!(parse_bgp_path_attrs(&encoded, encoded.len() as u16).unwrap().1, attrs);
+
+    // long attributes switch to the extended length form
+    let long = BGPPathAttribute { flags: 0xc0, code: 8, len: 0, data: Cow::Owned(vec![0; 300]) };
+    let encoded = encode_path_attrs(std::slice::from_ref(&long)).unwrap();
+    assert_eq!(&encoded[..4], &[0xd0, 8, 0x01, 0x2c]);
+    let parsed = parse_bgp_path_attrs(&encoded, encoded.len() as u16).unwrap().1;
+    assert_eq!(parsed[0].data, long.data);
+}
+
+#[test]
+fn encode_table_dump_v2_and_bgp4mp_round_trip_test() {
+    use bgp::parse_bgp_path_attrs;
+    use fixtures::{encode_records, header};
+    use mrt::{parse_mrt_body, split_mrt_record, MRTReader};
+
+    let peer_index = concat!(
+        "c0000201", "0004", "74657374", "0002",
+        "00", "0a000001", "c0000202", "0cb9",
+        "03", "0a000002", "20010db8000000000000000000000001", "0001fbf0"
+    );
+    // RIB_IPV4_UNICAST 198.51.100.0/24 with one entry from peer 1
+    let rib = concat!(
+        "00000007", "18", "c63364", "0001",
+        "0001", "3b23e244", "0014",
+        "40010100",
+        "400206020100000cb9",
+        "400304c0000202"
+    );
+    // BGP4MP_ET MESSAGE_AS4 carrying an UPDATE
+    let update = concat!(
+        "0000fde8", "0000fde9", "0000", "0001", "c0000201", "c0000202",
+        "ffffffffffffffffffffffffffffffff", "0033", "02",
+        "0004", "18c63364",
+        "0014",
+        "40010100",
+        "40020602010000fde8",
+        "400304c0000201",
+        "18cb0071"
+    );
+
+    let file = encode_records(&[
+        (header(1_000_000_000, 13, 1), peer_index),
+        (header(1_000_000_000, 13, 2), rib),
+        (MRTHeader { microsecond_timestamp: Some(250_000), ..header(1_000_000_000, 17, 4) }, update),
+    ]);
+
+    let mut writer = MRTWriter::new(Vec::new());
+    for item in MRTReader::new(&file) {
+        let (header, record) = item.unwrap();
+        writer.write_record(&header, &record).unwrap();
+    }
+    assert_eq!(writer.into_inner(), file);
+
+    // dropping a RIB entry rewrites the entry count and record length
+    let (rest, _, _) = split_mrt_record(&file).unwrap();
+    let (_, header, body) = split_mrt_record(rest).unwrap();
+    let mut record = parse_mrt_body(&header, body).unwrap();
+    if let MRTRecord::RibIPv4Unicast(ref mut r) = record {
+        let attrs = encode_path_attrs(&r.rib_entries[0].bgp_path_attrs).unwrap();
+        assert_eq!(attrs, &hex::decode(rib).unwrap()[18..]);
+        assert_eq!(parse_bgp_path_attrs(&attrs, 20).unwrap().1, r.rib_entries[0].bgp_path_attrs);
+        r.rib_entries.clear();
+    }
+    let encoded = encode_record(&header, &record).unwrap();
+    let (_, header, body) = split_mrt_record(&encoded).unwrap();
+    match parse_mrt_body(&header, body).unwrap() {
+        MRTRecord::RibIPv4Unicast(r) => assert_eq!(r.entry_count, 0),
+        _ => panic!("expected RIB_IPV4_UNICAST"),
+    }
+}
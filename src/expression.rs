@@ -0,0 +1,470 @@
+use bgp::{Afi, Prefix};
+use filter::{filter_record, parse_record_type, AsPathRegex, CommunityPattern};
+use mrt::{MRTHeader, MRTRecord, PeerEntry};
+use route::Route;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+// A boolean filter over routes, parsed once from an expression such as
+//
+//   peer_asn == 3356 and prefix <<= 10.0.0.0/8 and not community ~ 65535:666
+//
+// Tests are combined with "and", "or", "not" and parentheses; "and" binds
+// tighter than "or". The tests are
+//
+//   prefix     ==, !=, << (more specific), <<= (or equal), >>, >>=  PREFIX
+//   afi        ==, !=  ipv4 | ipv6
+//   peer_ip    ==, !=  ADDRESS
+//   peer_asn   ==, !=, <, <=, >, >=  ASN
+//   origin_asn ==, !=, <, <=, >, >=  ASN
+//   timestamp  ==, !=, <, <=, >, >=  seconds since the epoch, e.g. 1700000000.5
+//   type       ==, !=  TYPE[:SUBTYPE] as for filter::parse_record_type
+//   as_path    ~, !~   Cisco-style regex, as filter::AsPathRegex
+//   community  ~, !~   pattern, as filter::CommunityPattern
+//
+// Values containing spaces, parentheses or any of "=!<>~" can be quoted
+// with '' or "".
+// Records without routes (state changes, OPEN, ...) are tested with only
+// the header and the BGP4MP peer, "afi" going by the session's address
+// family; the other route tests are false for them, "!~" included, while
+// "not community ~ ..." is true.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    // prefix containment, the route's prefix on the left
+    MoreSpecific,
+    MoreSpecificOrEqual,
+    LessSpecific,
+    LessSpecificOrEqual,
+    Match,
+    NotMatch,
+}
+
+const OPERATORS: &[(&str, Op)] = &[
+    ("==", Op::Eq),
+    ("!=", Op::Ne),
+    ("<", Op::Lt),
+    ("<=", Op::Le),
+    (">", Op::Gt),
+    (">=", Op::Ge),
+    ("<<", Op::MoreSpecific),
+    ("<<=", Op::MoreSpecificOrEqual),
+    (">>", Op::LessSpecific),
+    (">>=", Op::LessSpecificOrEqual),
+    ("~", Op::Match),
+    ("!~", Op::NotMatch),
+];
+
+const EQUALITY: &[Op] = &[Op::Eq, Op::Ne];
+const ORDERING: &[Op] = &[Op::Eq, Op::Ne, Op::Lt, Op::Le, Op::Gt, Op::Ge];
+const CONTAINMENT: &[Op] = &[
+    Op::Eq,
+    Op::Ne,
+    Op::MoreSpecific,
+    Op::MoreSpecificOrEqual,
+    Op::LessSpecific,
+    Op::LessSpecificOrEqual,
+];
+const MATCHING: &[Op] = &[Op::Match, Op::NotMatch];
+
+#[derive(Debug, Clone)]
+enum Test {
+    Prefix(Op, Prefix),
+    Afi(Op, Afi),
+    PeerIp(Op, IpAddr),
+    PeerAsn(Op, u32),
+    OriginAsn(Op, u32),
+    // microseconds
+    Timestamp(Op, u64),
+    Type(Op, (u16, Option<u16>)),
+    AsPath(Op, AsPathRegex),
+    Community(Op, CommunityPattern),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Test(Test),
+}
+
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expr: Expr,
+}
+
+// What a test is run against: a route, or a record without routes.
+struct Subject<'r> {
+    header: &'r MRTHeader,
+    peer: Option<(IpAddr, u32)>,
+    // the route's, or else the BGP4MP session's
+    afi: Option<Afi>,
+    route: Option<&'r Route>,
+}
+
+fn compare<T: PartialOrd>(op: Op, left: T, right: T) -> bool {
+    match op {
+        Op::Eq => left == right,
+        Op::Ne => left != right,
+        Op::Lt => left < right,
+        Op::Le => left <= right,
+        Op::Gt => left > right,
+        Op::Ge => left >= right,
+        _ => false,
+    }
+}
+
+fn compare_prefix(op: Op, prefix: &Prefix, value: &Prefix) -> bool {
+    match op {
+        Op::MoreSpecific => value.contains(prefix) && prefix != value,
+        Op::MoreSpecificOrEqual => value.contains(prefix),
+        Op::LessSpecific => prefix.contains(value) && prefix != value,
+        Op::LessSpecificOrEqual => prefix.contains(value),
+        _ => compare(op, prefix, value),
+    }
+}
+
+impl Test {
+    fn matches(&self, subject: &Subject) -> bool {
+        let header = subject.header;
+        let route = subject.route;
+        match *self {
+            Test::Prefix(op, ref value) => route.is_some_and(|r| compare_prefix(op, &r.prefix, value)),
+            Test::Afi(op, afi) => subject.afi.is_some_and(|a| (a == afi) == (op == Op::Eq)),
+            Test::PeerIp(op, address) => subject.peer.is_some_and(|(a, _)| compare(op, a, address)),
+            Test::PeerAsn(op, asn) => subject.peer.is_some_and(|(_, a)| compare(op, a, asn)),
+            Test::OriginAsn(op, asn) => {
+                route.and_then(|r| r.origin_asn()).is_some_and(|a| compare(op, a, asn))
+            }
+            Test::Timestamp(op, time) => compare(op, header.time_us(), time),
+            Test::Type(op, (mrt_type, subtype)) => {
+                let matches = header.mrt_type == mrt_type
+                    && subtype.is_none_or(|s| s == header.mrt_subtype);
+                matches == (op == Op::Eq)
+            }
+            Test::AsPath(op, ref regex) => route.is_some_and(|r| {
+                let matches = r.attributes.as_path.as_ref().is_some_and(|path| regex.is_match(path));
+                matches == (op == Op::Match)
+            }),
+            Test::Community(op, ref pattern) => {
+                route.is_some_and(|r| pattern.matches(&r.attributes) == (op == Op::Match))
+            }
+        }
+    }
+}
+
+impl Expr {
+    fn matches(&self, subject: &Subject) -> bool {
+        match *self {
+            Expr::And(ref a, ref b) => a.matches(subject) && b.matches(subject),
+            Expr::Or(ref a, ref b) => a.matches(subject) || b.matches(subject),
+            Expr::Not(ref e) => !e.matches(subject),
+            Expr::Test(ref test) => test.matches(subject),
+        }
+    }
+}
+
+impl Filter {
+    pub fn matches(&self, header: &MRTHeader, route: &Route) -> bool {
+        let peer = Some((route.peer_address, route.peer_asn));
+        let afi = Some(route.prefix.afi());
+        self.expr.matches(&Subject { header, peer, afi, route: Some(route) })
+    }
+
+    // The record cut down to the matching routes as filter::filter_record,
+    // so an UPDATE keeps only the prefixes the expression holds for, or
+    // whole if it has no routes and its header and peer match.
+    pub fn apply<'a>(
+        &self,
+        header: &MRTHeader,
+        record: MRTRecord<'a>,
+        peers: &[PeerEntry],
+    ) -> Result<Option<MRTRecord<'a>>, String> {
+        if record.is_routeless() {
+            let session = record.bgp4mp_peer();
+            let peer = session.map(|p| (p.peer_address, p.peer_asn));
+            let afi = session.and_then(|p| p.address_family());
+            let keep = self.expr.matches(&Subject { header, peer, afi, route: None });
+            return Ok(if keep { Some(record) } else { None });
+        }
+        filter_record(header, record, peers, false, &mut |r: &Route| self.matches(header, r))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Op(Op),
+    Word(String),
+    // quoted, so never a keyword
+    Quoted(String),
+}
+
+fn is_operator_char(c: char) -> bool {
+    "=!<>~".contains(c)
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' || c == ')' {
+            chars.next();
+            tokens.push(if c == '(' { Token::Open } else { Token::Close });
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some(q) if q == c => break,
+                    Some(q) => value.push(q),
+                    None => return Err(format!("Unterminated quote in filter '{}'", s)),
+                }
+            }
+            tokens.push(Token::Quoted(value));
+        } else if is_operator_char(c) {
+            let mut op = String::new();
+            while let Some(&c) = chars.peek() {
+                if !is_operator_char(c) {
+                    break;
+                }
+                op.push(c);
+                chars.next();
+            }
+            match OPERATORS.iter().find(|&&(name, _)| name == op) {
+                Some(&(_, op)) => tokens.push(Token::Op(op)),
+                None => return Err(format!("Unknown operator '{}' in filter", op)),
+            }
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '(' || c == ')' || is_operator_char(c) {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.position += 1;
+            let expr = self.or()?;
+            if self.next() != Some(Token::Close) {
+                return Err("Missing ')' in filter".to_string());
+            }
+            return Ok(expr);
+        }
+        self.test().map(Expr::Test)
+    }
+
+    fn test(&mut self) -> Result<Test, String> {
+        let field = match self.next() {
+            Some(Token::Word(w)) => w,
+            Some(t) => return Err(format!("Expected a field in filter, found {:?}", t)),
+            None => return Err("Filter ends early".to_string()),
+        };
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            _ => return Err(format!("Expected an operator after '{}' in filter", field)),
+        };
+        let value = match self.next() {
+            Some(Token::Word(w)) | Some(Token::Quoted(w)) => w,
+            _ => return Err(format!("Expected a value after '{}' in filter", field)),
+        };
+        let (ops, test) = match field.as_str() {
+            "prefix" => (CONTAINMENT, Test::Prefix(op, value.parse()?)),
+            "afi" => (EQUALITY, Test::Afi(op, parse_afi(&value)?)),
+            "peer_ip" => (EQUALITY, Test::PeerIp(op, parse_value(&value, "address")?)),
+            "peer_asn" => (ORDERING, Test::PeerAsn(op, parse_value(&value, "ASN")?)),
+            "origin_asn" => (ORDERING, Test::OriginAsn(op, parse_value(&value, "ASN")?)),
+            "timestamp" => (ORDERING, Test::Timestamp(op, parse_time(&value)?)),
+            "type" => (EQUALITY, Test::Type(op, parse_record_type(&value)?)),
+            "as_path" => (MATCHING, Test::AsPath(op, AsPathRegex::new(&value)?)),
+            "community" => (MATCHING, Test::Community(op, value.parse()?)),
+            _ => return Err(format!("Unknown filter field '{}'", field)),
+        };
+        if !ops.contains(&op) {
+            return Err(format!("Operator {:?} cannot be used with '{}'", op, field));
+        }
+        Ok(test)
+    }
+}
+
+fn parse_value<T: FromStr>(value: &str, what: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Bad {} '{}' in filter", what, value))
+}
+
+pub fn parse_afi(name: &str) -> Result<Afi, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "ipv4" | "4" => Ok(Afi::IPv4),
+        "ipv6" | "6" => Ok(Afi::IPv6),
+        _ => Err(format!("Unknown address family '{}'", name)),
+    }
+}
+
+// Seconds since the epoch, with up to six decimal places, in microseconds.
+pub fn parse_time(time: &str) -> Result<u64, String> {
+    let bad = || format!("Bad time '{}'", time);
+    let (seconds, fraction) = match time.find('.') {
+        Some(i) => (&time[..i], &time[i + 1..]),
+        None => (time, ""),
+    };
+    if fraction.len() > 6 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(bad());
+    }
+    let seconds: u64 = seconds.parse().map_err(|_| bad())?;
+    let micros = format!("{:0<6}", fraction).parse::<u64>().map_err(|_| bad())?;
+    seconds.checked_mul(1_000_000).and_then(|us| us.checked_add(micros)).ok_or_else(bad)
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Filter, String> {
+        let mut parser = Parser { tokens: tokenize(s)?, position: 0 };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("Unexpected {:?} in filter", token));
+        }
+        Ok(Filter { expr })
+    }
+}
+
+#[test]
+fn filter_test() {
+    use bgp::{AsPath, AsPathSegment, AsPathSegmentType, Community, RouteAttributes};
+    use mrt::MRTReader;
+    use route::{self, RouteKind};
+
+    let header = MRTHeader {
+        timestamp: 1_700_000_000,
+        mrt_type: 16,
+        mrt_subtype: 4,
+        length: 0,
+        microsecond_timestamp: None,
+    };
+    let route = Route {
+        timestamp: header.timestamp,
+        microsecond_timestamp: None,
+        kind: RouteKind::Announce,
+        peer_address: "192.0.2.1".parse().unwrap(),
+        peer_asn: 3356,
+        prefix: "10.1.0.0/16".parse().unwrap(),
+        attributes: RouteAttributes {
+            as_path: Some(AsPath {
+                segments: vec![AsPathSegment {
+                    segment_type: AsPathSegmentType::AS_SEQUENCE,
+                    asns: vec![3356, 174, 13335],
+                }],
+            }),
+            communities: vec![Community(0xffff_029a)],
+            ..Default::default()
+        },
+    };
+    let matches = |expr: &str| expr.parse::<Filter>().unwrap().matches(&header, &route);
+    assert!(matches("peer_asn == 3356 and prefix <<= 10.0.0.0/8"));
+    assert!(!matches("peer_asn == 3356 and prefix <<= 10.0.0.0/8 and not community ~ 65535:666"));
+    assert!(matches("prefix << 10.0.0.0/8 and prefix >>= 10.1.2.0/24 and prefix != 10.1.0.0/24"));
+    assert!(!matches("prefix << 10.1.0.0/16"));
+    assert!(matches("origin_asn == 13335 or peer_asn < 10"));
+    assert!(matches("not (origin_asn == 1 or afi == ipv6) and type == bgp4mp:message_as4"));
+    assert!(matches("as_path ~ '^3356 .* 13335$' and as_path !~ _701_"));
+    assert!(matches("timestamp >= 1700000000 and timestamp < 1700000000.5 and peer_ip == 192.0.2.1"));
+
+    // route tests, negated or not, are false for records without routes
+    let routeless = |expr: &str| {
+        let filter = expr.parse::<Filter>().unwrap();
+        let peer = Some((route.peer_address, 3356));
+        filter.expr.matches(&Subject { header: &header, peer, afi: Some(Afi::IPv6), route: None })
+    };
+    assert!(routeless("peer_asn == 3356"));
+    assert!(routeless("afi == ipv6"));
+    assert!(!routeless("afi != ipv6"));
+    assert!(!routeless("community !~ 65535:666"));
+    assert!(!routeless("as_path !~ _701_"));
+    assert!(routeless("not community ~ 65535:666"));
+
+    // an UPDATE withdrawing 192.0.2.0/25 and announcing 10.0.0.0/8 is cut
+    // down to the routes the expression holds for
+    let buffer = hex::decode(concat!(
+        "3b2406ce", "0010", "0001", "00000040",
+        "fde8", "fde9", "0000", "0001", "c0000201", "c0000202",
+        "ffffffffffffffffffffffffffffffff", "0030", "02",
+        "0005", "19c0000200", "0012", "40010100", "40020402010001", "4003040a000001", "080a"
+    ))
+    .unwrap();
+    let (header, record) = MRTReader::new(&buffer).next().unwrap().unwrap();
+    let filter: Filter = "prefix <<= 10.0.0.0/8".parse().unwrap();
+    let record = filter.apply(&header, record, &[]).unwrap().unwrap();
+    let routes = route::routes(&header, &record, &[]).unwrap();
+    assert_eq!(routes.len(), 1);
+    assert_eq!((routes[0].kind, routes[0].prefix.to_string()), (RouteKind::Announce, "10.0.0.0/8".to_string()));
+
+    assert!("prefix ~ 10.0.0.0/8".parse::<Filter>().is_err());
+    assert!("peer_asn == 3356 and".parse::<Filter>().is_err());
+    assert!("(peer_asn == 1".parse::<Filter>().is_err());
+    assert!("next_hop == 192.0.2.1".parse::<Filter>().is_err());
+    assert!("peer_asn =< 1".parse::<Filter>().is_err());
+    assert_eq!(parse_time("1700000000.25"), Ok(1_700_000_000_250_000));
+    assert!(parse_time("18446744073709551615").is_err());
+}
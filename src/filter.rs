@@ -0,0 +1,882 @@
+use bgp::{
+    AsPath, Afi, AsnSize, BGPPathAttrTypes, PathAttributeValue, Prefix, RouteAttributes,
+    COMMUNITY_NO_ADVERTISE, COMMUNITY_NO_EXPORT, COMMUNITY_NO_EXPORT_SUBCONFED,
+};
+use bgp4mp::{BGPMessage, BGPUpdate};
+use encode::{encode_mp_reach_nlri, encode_mp_unreach_nlri, MRTWriter};
+use expression::Filter;
+use mrt::{type_names, MRTHeader, MRTRecord, PeerEntry, RibEntry};
+use regex::Regex;
+use route::{self, Route, RouteKind};
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+// Cutting a dump down to the routes matching a predicate, e.g. the prefixes
+// of one customer, and writing the result as a smaller MRT file.
+
+// What is left of a record once its routes are run through keep, or None if
+// nothing is. TABLE_DUMP_V2 RIB records lose the entries that do not match,
+// and BGP4MP UPDATEs the prefixes that do not, see prune_update.
+// PEER_INDEX_TABLE records are always kept; other
+// records without routes (state changes, OPEN, KEEPALIVE, ...) only when
+// keep_routeless is set.
+pub fn filter_record<'a, F>(
+    header: &MRTHeader,
+    mut record: MRTRecord<'a>,
+    peers: &[PeerEntry],
+    keep_routeless: bool,
+    keep: &mut F,
+) -> Result<Option<MRTRecord<'a>>, String>
+where
+    F: FnMut(&Route) -> bool,
+{
+    if let MRTRecord::PeerIndexTable(_) = record {
+        return Ok(Some(record));
+    }
+    let routes = route::routes(header, &record, peers)?;
+    if routes.is_empty() {
+        return Ok(if keep_routeless { Some(record) } else { None });
+    }
+    if let Some((_, entries)) = rib_parts(&mut record) {
+        // one route per RIB entry, in order
+        let mut matches = routes.iter().map(&mut *keep);
+        entries.retain(|_| matches.next().unwrap_or(false));
+        if entries.is_empty() {
+            return Ok(None);
+        }
+        set_entry_count(&mut record);
+        return Ok(Some(record));
+    }
+    let kept: Vec<bool> = routes.iter().map(&mut *keep).collect();
+    if !kept.contains(&true) {
+        return Ok(None);
+    }
+    if let MRTRecord::BGP4MPMessage(ref mut msg) = record {
+        if let BGPMessage::Update(ref mut update) = msg.message {
+            if kept.contains(&false) {
+                let (mut withdrawn, mut announced) = (Vec::new(), Vec::new());
+                for (route, _) in routes.iter().zip(&kept).filter(|&(_, &k)| k) {
+                    match route.kind {
+                        RouteKind::Withdraw => withdrawn.push(route.prefix),
+                        _ => announced.push(route.prefix),
+                    }
+                }
+                prune_update(update, msg.asn_size, &withdrawn, &announced);
+            }
+        }
+    }
+    Ok(Some(record))
+}
+
+// Cut an UPDATE down to the withdrawn and announced prefixes given, both in
+// the message and in MP_UNREACH_NLRI/MP_REACH_NLRI. With no announcement
+// left the other attributes go too, as they only describe announced routes.
+fn prune_update(
+    update: &mut BGPUpdate,
+    asn_size: AsnSize,
+    withdrawn: &[Prefix],
+    announced: &[Prefix],
+) {
+    update.withdrawn_routes.retain(|p| withdrawn.contains(p));
+    update.nlri.retain(|p| announced.contains(p));
+    let mut announces = !update.nlri.is_empty();
+    update.path_attributes.retain_mut(|attr| match attr.value(asn_size) {
+        Ok(PathAttributeValue::MpReachNlri(mut mp)) => {
+            mp.nlri.retain(|p| announced.contains(p));
+            attr.data = Cow::Owned(encode_mp_reach_nlri(&mp));
+            announces |= !mp.nlri.is_empty();
+            !mp.nlri.is_empty()
+        }
+        Ok(PathAttributeValue::MpUnreachNlri(mut mp)) => {
+            mp.withdrawn_routes.retain(|p| withdrawn.contains(p));
+            attr.data = Cow::Owned(encode_mp_unreach_nlri(&mp));
+            !mp.withdrawn_routes.is_empty()
+        }
+        _ => true,
+    });
+    if !announces {
+        let mp_unreach = BGPPathAttrTypes::BGP_PATH_ATTR_MP_UNREACH_NLRI as u8;
+        update.path_attributes.retain(|attr| attr.code == mp_unreach);
+    }
+}
+
+// A record type for mrt::HeaderFilter: a type, optionally with a subtype, by
+// name or number, e.g. "BGP4MP", "bgp4mp_et:state_change",
+// "TABLE_DUMP_V2:RIB_IPV6_UNICAST" or "13:4". A subtype name can leave out
+// the "BGP4MP_" its type starts with.
+pub fn parse_record_type(spec: &str) -> Result<(u16, Option<u16>), String> {
+    let (type_spec, subtype_spec) = match spec.find(':') {
+        Some(i) => (&spec[..i], Some(&spec[i + 1..])),
+        None => (spec, None),
+    };
+    let named = |name: Option<String>, wanted: &str| {
+        name.is_some_and(|name| {
+            name.eq_ignore_ascii_case(wanted)
+                || (name.starts_with("BGP4MP_") && name[7..].eq_ignore_ascii_case(wanted))
+        })
+    };
+    let mrt_type = type_spec
+        .parse()
+        .ok()
+        .or_else(|| (0..=255).find(|&t| named(type_names(t, 0).0, type_spec)))
+        .ok_or_else(|| format!("Unknown MRT type '{}'", type_spec))?;
+    let subtype = match subtype_spec {
+        Some(s) => Some(
+            s.parse()
+                .ok()
+                .or_else(|| (0..=255).find(|&st| named(type_names(mrt_type, st).1, s)))
+                .ok_or_else(|| format!("Unknown MRT subtype '{}'", s))?,
+        ),
+        None => None,
+    };
+    Ok((mrt_type, subtype))
+}
+
+// How a route's prefix is compared with those a PrefixFilter is given.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PrefixMatch {
+    // the prefix itself
+    Exact,
+    // the prefix or any it covers
+    #[default]
+    MoreSpecific,
+    // the prefix or any covering it
+    LessSpecific,
+}
+
+impl PrefixMatch {
+    // "exact", "more" or "less", as on the command line.
+    pub fn from_name(name: &str) -> Option<PrefixMatch> {
+        match name {
+            "exact" => Some(PrefixMatch::Exact),
+            "more" => Some(PrefixMatch::MoreSpecific),
+            "less" => Some(PrefixMatch::LessSpecific),
+            _ => None,
+        }
+    }
+}
+
+// Keeps the routes for any of a list of prefixes. Works on the prefixes of
+// TABLE_DUMP and TABLE_DUMP_V2 records and on BGP4MP announcements and
+// withdrawals alike, since it goes by route::Route.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PrefixFilter {
+    pub prefixes: Vec<Prefix>,
+    pub mode: PrefixMatch,
+}
+
+impl PrefixFilter {
+    pub fn new(prefixes: Vec<Prefix>, mode: PrefixMatch) -> PrefixFilter {
+        PrefixFilter { prefixes, mode }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+
+    pub fn matches(&self, prefix: &Prefix) -> bool {
+        self.prefixes.iter().any(|p| match self.mode {
+            PrefixMatch::Exact => p == prefix,
+            PrefixMatch::MoreSpecific => p.contains(prefix),
+            PrefixMatch::LessSpecific => prefix.contains(p),
+        })
+    }
+
+    // The record cut down to the routes for the prefixes, as filter_record,
+    // with PEER_INDEX_TABLEs kept and other records without routes dropped.
+    pub fn apply<'a>(
+        &self,
+        header: &MRTHeader,
+        record: MRTRecord<'a>,
+        peers: &[PeerEntry],
+    ) -> Result<Option<MRTRecord<'a>>, String> {
+        filter_record(header, record, peers, false, &mut |r: &Route| self.matches(&r.prefix))
+    }
+}
+
+// Route criteria such as those of the command line; a route has to meet
+// all of those given. Empty lists and None stand for no criterion, so
+// RouteFilter::default() keeps everything.
+#[derive(Debug, Clone, Default)]
+pub struct RouteFilter {
+    pub prefixes: PrefixFilter,
+    // of the prefix, or of the session for BGP4MP records without routes
+    pub afi: Option<Afi>,
+    // any of them
+    pub peer_addresses: Vec<IpAddr>,
+    pub peer_asns: Vec<u32>,
+    pub origin_asns: Vec<u32>,
+    // in the path anywhere
+    pub path_asns: Vec<u32>,
+    pub as_paths: Vec<AsPathRegex>,
+    // any of them
+    pub communities: Vec<CommunityPattern>,
+    // an expression::Filter, on top of the rest
+    pub expression: Option<Filter>,
+}
+
+impl RouteFilter {
+    pub fn is_empty(&self) -> bool {
+        self.peer_addresses.is_empty()
+            && self.peer_asns.is_empty()
+            && self.afi.is_none()
+            && self.is_peers_only()
+            && self.expression.is_none()
+    }
+
+    // Whether the route criteria, if any, are all on the session: its peer
+    // and address family. The expression goes its own way.
+    fn is_peers_only(&self) -> bool {
+        self.prefixes.is_empty()
+            && self.origin_asns.is_empty()
+            && self.path_asns.is_empty()
+            && self.as_paths.is_empty()
+            && self.communities.is_empty()
+    }
+
+    pub fn matches(&self, route: &Route) -> bool {
+        (self.prefixes.is_empty() || self.prefixes.matches(&route.prefix))
+            && self.afi.is_none_or(|afi| afi == route.prefix.afi())
+            && self.matches_peer(route.peer_address, route.peer_asn)
+            && self.matches_as_path(route)
+            && (self.communities.is_empty()
+                || self.communities.iter().any(|c| c.matches(&route.attributes)))
+    }
+
+    pub fn matches_peer(&self, address: IpAddr, asn: u32) -> bool {
+        (self.peer_addresses.is_empty() || self.peer_addresses.contains(&address))
+            && (self.peer_asns.is_empty() || self.peer_asns.contains(&asn))
+    }
+
+    // Withdrawals carry no path, so they only pass when no path criteria
+    // are given.
+    fn matches_as_path(&self, route: &Route) -> bool {
+        if self.origin_asns.is_empty() && self.path_asns.is_empty() && self.as_paths.is_empty() {
+            return true;
+        }
+        let path = match route.attributes.as_path {
+            Some(ref path) => path,
+            None => return false,
+        };
+        (self.origin_asns.is_empty()
+            || path.origin_asn().is_some_and(|asn| self.origin_asns.contains(&asn)))
+            && (self.path_asns.is_empty() || self.path_asns.iter().any(|&asn| path.contains_asn(asn)))
+            && self.as_paths.iter().all(|regex| regex.is_match(path))
+    }
+
+    // The record cut down to the matching routes, None if nothing matches.
+    // BGP4MP records go by their session first: those of other peers are
+    // dropped whole. Records without routes (state changes, OPEN, ...) have
+    // no prefix to take an address family from, so afi is checked against
+    // the session's (BGP4MPPeer::address_family) instead; they are kept when
+    // there are no other route criteria, so that a session's state changes
+    // come along with its routes.
+    pub fn apply<'a>(
+        &self,
+        header: &MRTHeader,
+        record: MRTRecord<'a>,
+        peers: &[PeerEntry],
+    ) -> Result<Option<MRTRecord<'a>>, String> {
+        if self.is_empty() {
+            return Ok(Some(record));
+        }
+        if let Some(peer) = record.bgp4mp_peer() {
+            if !self.matches_peer(peer.peer_address, peer.peer_asn) {
+                return Ok(None);
+            }
+        }
+        let record = match record.bgp4mp_peer() {
+            Some(peer) if record.is_routeless() && self.is_peers_only() => {
+                if self.afi.is_some_and(|afi| peer.address_family() != Some(afi)) {
+                    return Ok(None);
+                }
+                record
+            }
+            Some(_) if self.is_peers_only() && self.afi.is_none() => record,
+            _ => match filter_record(header, record, peers, false, &mut |r: &Route| self.matches(r))? {
+                Some(record) => record,
+                None => return Ok(None),
+            },
+        };
+        match self.expression {
+            Some(ref expression) => expression.apply(header, record, peers),
+            None => Ok(Some(record)),
+        }
+    }
+}
+
+// A Cisco-style AS path regular expression, matched against the path as
+// printed ("174 3356 {64512,64513}"). '_' stands for the start or end of the
+// path or any delimiter between ASNs, so "_3356_" finds 3356 anywhere in the
+// path but not 33561.
+#[derive(Debug, Clone)]
+pub struct AsPathRegex {
+    regex: Regex,
+}
+
+impl AsPathRegex {
+    pub fn new(pattern: &str) -> Result<AsPathRegex, String> {
+        let translated = pattern.replace('_', r"(?:^|$|[ ,{}()\[\]])");
+        let regex = Regex::new(&translated)
+            .map_err(|e| format!("Bad AS path regex '{}': {}", pattern, e))?;
+        Ok(AsPathRegex { regex })
+    }
+
+    pub fn is_match(&self, as_path: &AsPath) -> bool {
+        self.regex.is_match(&as_path.to_string())
+    }
+}
+
+// A community to look for, with "*" for any value of a part: "65535:666",
+// "*:666" and "no-export" (standard), "64496:1:*" (large), "rt:65000:*" and
+// "soo:192.0.2.1:*" (extended, as printed by ExtendedCommunity).
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommunityPattern {
+    Standard(Option<u16>, Option<u16>),
+    Large(Option<u32>, Option<u32>, Option<u32>),
+    Extended(String, Option<String>, Option<u32>),
+}
+
+fn pattern_part<T: FromStr>(part: &str, pattern: &str) -> Result<Option<T>, String> {
+    if part == "*" {
+        return Ok(None);
+    }
+    part.parse().map(Some).map_err(|_| format!("Bad community '{}'", pattern))
+}
+
+impl FromStr for CommunityPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<CommunityPattern, String> {
+        let well_known = match s {
+            "no-export" => Some(COMMUNITY_NO_EXPORT),
+            "no-advertise" => Some(COMMUNITY_NO_ADVERTISE),
+            "local-AS" => Some(COMMUNITY_NO_EXPORT_SUBCONFED),
+            _ => None,
+        };
+        if let Some(c) = well_known {
+            return Ok(CommunityPattern::Standard(Some((c >> 16) as u16), Some(c as u16)));
+        }
+        let parts: Vec<&str> = s.split(':').collect();
+        match parts[..] {
+            [label @ "rt", global, local] | [label @ "soo", global, local] => {
+                let global = if global == "*" { None } else { Some(global.to_string()) };
+                Ok(CommunityPattern::Extended(label.to_string(), global, pattern_part(local, s)?))
+            }
+            [asn, value] => Ok(CommunityPattern::Standard(pattern_part(asn, s)?, pattern_part(value, s)?)),
+            [global, local_1, local_2] => Ok(CommunityPattern::Large(
+                pattern_part(global, s)?,
+                pattern_part(local_1, s)?,
+                pattern_part(local_2, s)?,
+            )),
+            _ => Err(format!("Bad community '{}'", s)),
+        }
+    }
+}
+
+fn part_matches<T: PartialEq>(pattern: &Option<T>, value: T) -> bool {
+    pattern.as_ref().is_none_or(|p| *p == value)
+}
+
+impl CommunityPattern {
+    // Whether any of the route's communities of the pattern's kind match.
+    pub fn matches(&self, attributes: &RouteAttributes) -> bool {
+        match *self {
+            CommunityPattern::Standard(ref asn, ref value) => attributes
+                .communities
+                .iter()
+                .any(|c| part_matches(asn, c.asn()) && part_matches(value, c.value())),
+            CommunityPattern::Large(ref global, ref local_1, ref local_2) => {
+                attributes.large_communities.iter().any(|c| {
+                    part_matches(global, c.global_admin)
+                        && part_matches(local_1, c.local_data_1)
+                        && part_matches(local_2, c.local_data_2)
+                })
+            }
+            CommunityPattern::Extended(ref label, ref global, ref local) => {
+                attributes.extended_communities.iter().filter_map(|c| c.parts()).any(|(l, g, n)| {
+                    l == label && part_matches(global, g) && part_matches(local, n)
+                })
+            }
+        }
+    }
+}
+
+fn rib_parts<'r, 'a>(
+    record: &'r mut MRTRecord<'a>,
+) -> Option<(&'r mut u32, &'r mut Vec<RibEntry<'a>>)> {
+    match *record {
+        MRTRecord::RibIPv4Unicast(ref mut r) => Some((&mut r.sequence_number, &mut r.rib_entries)),
+        MRTRecord::RibIPv6Unicast(ref mut r) => Some((&mut r.sequence_number, &mut r.rib_entries)),
+        _ => None,
+    }
+}
+
+fn set_entry_count(record: &mut MRTRecord) {
+    match *record {
+        MRTRecord::RibIPv4Unicast(ref mut r) => r.entry_count = r.rib_entries.len() as u16,
+        MRTRecord::RibIPv6Unicast(ref mut r) => r.entry_count = r.rib_entries.len() as u16,
+        _ => {}
+    }
+}
+
+fn invalid_data(e: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+// Writes the records of a dump that survive filter_record as a new MRT
+// stream. So that the output stands on its own:
+//
+// - a PEER_INDEX_TABLE is written only if some RIB entry after it is kept,
+//   unchanged and just before the first such entry, so peer indexes stay
+//   valid.
+// - RIB sequence numbers count up from 0 after each PEER_INDEX_TABLE, and
+//   TABLE_DUMP sequence numbers from 0 over the whole output.
+//
+// Records are written as they come; only the last PEER_INDEX_TABLE is held.
+pub struct FilteredMRTWriter<W: Write, F: FnMut(&Route) -> bool> {
+    writer: MRTWriter<W>,
+    keep: F,
+    keep_routeless: bool,
+    // the table the RIB records refer to, and whether it has been written
+    peer_index: Option<(MRTHeader, MRTRecord<'static>)>,
+    peer_index_written: bool,
+    peers: Vec<PeerEntry>,
+    rib_sequence: u32,
+    table_dump_sequence: u16,
+}
+
+impl<W: Write, F: FnMut(&Route) -> bool> FilteredMRTWriter<W, F> {
+    pub fn new(out: W, keep_routeless: bool, keep: F) -> FilteredMRTWriter<W, F> {
+        FilteredMRTWriter {
+            writer: MRTWriter::new(out),
+            keep,
+            keep_routeless,
+            peer_index: None,
+            peer_index_written: false,
+            peers: Vec::new(),
+            rib_sequence: 0,
+            table_dump_sequence: 0,
+        }
+    }
+
+    // Records whose routes cannot be worked out (a RIB entry naming a peer
+    // missing from the PEER_INDEX_TABLE) give an InvalidData error and are
+    // not written.
+    pub fn write(&mut self, header: &MRTHeader, record: MRTRecord) -> io::Result<()> {
+        if let MRTRecord::PeerIndexTable(ref index) = record {
+            self.peers = index.peer_entries.clone();
+            self.peer_index = Some((header.clone(), record.into_owned()));
+            self.peer_index_written = false;
+            self.rib_sequence = 0;
+            return Ok(());
+        }
+        let keep = &mut self.keep;
+        let record = filter_record(header, record, &self.peers, self.keep_routeless, keep)
+            .map_err(invalid_data)?;
+        match record {
+            None => Ok(()),
+            Some(mut rib @ MRTRecord::RibIPv4Unicast(_))
+            | Some(mut rib @ MRTRecord::RibIPv6Unicast(_)) => {
+                self.write_peer_index()?;
+                if let Some((sequence, _)) = rib_parts(&mut rib) {
+                    *sequence = self.rib_sequence;
+                }
+                self.rib_sequence = self.rib_sequence.wrapping_add(1);
+                self.writer.write_record(header, &rib)
+            }
+            Some(MRTRecord::TableDumpIPv4(mut r)) => {
+                r.sequence_number = self.next_table_dump_sequence();
+                self.writer.write_record(header, &MRTRecord::TableDumpIPv4(r))
+            }
+            Some(MRTRecord::TableDumpIPv6(mut r)) => {
+                r.sequence_number = self.next_table_dump_sequence();
+                self.writer.write_record(header, &MRTRecord::TableDumpIPv6(r))
+            }
+            Some(other) => self.writer.write_record(header, &other),
+        }
+    }
+
+    fn next_table_dump_sequence(&mut self) -> u16 {
+        let sequence = self.table_dump_sequence;
+        self.table_dump_sequence = sequence.wrapping_add(1);
+        sequence
+    }
+
+    // Write the PEER_INDEX_TABLE ahead of the first RIB record kept after it.
+    fn write_peer_index(&mut self) -> io::Result<()> {
+        if self.peer_index_written {
+            return Ok(());
+        }
+        match self.peer_index {
+            Some((ref header, ref index)) => self.writer.write_record(header, index)?,
+            None => return Err(invalid_data("RIB records without a PEER_INDEX_TABLE".to_string())),
+        }
+        self.peer_index_written = true;
+        Ok(())
+    }
+
+    // Flush the output and hand it back.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer.into_inner())
+    }
+}
+
+#[test]
+fn filtered_writer_test() {
+    use bgp::Prefix;
+    use fixtures::{encode_records, header};
+    use mrt::MRTReader;
+
+    // three peers; two RIB records, one with entries from peers 0 and 2
+    let peer_index = concat!(
+        "c0000201", "0000", "0003",
+        "00", "0a000001", "c0000201", "0001",
+        "00", "0a000002", "c0000202", "0002",
+        "00", "0a000003", "c0000203", "0003"
+    );
+    let rib_a = concat!(
+        "00000000", "18", "c63364", "0002",
+        "0000", "3b23e244", "0004", "40010100",
+        "0002", "3b23e244", "0004", "40010100"
+    );
+    let rib_b = concat!(
+        "00000001", "18", "cb0071", "0001",
+        "0001", "3b23e244", "0004", "40010100"
+    );
+    let input = encode_records(&[
+        (header(1_000_000_000, 13, 1), peer_index),
+        (header(1_000_000_000, 13, 2), rib_a),
+        (header(1_000_000_000, 13, 2), rib_b),
+    ]);
+
+    // keep 203.0.113.0/24 only: the table as it was and the second record
+    let wanted: Prefix = "203.0.113.0/24".parse().unwrap();
+    let mut writer = FilteredMRTWriter::new(Vec::new(), false, |r: &Route| wanted.contains(&r.prefix));
+    for item in MRTReader::new(&input) {
+        let (header, record) = item.unwrap();
+        writer.write(&header, record).unwrap();
+    }
+    let output = writer.finish().unwrap();
+
+    let records: Vec<MRTRecord> = MRTReader::new(&output).map(|r| r.unwrap().1).collect();
+    assert_eq!(records.len(), 2);
+    match records[0] {
+        MRTRecord::PeerIndexTable(ref index) => {
+            assert_eq!(index.peer_count, 3);
+            assert_eq!(index.peer_entries[1].peer_asn, 2);
+        }
+        _ => panic!("expected PEER_INDEX_TABLE"),
+    }
+    match records[1] {
+        MRTRecord::RibIPv4Unicast(ref r) => {
+            assert_eq!(r.sequence_number, 0);
+            assert_eq!(r.rib_entries.len(), 1);
+            assert_eq!(r.rib_entries[0].peer_index, 1);
+        }
+        _ => panic!("expected RIB_IPV4_UNICAST"),
+    }
+
+    // dropping the entries of peer 0 leaves peer 2 alone in the first record
+    let mut writer = FilteredMRTWriter::new(Vec::new(), false, |r: &Route| r.peer_asn != 1);
+    for item in MRTReader::new(&input) {
+        let (header, record) = item.unwrap();
+        writer.write(&header, record).unwrap();
+    }
+    let output = writer.finish().unwrap();
+    let records: Vec<MRTRecord> = MRTReader::new(&output).map(|r| r.unwrap().1).collect();
+    match records[1] {
+        MRTRecord::RibIPv4Unicast(ref r) => {
+            assert_eq!(r.entry_count, 1);
+            assert_eq!(r.rib_entries[0].peer_index, 2);
+        }
+        _ => panic!("expected RIB_IPV4_UNICAST"),
+    }
+
+    // with no RIB entry kept the table is left out too
+    let mut writer = FilteredMRTWriter::new(Vec::new(), false, |_: &Route| false);
+    for item in MRTReader::new(&input) {
+        let (header, record) = item.unwrap();
+        writer.write(&header, record).unwrap();
+    }
+    assert!(writer.finish().unwrap().is_empty());
+}
+
+#[test]
+fn prefix_filter_test() {
+    use mrt::split_mrt_record;
+    use mrt::parse_mrt_body;
+
+    let prefixes = vec!["192.0.2.0/24".parse().unwrap()];
+    let exact = PrefixFilter::new(prefixes.clone(), PrefixMatch::Exact);
+    let more = PrefixFilter::new(prefixes.clone(), PrefixMatch::MoreSpecific);
+    let less = PrefixFilter::new(prefixes, PrefixMatch::LessSpecific);
+    for (prefix, e, m, l) in [
+        ("192.0.2.0/24", true, true, true),
+        ("192.0.2.128/25", false, true, false),
+        ("192.0.0.0/16", false, false, true),
+        ("198.51.100.0/24", false, false, false),
+    ] {
+        let prefix: Prefix = prefix.parse().unwrap();
+        assert_eq!((exact.matches(&prefix), more.matches(&prefix), less.matches(&prefix)), (e, m, l));
+    }
+
+    // a BGP4MP UPDATE withdrawing 192.0.2.0/25 and announcing 10.0.0.0/8
+    let buffer = hex::decode(concat!(
+        "3b2406ce", "0010", "0001", "00000040",
+        "fde8", "fde9", "0000", "0001", "c0000201", "c0000202",
+        "ffffffffffffffffffffffffffffffff", "0030", "02",
+        "0005", "19c0000200", "0012", "40010100", "40020402010001", "4003040a000001", "080a"
+    ))
+    .unwrap();
+    let (_, header, body) = split_mrt_record(&buffer).unwrap();
+    let more = PrefixFilter::new(vec!["192.0.2.0/24".parse().unwrap()], PrefixMatch::MoreSpecific);
+    let record = parse_mrt_body(&header, body).unwrap();
+    let record = more.apply(&header, record, &[]).unwrap().unwrap();
+    let routes = route::routes(&header, &record, &[]).unwrap();
+    assert_eq!(routes.len(), 1);
+    assert_eq!(routes[0].kind, RouteKind::Withdraw);
+    let exact = PrefixFilter::new(vec!["192.0.2.0/24".parse().unwrap()], PrefixMatch::Exact);
+    let record = parse_mrt_body(&header, body).unwrap();
+    assert!(exact.apply(&header, record, &[]).unwrap().is_none());
+}
+
+#[test]
+fn prune_update_test() {
+    use bgp::{BGPPathAttribute, MpReachNlri, MpUnreachNlri};
+    use bgp4mp::{BGP4MPMessage, BGP4MPPeer};
+    use encode::encode_record;
+    use mrt::MRTReader;
+
+    let prefixes = |list: &[&str]| list.iter().map(|p| p.parse().unwrap()).collect::<Vec<Prefix>>();
+    let attr = |flags, code, data: Vec<u8>| BGPPathAttribute {
+        flags,
+        code,
+        len: data.len() as u16,
+        data: Cow::Owned(data),
+    };
+    let mp_reach = MpReachNlri {
+        afi: 2,
+        safi: 1,
+        next_hop: "2001:db8::1".parse().unwrap(),
+        link_local_next_hop: None,
+        nlri: prefixes(&["2001:db8:1::/48", "2001:db8:2::/48"]),
+    };
+    let mp_unreach = MpUnreachNlri {
+        afi: 2,
+        safi: 1,
+        withdrawn_routes: prefixes(&["2001:db8:3::/48", "2001:db8:1:1::/64"]),
+    };
+    let update = BGPUpdate {
+        withdrawn_routes: prefixes(&["192.0.2.0/25", "198.51.100.0/24"]),
+        path_attributes: vec![
+            attr(0x40, 1, vec![0]),
+            attr(0x40, 2, vec![2, 1, 0, 0, 0xfd, 0xe8]),
+            attr(0x80, 14, encode_mp_reach_nlri(&mp_reach)),
+            attr(0x80, 15, encode_mp_unreach_nlri(&mp_unreach)),
+        ],
+        nlri: prefixes(&["10.0.0.0/8", "192.0.2.128/25"]),
+    };
+    let header = MRTHeader { timestamp: 0, mrt_type: 16, mrt_subtype: 4, length: 0, microsecond_timestamp: None };
+    let record = MRTRecord::BGP4MPMessage(BGP4MPMessage {
+        peer: BGP4MPPeer {
+            peer_asn: 65000,
+            local_asn: 65001,
+            interface_index: 0,
+            afi: 1,
+            peer_address: "192.0.2.1".parse().unwrap(),
+            local_address: "192.0.2.2".parse().unwrap(),
+        },
+        asn_size: AsnSize::Four,
+        message: BGPMessage::Update(update),
+    });
+    let input = encode_record(&header, &record).unwrap();
+
+    // the routes left once the UPDATE is filtered, written and read back
+    let filtered = |filter: PrefixFilter| -> Vec<(RouteKind, String, bool)> {
+        let (header, record) = MRTReader::new(&input).next().unwrap().unwrap();
+        let record = match filter.apply(&header, record, &[]).unwrap() {
+            Some(record) => record,
+            None => return Vec::new(),
+        };
+        let output = encode_record(&header, &record).unwrap();
+        let (header, record) = MRTReader::new(&output).next().unwrap().unwrap();
+        route::routes(&header, &record, &[])
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.kind, r.prefix.to_string(), r.attributes.origin.is_some()))
+            .collect()
+    };
+
+    let mixed = PrefixFilter::new(prefixes(&["192.0.2.0/24", "2001:db8:1::/48"]), PrefixMatch::MoreSpecific);
+    assert_eq!(
+        filtered(mixed),
+        vec![
+            (RouteKind::Withdraw, "192.0.2.0/25".to_string(), false),
+            (RouteKind::Withdraw, "2001:db8:1:1::/64".to_string(), false),
+            (RouteKind::Announce, "192.0.2.128/25".to_string(), true),
+            (RouteKind::Announce, "2001:db8:1::/48".to_string(), true),
+        ]
+    );
+
+    // a withdrawal alone keeps no attributes but MP_UNREACH_NLRI
+    let withdrawals = PrefixFilter::new(prefixes(&["198.51.100.0/24", "2001:db8:3::/48"]), PrefixMatch::Exact);
+    let (header, record) = MRTReader::new(&input).next().unwrap().unwrap();
+    match withdrawals.apply(&header, record, &[]).unwrap() {
+        Some(MRTRecord::BGP4MPMessage(BGP4MPMessage { message: BGPMessage::Update(ref update), .. })) => {
+            assert_eq!(update.withdrawn_routes, prefixes(&["198.51.100.0/24"]));
+            assert!(update.nlri.is_empty());
+            assert_eq!(update.path_attributes.len(), 1);
+            assert_eq!(update.path_attributes[0].code, 15);
+        }
+        _ => panic!("expected an UPDATE"),
+    }
+
+    assert!(filtered(PrefixFilter::new(prefixes(&["203.0.113.0/24"]), PrefixMatch::Exact)).is_empty());
+}
+
+#[test]
+fn route_filter_test() {
+    use bgp::{AsPathSegment, AsPathSegmentType};
+
+    let route = Route {
+        timestamp: 0,
+        microsecond_timestamp: None,
+        kind: RouteKind::Announce,
+        peer_address: "192.0.2.1".parse().unwrap(),
+        peer_asn: 3356,
+        prefix: "10.1.0.0/16".parse().unwrap(),
+        attributes: RouteAttributes {
+            as_path: Some(AsPath {
+                segments: vec![AsPathSegment {
+                    segment_type: AsPathSegmentType::AS_SEQUENCE,
+                    asns: vec![3356, 174, 13335],
+                }],
+            }),
+            ..Default::default()
+        },
+    };
+    let matches = |filter: RouteFilter| filter.matches(&route);
+    assert!(matches(RouteFilter::default()));
+    assert!(matches(RouteFilter { origin_asns: vec![1, 13335], ..RouteFilter::default() }));
+    assert!(!matches(RouteFilter { origin_asns: vec![174], ..RouteFilter::default() }));
+    assert!(matches(RouteFilter { path_asns: vec![174], ..RouteFilter::default() }));
+    assert!(!matches(RouteFilter { path_asns: vec![1], ..RouteFilter::default() }));
+    assert!(matches(RouteFilter { peer_asns: vec![3356], ..RouteFilter::default() }));
+    assert!(!matches(RouteFilter { peer_asns: vec![174], path_asns: vec![174], ..RouteFilter::default() }));
+    let as_paths = vec![AsPathRegex::new("^3356_").unwrap(), AsPathRegex::new("_13335$").unwrap()];
+    assert!(matches(RouteFilter { as_paths, ..RouteFilter::default() }));
+
+    // withdrawals carry no path, so fail any path criterion
+    let withdrawal = Route { kind: RouteKind::Withdraw, attributes: RouteAttributes::default(), ..route };
+    assert!(RouteFilter { peer_asns: vec![3356], ..RouteFilter::default() }.matches(&withdrawal));
+    assert!(!RouteFilter { path_asns: vec![174], ..RouteFilter::default() }.matches(&withdrawal));
+}
+
+#[test]
+fn route_filter_session_test() {
+    use bgp4mp::{BGP4MPPeer, BGP4MPStateChange};
+
+    let header = MRTHeader {
+        timestamp: 0,
+        mrt_type: 16,
+        mrt_subtype: 5,
+        length: 0,
+        microsecond_timestamp: None,
+    };
+    let state_change = || {
+        MRTRecord::BGP4MPStateChange(BGP4MPStateChange {
+            peer: BGP4MPPeer {
+                peer_asn: 65000,
+                local_asn: 65001,
+                interface_index: 0,
+                afi: 1,
+                peer_address: "192.0.2.1".parse().unwrap(),
+                local_address: "192.0.2.2".parse().unwrap(),
+            },
+            old_state: 1,
+            new_state: 2,
+        })
+    };
+    let kept = |filter: RouteFilter| filter.apply(&header, state_change(), &[]).unwrap().is_some();
+    assert!(kept(RouteFilter::default()));
+    assert!(kept(RouteFilter { peer_asns: vec![65000], ..RouteFilter::default() }));
+    assert!(!kept(RouteFilter { peer_asns: vec![65001], ..RouteFilter::default() }));
+    let peer_addresses = vec!["192.0.2.1".parse().unwrap()];
+    assert!(kept(RouteFilter { peer_addresses, ..RouteFilter::default() }));
+    let peer_addresses = vec!["192.0.2.2".parse().unwrap()];
+    assert!(!kept(RouteFilter { peer_addresses, ..RouteFilter::default() }));
+
+    // an IPv4 session, whatever its messages carry
+    assert!(kept(RouteFilter { afi: Some(Afi::IPv4), peer_asns: vec![65000], ..RouteFilter::default() }));
+    assert!(!kept(RouteFilter { afi: Some(Afi::IPv6), ..RouteFilter::default() }));
+
+    // with other route criteria there are no routes to meet them
+    assert!(!kept(RouteFilter { afi: Some(Afi::IPv4), origin_asns: vec![65000], ..RouteFilter::default() }));
+}
+
+#[test]
+fn as_path_regex_test() {
+    use bgp::{AsPathSegment, AsPathSegmentType};
+
+    let path = AsPath {
+        segments: vec![
+            AsPathSegment { segment_type: AsPathSegmentType::AS_SEQUENCE, asns: vec![174, 3356, 13335] },
+            AsPathSegment { segment_type: AsPathSegmentType::AS_SET, asns: vec![64512, 64513] },
+        ],
+    };
+    let matches = |pattern: &str| AsPathRegex::new(pattern).unwrap().is_match(&path);
+    assert!(matches("_3356_"));
+    assert!(!matches("_335_"));
+    assert!(matches("^174_"));
+    assert!(matches("_64513_"));
+    assert!(!matches("_64513$"));
+    assert!(matches("^174 .* 13335_"));
+    assert!(!matches("^3356_"));
+    assert!(AsPathRegex::new("(").is_err());
+    assert!(path.contains_asn(64512));
+    assert!(!path.contains_asn(1));
+}
+
+#[test]
+fn community_pattern_test() {
+    use bgp::{Community, ExtendedCommunity, LargeCommunity};
+
+    let attributes = RouteAttributes {
+        communities: vec![Community(0xfde8_029a), Community(COMMUNITY_NO_EXPORT)],
+        large_communities: vec![LargeCommunity { global_admin: 64496, local_data_1: 1, local_data_2: 2 }],
+        // route target 65000:100, route origin 192.0.2.1:7
+        extended_communities: vec![
+            ExtendedCommunity(0x0002_fde8_0000_0064),
+            ExtendedCommunity(0x0103_c000_0201_0007),
+        ],
+        ..Default::default()
+    };
+    let matches = |pattern: &str| pattern.parse::<CommunityPattern>().unwrap().matches(&attributes);
+    assert!(matches("65000:666"));
+    assert!(matches("*:666"));
+    assert!(matches("65000:*"));
+    assert!(!matches("65535:666"));
+    assert!(matches("no-export"));
+    assert!(!matches("no-advertise"));
+    assert!(matches("64496:1:*"));
+    assert!(!matches("64496:2:*"));
+    assert!(matches("rt:65000:*"));
+    assert!(matches("rt:*:100"));
+    assert!(!matches("soo:65000:*"));
+    assert!(matches("soo:192.0.2.1:7"));
+    assert!("65536:1".parse::<CommunityPattern>().is_err());
+    assert!("rt:1".parse::<CommunityPattern>().is_err());
+    assert_eq!(attributes.extended_communities[1].to_string(), "soo:192.0.2.1:7");
+}
+
+#[test]
+fn record_type_test() {
+    assert_eq!(parse_record_type("bgp4mp_et:state_change"), Ok((17, Some(0))));
+    assert_eq!(parse_record_type("TABLE_DUMP_V2:RIB_IPV6_UNICAST"), Ok((13, Some(4))));
+    assert_eq!(parse_record_type("16"), Ok((16, None)));
+    assert!(parse_record_type("BGP4MP:nonsense").is_err());
+}
@@ -0,0 +1,34 @@
+use encode::encode_header;
+use mrt::MRTHeader;
+
+// Records shared by the tests of several modules, and a way to write more.
+// Also compiled into the binary's tests, hence the plain crate paths.
+
+// A TABLE_DUMP record for 3.0.0.0/8 from 193.148.15.85 AS3257, with ORIGIN
+// IGP, AS_PATH 3257 701 80 (2-octet) and NEXT_HOP 193.148.15.85.
+pub const TABLE_DUMP: &str = concat!(
+    "3b2406ce", "000c", "0001", "0000002c",
+    "0000", "0000", "03000000", "08", "01", "3b23e244", "c1940f55", "0cb9", "0016",
+    "40010100", "40020802030cb902bd0050", "400304c1940f55"
+);
+
+pub fn table_dump() -> Vec<u8> {
+    hex::decode(TABLE_DUMP).unwrap()
+}
+
+// A header of the given type and subtype, without _ET microseconds.
+pub fn header(timestamp: u32, mrt_type: u16, mrt_subtype: u16) -> MRTHeader {
+    MRTHeader { timestamp, mrt_type, mrt_subtype, length: 0, microsecond_timestamp: None }
+}
+
+// The records, headers with bodies in hex, as an MRT stream. The lengths in
+// the headers are worked out from the bodies.
+pub fn encode_records<S: AsRef<str>>(records: &[(MRTHeader, S)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (header, body) in records {
+        let body = hex::decode(body.as_ref()).unwrap();
+        encode_header(&mut out, header, body.len());
+        out.extend_from_slice(&body);
+    }
+    out
+}
@@ -0,0 +1,357 @@
+use bgp::Prefix;
+use filter::PrefixMatch;
+use mrt::{parse_mrt_body, split_mrt_record, MRTHeader, MRTReader, MRTRecord, PeerEntry};
+use route::update_routes;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// A sidecar index of an uncompressed MRT file, so that the records for a
+// prefix can be read from a large dump without going through the rest: the
+// byte offset of every record that carries a route, keyed by prefix and
+// timestamp, plus the offsets of the PEER_INDEX_TABLEs that RIB records
+// need to be decoded.
+//
+// The index is written next to the dump (FILE.idx by default) as:
+//
+//   "MRTIDX01"
+//   u64  length of the indexed file, to tell a stale index
+//   u64  number of PEER_INDEX_TABLE offsets, then each as a u64
+//   u64  number of entries, then each as
+//        u64 offset, u32 timestamp, u8 4 or 6, u8 prefix length,
+//        4 or 16 address octets
+//
+// all big-endian. Entries are sorted by prefix, then time, then offset; a
+// record with several prefixes (a BGP4MP UPDATE) has an entry for each.
+
+const MAGIC: &[u8; 8] = b"MRTIDX01";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexEntry {
+    pub prefix: Prefix,
+    pub timestamp: u32,
+    pub offset: u64,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Index {
+    file_length: u64,
+    peer_tables: Vec<u64>,
+    entries: Vec<IndexEntry>,
+}
+
+// The sidecar path for an MRT file.
+pub fn index_path(path: &str) -> String {
+    format!("{}.idx", path)
+}
+
+// The prefixes of a record's routes, without duplicates.
+fn record_prefixes(header: &MRTHeader, record: &MRTRecord) -> Vec<Prefix> {
+    let mut prefixes = match *record {
+        MRTRecord::TableDumpIPv4(ref r) => vec![r.prefix()],
+        MRTRecord::TableDumpIPv6(ref r) => vec![r.prefix()],
+        MRTRecord::RibIPv4Unicast(ref r) => vec![r.prefix()],
+        MRTRecord::RibIPv6Unicast(ref r) => vec![r.prefix()],
+        MRTRecord::BGP4MPMessage(ref msg) => {
+            update_routes(header, msg).iter().map(|r| r.prefix).collect()
+        }
+        _ => Vec::new(),
+    };
+    prefixes.sort();
+    prefixes.dedup();
+    prefixes
+}
+
+// The highest address in a prefix.
+fn last_address(prefix: &Prefix) -> IpAddr {
+    match prefix.addr {
+        IpAddr::V4(addr) => {
+            let host = u32::MAX.checked_shr(u32::from(prefix.len)).unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(u32::from(addr) | host))
+        }
+        IpAddr::V6(addr) => {
+            let host = u128::MAX.checked_shr(u32::from(prefix.len)).unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(u128::from(addr) | host))
+        }
+    }
+}
+
+fn read_u64<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    input.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl Index {
+    // Index an uncompressed MRT dump. Records that fail to decode are left
+    // out; the index stops where framing is lost.
+    pub fn build(input: &[u8]) -> Index {
+        let mut index = Index { file_length: input.len() as u64, ..Default::default() };
+        let mut reader = MRTReader::new(input);
+        while let Some(entry) = reader.next() {
+            let offset = reader.offset() as u64;
+            let (header, record) = match entry {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+            if let MRTRecord::PeerIndexTable(_) = record {
+                index.peer_tables.push(offset);
+            }
+            for prefix in record_prefixes(&header, &record) {
+                index.entries.push(IndexEntry { prefix, timestamp: header.timestamp, offset });
+            }
+        }
+        index.entries.sort_by_key(|e| (e.prefix, e.timestamp, e.offset));
+        index
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    // The entries for prefix, or with more_specifics also those for every
+    // prefix it covers, in prefix then time order.
+    pub fn lookup(&self, prefix: &Prefix, more_specifics: bool) -> &[IndexEntry] {
+        let start = self.entries.partition_point(|e| e.prefix < *prefix);
+        let end = if more_specifics {
+            let last = Prefix { addr: last_address(prefix), len: 128 };
+            self.entries.partition_point(|e| e.prefix <= last)
+        } else {
+            self.entries.partition_point(|e| e.prefix <= *prefix)
+        };
+        &self.entries[start..end.max(start)]
+    }
+
+    // The offset of the PEER_INDEX_TABLE in force at offset, if any.
+    pub fn peer_table_before(&self, offset: u64) -> Option<u64> {
+        let i = self.peer_tables.partition_point(|&o| o < offset);
+        if i == 0 {
+            None
+        } else {
+            Some(self.peer_tables[i - 1])
+        }
+    }
+
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&self.file_length.to_be_bytes())?;
+        out.write_all(&(self.peer_tables.len() as u64).to_be_bytes())?;
+        for offset in &self.peer_tables {
+            out.write_all(&offset.to_be_bytes())?;
+        }
+        out.write_all(&(self.entries.len() as u64).to_be_bytes())?;
+        for entry in &self.entries {
+            out.write_all(&entry.offset.to_be_bytes())?;
+            out.write_all(&entry.timestamp.to_be_bytes())?;
+            match entry.prefix.addr {
+                IpAddr::V4(addr) => {
+                    out.write_all(&[4, entry.prefix.len])?;
+                    out.write_all(&addr.octets())?;
+                }
+                IpAddr::V6(addr) => {
+                    out.write_all(&[6, entry.prefix.len])?;
+                    out.write_all(&addr.octets())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(input: &mut R) -> io::Result<Index> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not an MRT index"));
+        }
+        let file_length = read_u64(input)?;
+        let count = read_u64(input)?;
+        let peer_tables = (0..count).map(|_| read_u64(input)).collect::<io::Result<_>>()?;
+        let count = read_u64(input)?;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let offset = read_u64(input)?;
+            let mut fixed = [0; 6];
+            input.read_exact(&mut fixed)?;
+            let timestamp = u32::from_be_bytes([fixed[0], fixed[1], fixed[2], fixed[3]]);
+            let addr = match fixed[4] {
+                4 => {
+                    let mut octets = [0; 4];
+                    input.read_exact(&mut octets)?;
+                    IpAddr::V4(Ipv4Addr::from(octets))
+                }
+                6 => {
+                    let mut octets = [0; 16];
+                    input.read_exact(&mut octets)?;
+                    IpAddr::V6(Ipv6Addr::from(octets))
+                }
+                _ => return Err(invalid("bad address family in MRT index")),
+            };
+            entries.push(IndexEntry { prefix: Prefix { addr, len: fixed[5] }, timestamp, offset });
+        }
+        Ok(Index { file_length, peer_tables, entries })
+    }
+
+    pub fn write_file(&self, path: &str) -> Result<(), String> {
+        File::create(path)
+            .and_then(|file| {
+                let mut out = BufWriter::new(file);
+                self.write(&mut out)?;
+                out.flush()
+            })
+            .map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn read_file(path: &str) -> Result<Index, String> {
+        File::open(path)
+            .and_then(|file| Index::read(&mut BufReader::new(file)))
+            .map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+// Reads single records of an indexed file by seeking to them.
+pub struct IndexedReader<R: Read + Seek> {
+    input: R,
+    index: Index,
+    // the PEER_INDEX_TABLE last read, by offset
+    peers: Option<(u64, Vec<PeerEntry>)>,
+}
+
+impl IndexedReader<File> {
+    // Open the MRT file at path with the index at index_path, which has to
+    // have been built from the file as it is now.
+    pub fn open(path: &str, index_path: &str) -> Result<IndexedReader<File>, String> {
+        let index = Index::read_file(index_path)?;
+        let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        let length = file.metadata().map_err(|e| format!("{}: {}", path, e))?.len();
+        if length != index.file_length {
+            return Err(format!("{}: index is out of date, rebuild it", index_path));
+        }
+        Ok(IndexedReader::new(file, index))
+    }
+}
+
+impl<R: Read + Seek> IndexedReader<R> {
+    pub fn new(input: R, index: Index) -> IndexedReader<R> {
+        IndexedReader { input, index, peers: None }
+    }
+
+    pub fn index(&self) -> &Index {
+        &self.index
+    }
+
+    fn read_raw(&mut self, offset: u64) -> Result<Vec<u8>, String> {
+        let mut buffer = vec![0; 12];
+        self.input
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.input.read_exact(&mut buffer))
+            .map_err(|e| format!("offset {}: {}", offset, e))?;
+        let length = u32::from_be_bytes([buffer[8], buffer[9], buffer[10], buffer[11]]);
+        let mut body = vec![0; length as usize];
+        self.input.read_exact(&mut body).map_err(|e| format!("offset {}: {}", offset, e))?;
+        buffer.extend_from_slice(&body);
+        Ok(buffer)
+    }
+
+    // The PEER_INDEX_TABLE at offset, whose peers read_at then gives for the
+    // records after it.
+    pub fn read_peer_table(&mut self, offset: u64) -> Result<(MRTHeader, MRTRecord<'static>), String> {
+        let raw = self.read_raw(offset)?;
+        let (_, header, body) = split_mrt_record(&raw)?;
+        match parse_mrt_body(&header, body)?.into_owned() {
+            MRTRecord::PeerIndexTable(index) => {
+                self.peers = Some((offset, index.peer_entries.clone()));
+                Ok((header, MRTRecord::PeerIndexTable(index)))
+            }
+            _ => Err(format!("offset {}: not a PEER_INDEX_TABLE", offset)),
+        }
+    }
+
+    // The record at offset, with the peers of the PEER_INDEX_TABLE before it.
+    pub fn read_at(
+        &mut self,
+        offset: u64,
+    ) -> Result<(MRTHeader, MRTRecord<'static>, &[PeerEntry]), String> {
+        let table = self.index.peer_table_before(offset);
+        if let Some(table) = table {
+            if self.peers.as_ref().map(|p| p.0) != Some(table) {
+                self.read_peer_table(table)?;
+            }
+        }
+        let raw = self.read_raw(offset)?;
+        let (_, header, body) = split_mrt_record(&raw)?;
+        let record = parse_mrt_body(&header, body)?.into_owned();
+        let peers = match self.peers {
+            Some((at, ref peers)) if Some(at) == table => &peers[..],
+            _ => &[],
+        };
+        Ok((header, record, peers))
+    }
+
+    // The offsets of the records for prefix, and the prefixes it covers or
+    // is covered by as mode says, timestamped within from..=to, in file order.
+    pub fn offsets(&self, prefix: &Prefix, mode: PrefixMatch, from: u32, to: u32) -> Vec<u64> {
+        let entries: Vec<&IndexEntry> = match mode {
+            PrefixMatch::Exact => self.index.lookup(prefix, false).iter().collect(),
+            PrefixMatch::MoreSpecific => self.index.lookup(prefix, true).iter().collect(),
+            PrefixMatch::LessSpecific => (0..=prefix.len)
+                .flat_map(|len| self.index.lookup(&prefix.supernet(len), false))
+                .collect(),
+        };
+        let mut offsets: Vec<u64> = entries
+            .into_iter()
+            .filter(|e| e.timestamp >= from && e.timestamp <= to)
+            .map(|e| e.offset)
+            .collect();
+        offsets.sort_unstable();
+        offsets.dedup();
+        offsets
+    }
+}
+
+#[test]
+fn index_test() {
+    use fixtures::{encode_records, header};
+    use std::io::Cursor;
+
+    // a PEER_INDEX_TABLE, then RIB records for 198.51.100.0/24,
+    // 198.51.100.128/25 and 203.0.113.0/24
+    let peer_index = concat!("c0000201", "0000", "0001", "00", "0a000001", "c0000201", "0001");
+    let mut input = encode_records(&[(header(100, 13, 1), peer_index)]);
+    let mut offsets = Vec::new();
+    for prefix in ["18c63364", "19c6336480", "18cb0071"] {
+        let rib = format!("00000000{}00010000000000000000", prefix);
+        offsets.push(input.len() as u64);
+        input.extend(encode_records(&[(header(100, 13, 2), rib)]));
+    }
+
+    let index = Index::build(&input);
+    let mut file = Vec::new();
+    index.write(&mut file).unwrap();
+    let index = Index::read(&mut &file[..]).unwrap();
+    assert_eq!(index.entries().len(), 3);
+
+    let wanted: Prefix = "198.51.100.0/24".parse().unwrap();
+    let mut reader = IndexedReader::new(Cursor::new(&input), index);
+    assert_eq!(reader.offsets(&wanted, PrefixMatch::Exact, 0, u32::MAX), vec![offsets[0]]);
+    assert_eq!(
+        reader.offsets(&wanted, PrefixMatch::MoreSpecific, 0, u32::MAX),
+        vec![offsets[0], offsets[1]]
+    );
+    assert!(reader.offsets(&wanted, PrefixMatch::MoreSpecific, 200, u32::MAX).is_empty());
+    let half: Prefix = "198.51.100.128/25".parse().unwrap();
+    assert_eq!(
+        reader.offsets(&half, PrefixMatch::LessSpecific, 0, u32::MAX),
+        vec![offsets[0], offsets[1]]
+    );
+
+    let (_, record, peers) = reader.read_at(offsets[1]).unwrap();
+    assert_eq!(peers.len(), 1);
+    match record {
+        MRTRecord::RibIPv4Unicast(ref r) => assert_eq!(r.prefix().to_string(), "198.51.100.128/25"),
+        _ => panic!("expected RIB_IPV4_UNICAST"),
+    }
+}
@@ -0,0 +1,409 @@
+use bgp::{
+    path_attribute_name, AsPath, AsnSize, BGPPathAttribute, MpReachNlri, MpUnreachNlri,
+    PathAttributeValue, Prefix,
+};
+use bgp4mp::{BGP4MPPeer, BGPMessage};
+use bgpdump::state_name;
+use bgpsec::BgpsecPath;
+use mrt::{type_names, MRTHeader, MRTRecord, PeerEntry, Provenance, RibEntry};
+use serde_json::{self, Value};
+use std::io::{self, Write};
+
+// Newline-delimited JSON: one object per MRT record.
+//
+// Every object has the same top level:
+//
+//   "timestamp"              seconds since the epoch
+//   "microsecond_timestamp"  number, or null for types without one
+//   "mrt_type", "mrt_subtype"            codes from the header
+//   "mrt_type_name", "mrt_subtype_name"  names, or null if unknown
+//   "length"                 record length, as on the wire
+//   "record"                 the decoded body, described below
+//   "source"                 {file, offset, collector} of the record in its
+//                            input, only when the writer is given it;
+//                            collector is null unless inputs were tagged
+//
+// "record" always has a "kind", one of:
+//
+//   "table_dump": view_number, sequence_number, prefix, status,
+//       originated_time, peer_address, peer_asn, attributes; if the
+//       attributes cannot be split apart they are [] and "attributes_error"
+//       says why
+//   "peer_index_table": collector_bgp_id, view_name, peers, where each peer is
+//       {index, peer_type, peer_bgp_id, peer_address, peer_asn}
+//   "rib": sequence_number, prefix, entries, where each entry is
+//       {peer_index, peer_address, peer_asn, originated_time, attributes};
+//       peer_address and peer_asn are null if the peer index is unknown
+//   "bgp4mp_state_change": peer, old_state, new_state, old_state_name,
+//       new_state_name
+//   "bgp4mp_message": peer, asn_size (2 or 4), message
+//   "unsupported": no other fields
+//
+// A BGP4MP peer is {peer_asn, local_asn, interface_index, afi, peer_address,
+// local_address}. A message has a "type" of "open" (version, my_asn,
+// hold_time, bgp_id, opt_params), "update" (withdrawn_routes, attributes,
+// nlri), "notification" (error_code, error_subcode, data), "keepalive",
+// "route_refresh" or "unknown" (code).
+//
+// Each path attribute is {code, flags, name, value}, with name null for
+// codes this crate does not know. The value depends on the attribute:
+//
+//   ORIGIN                  "IGP", "EGP" or "INCOMPLETE"
+//   AS_PATH, AS4_PATH       [{"type": "AS_SEQUENCE", "asns": [..]}, ..]
+//   NEXT_HOP, ORIGINATOR_ID address string
+//   MULTI_EXIT_DISC, LOCAL_PREF  number
+//   ATOMIC_AGGREGATE        null
+//   AGGREGATOR, AS4_AGGREGATOR   {asn, address}
+//   COMMUNITIES             ["65535:666", ..]
+//   EXTENDED_COMMUNITIES    ["rt:64496:100", "soo:192.0.2.1:7", ..], or
+//                           "0x" and 16 hex digits for types without one
+//   CLUSTER_LIST            [address, ..]
+//   MP_REACH_NLRI           {afi, safi, next_hop, link_local_next_hop, nlri}
+//   MP_UNREACH_NLRI         {afi, safi, withdrawn_routes}
+//   LARGE_COMMUNITY         ["64496:1:2", ..]
+//   BGPsec_PATH             {secure_path: [{pcount, confed_segment, asn}],
+//                            signature_blocks: [{algorithm_suite,
+//                            signature_segments: [{ski, signature}]}]}
+//   unknown codes           hex string of the attribute data
+//
+// An attribute that fails to decode has "value": null, an "error" message
+// and its raw "data" in hex. Prefixes are "address/length" strings and
+// opaque byte strings are hex.
+
+fn prefixes(prefixes: &[Prefix]) -> Value {
+    prefixes.iter().map(|p| Value::from(p.to_string())).collect()
+}
+
+fn as_path(path: &AsPath) -> Value {
+    path.segments
+        .iter()
+        .map(|s| json!({"type": format!("{:?}", s.segment_type), "asns": s.asns}))
+        .collect()
+}
+
+fn mp_reach_nlri(mp: &MpReachNlri) -> Value {
+    json!({
+        "afi": mp.afi,
+        "safi": mp.safi,
+        "next_hop": mp.next_hop.to_string(),
+        "link_local_next_hop": mp.link_local_next_hop.map(|a| a.to_string()),
+        "nlri": prefixes(&mp.nlri),
+    })
+}
+
+fn mp_unreach_nlri(mp: &MpUnreachNlri) -> Value {
+    json!({
+        "afi": mp.afi,
+        "safi": mp.safi,
+        "withdrawn_routes": prefixes(&mp.withdrawn_routes),
+    })
+}
+
+fn bgpsec_path(path: &BgpsecPath) -> Value {
+    let secure_path: Vec<Value> = path
+        .secure_path
+        .iter()
+        .map(|s| json!({"pcount": s.pcount, "confed_segment": s.confed_segment, "asn": s.asn}))
+        .collect();
+    let signature_blocks: Vec<Value> = path
+        .signature_blocks
+        .iter()
+        .map(|b| {
+            let segments: Vec<Value> = b
+                .signature_segments
+                .iter()
+                .map(|s| json!({"ski": hex::encode(s.ski), "signature": hex::encode(&s.signature)}))
+                .collect();
+            json!({"algorithm_suite": b.algorithm_suite, "signature_segments": segments})
+        })
+        .collect();
+    json!({"secure_path": secure_path, "signature_blocks": signature_blocks})
+}
+
+fn attribute_value(attr: &BGPPathAttribute, value: PathAttributeValue) -> Value {
+    match value {
+        PathAttributeValue::Origin(origin) => Value::from(origin.to_string()),
+        PathAttributeValue::AsPath(path) | PathAttributeValue::As4Path(path) => as_path(&path),
+        PathAttributeValue::NextHop(addr) | PathAttributeValue::OriginatorId(addr) => {
+            Value::from(addr.to_string())
+        }
+        PathAttributeValue::MultiExitDisc(n) | PathAttributeValue::LocalPref(n) => Value::from(n),
+        PathAttributeValue::AtomicAggregate => Value::Null,
+        PathAttributeValue::Aggregator(agg) | PathAttributeValue::As4Aggregator(agg) => {
+            json!({"asn": agg.asn, "address": agg.address.to_string()})
+        }
+        PathAttributeValue::Communities(communities) => communities
+            .iter()
+            .map(|c| Value::from(format!("{}:{}", c.asn(), c.value())))
+            .collect(),
+        PathAttributeValue::ClusterList(ids) => {
+            ids.iter().map(|a| Value::from(a.to_string())).collect()
+        }
+        PathAttributeValue::MpReachNlri(mp) => mp_reach_nlri(&mp),
+        PathAttributeValue::MpUnreachNlri(mp) => mp_unreach_nlri(&mp),
+        PathAttributeValue::ExtendedCommunities(communities) => {
+            communities.iter().map(|c| Value::from(c.to_string())).collect()
+        }
+        PathAttributeValue::LargeCommunities(communities) => {
+            communities.iter().map(|c| Value::from(c.to_string())).collect()
+        }
+        PathAttributeValue::BgpsecPath(path) => bgpsec_path(&path),
+        PathAttributeValue::Unknown => Value::from(hex::encode(&attr.data)),
+    }
+}
+
+pub fn path_attribute(attr: &BGPPathAttribute, asn_size: AsnSize) -> Value {
+    let mut object = json!({
+        "code": attr.code,
+        "flags": attr.flags,
+        "name": path_attribute_name(attr.code),
+    });
+    match attr.value(asn_size) {
+        Ok(value) => object["value"] = attribute_value(attr, value),
+        Err(e) => {
+            object["value"] = Value::Null;
+            object["error"] = Value::from(e);
+            object["data"] = Value::from(hex::encode(&attr.data));
+        }
+    }
+    object
+}
+
+fn path_attributes(attrs: &[BGPPathAttribute], asn_size: AsnSize) -> Value {
+    attrs.iter().map(|a| path_attribute(a, asn_size)).collect()
+}
+
+// TABLE_DUMP attributes are only split apart on demand; if that fails the
+// record keeps an empty "attributes" and says why in "attributes_error".
+fn set_attributes(object: &mut Value, attrs: Result<Vec<BGPPathAttribute>, String>, asn_size: AsnSize) {
+    match attrs {
+        Ok(attrs) => object["attributes"] = path_attributes(&attrs, asn_size),
+        Err(e) => {
+            object["attributes"] = json!([]);
+            object["attributes_error"] = Value::from(e);
+        }
+    }
+}
+
+fn bgp4mp_peer(peer: &BGP4MPPeer) -> Value {
+    json!({
+        "peer_asn": peer.peer_asn,
+        "local_asn": peer.local_asn,
+        "interface_index": peer.interface_index,
+        "afi": peer.afi,
+        "peer_address": peer.peer_address.to_string(),
+        "local_address": peer.local_address.to_string(),
+    })
+}
+
+fn bgp_message(message: &BGPMessage, asn_size: AsnSize) -> Value {
+    match *message {
+        BGPMessage::Open(ref open) => json!({
+            "type": "open",
+            "version": open.version,
+            "my_asn": open.my_asn,
+            "hold_time": open.hold_time,
+            "bgp_id": open.bgp_id.to_string(),
+            "opt_params": hex::encode(&open.opt_params),
+        }),
+        BGPMessage::Update(ref update) => json!({
+            "type": "update",
+            "withdrawn_routes": prefixes(&update.withdrawn_routes),
+            "attributes": path_attributes(&update.path_attributes, asn_size),
+            "nlri": prefixes(&update.nlri),
+        }),
+        BGPMessage::Notification(ref notification) => json!({
+            "type": "notification",
+            "error_code": notification.error_code,
+            "error_subcode": notification.error_subcode,
+            "data": hex::encode(&notification.data),
+        }),
+        BGPMessage::Keepalive => json!({"type": "keepalive"}),
+        BGPMessage::RouteRefresh => json!({"type": "route_refresh"}),
+        BGPMessage::Unknown(code) => json!({"type": "unknown", "code": code}),
+    }
+}
+
+fn rib_entries(entries: &[RibEntry], peers: &[PeerEntry]) -> Value {
+    entries
+        .iter()
+        .map(|entry| {
+            let peer = peers.get(entry.peer_index as usize);
+            json!({
+                "peer_index": entry.peer_index,
+                "peer_address": peer.map(|p| p.peer_address.to_string()),
+                "peer_asn": peer.map(|p| p.peer_asn),
+                "originated_time": entry.originated_timestamp,
+                "attributes": path_attributes(&entry.bgp_path_attrs, AsnSize::Four),
+            })
+        })
+        .collect()
+}
+
+fn record_body(record: &MRTRecord, peers: &[PeerEntry]) -> Value {
+    match *record {
+        MRTRecord::TableDumpIPv4(ref r) => {
+            let mut object = json!({
+                "kind": "table_dump",
+                "view_number": r.view_number,
+                "sequence_number": r.sequence_number,
+                "prefix": r.prefix().to_string(),
+                "status": r.status,
+                "originated_time": r.originated_time,
+                "peer_address": r.peer_address.to_string(),
+                "peer_asn": r.peer_asn,
+            });
+            set_attributes(&mut object, r.attributes(), AsnSize::Two);
+            object
+        }
+        MRTRecord::TableDumpIPv6(ref r) => {
+            let mut object = json!({
+                "kind": "table_dump",
+                "view_number": r.view_number,
+                "sequence_number": r.sequence_number,
+                "prefix": r.prefix().to_string(),
+                "status": r.status,
+                "originated_time": r.originated_time,
+                "peer_address": r.peer_address.to_string(),
+                "peer_asn": r.peer_asn,
+            });
+            set_attributes(&mut object, r.attributes(), AsnSize::Two);
+            object
+        }
+        MRTRecord::PeerIndexTable(ref index) => {
+            let entries: Vec<Value> = index
+                .peer_entries
+                .iter()
+                .enumerate()
+                .map(|(i, p)| {
+                    json!({
+                        "index": i,
+                        "peer_type": p.peer_type,
+                        "peer_bgp_id": p.peer_bgp_id.to_string(),
+                        "peer_address": p.peer_address.to_string(),
+                        "peer_asn": p.peer_asn,
+                    })
+                })
+                .collect();
+            json!({
+                "kind": "peer_index_table",
+                "collector_bgp_id": index.collector_bgp_id.to_string(),
+                "view_name": String::from_utf8_lossy(&index.view_name),
+                "peers": entries,
+            })
+        }
+        MRTRecord::RibIPv4Unicast(ref r) => json!({
+            "kind": "rib",
+            "sequence_number": r.sequence_number,
+            "prefix": r.prefix().to_string(),
+            "entries": rib_entries(&r.rib_entries, peers),
+        }),
+        MRTRecord::RibIPv6Unicast(ref r) => json!({
+            "kind": "rib",
+            "sequence_number": r.sequence_number,
+            "prefix": r.prefix().to_string(),
+            "entries": rib_entries(&r.rib_entries, peers),
+        }),
+        MRTRecord::BGP4MPStateChange(ref state) => json!({
+            "kind": "bgp4mp_state_change",
+            "peer": bgp4mp_peer(&state.peer),
+            "old_state": state.old_state,
+            "new_state": state.new_state,
+            "old_state_name": state_name(state.old_state),
+            "new_state_name": state_name(state.new_state),
+        }),
+        MRTRecord::BGP4MPMessage(ref msg) => json!({
+            "kind": "bgp4mp_message",
+            "peer": bgp4mp_peer(&msg.peer),
+            "asn_size": match msg.asn_size {
+                AsnSize::Two => 2,
+                AsnSize::Four => 4,
+            },
+            "message": bgp_message(&msg.message, msg.asn_size),
+        }),
+        MRTRecord::Unsupported => json!({"kind": "unsupported"}),
+    }
+}
+
+// The JSON object for one record, in the schema described above. RIB
+// entries are resolved against the peers of the preceding PEER_INDEX_TABLE.
+pub fn record_to_json(header: &MRTHeader, record: &MRTRecord, peers: &[PeerEntry]) -> Value {
+    let (type_name, subtype_name) = type_names(header.mrt_type, header.mrt_subtype);
+    json!({
+        "timestamp": header.timestamp,
+        "microsecond_timestamp": header.microsecond_timestamp,
+        "mrt_type": header.mrt_type,
+        "mrt_subtype": header.mrt_subtype,
+        "mrt_type_name": type_name,
+        "mrt_subtype_name": subtype_name,
+        "length": header.length,
+        "record": record_body(record, peers),
+    })
+}
+
+// Write one record as a single line of JSON.
+pub fn write_json<W: Write>(
+    out: &mut W,
+    source: Option<&Provenance>,
+    header: &MRTHeader,
+    record: &MRTRecord,
+    peers: &[PeerEntry],
+) -> io::Result<()> {
+    let mut value = record_to_json(header, record, peers);
+    if let Some(source) = source {
+        value["source"] = json!({
+            "file": source.file,
+            "offset": source.offset,
+            "collector": source.collector,
+        });
+    }
+    serde_json::to_writer(&mut *out, &value)?;
+    writeln!(out)
+}
+
+#[test]
+fn record_to_json_table_dump_test() {
+    use fixtures::table_dump;
+    use mrt::{parse_mrt_body, split_mrt_record};
+
+    let buffer = table_dump();
+
+    let (_, header, body) = split_mrt_record(&buffer).unwrap();
+    let record = parse_mrt_body(&header, body).unwrap();
+    let value = record_to_json(&header, &record, &[]);
+
+    assert_eq!(value["mrt_type_name"], "TABLE_DUMP");
+    assert_eq!(value["mrt_subtype_name"], "AFI_IPv4");
+    assert_eq!(value["microsecond_timestamp"], Value::Null);
+    assert_eq!(value["record"]["kind"], "table_dump");
+    assert_eq!(value["record"]["prefix"], "3.0.0.0/8");
+    assert_eq!(value["record"]["peer_asn"], 3257);
+
+    let attrs = &value["record"]["attributes"];
+    assert_eq!(attrs[0], json!({"code": 1, "flags": 0x40, "name": "ORIGIN", "value": "IGP"}));
+    assert_eq!(
+        attrs[1]["value"],
+        json!([{"type": "AS_SEQUENCE", "asns": [3257, 701, 80]}])
+    );
+    assert_eq!(attrs[2]["value"], "193.148.15.85");
+
+    let mut out = Vec::new();
+    let source = Provenance { file: "-".to_string(), offset: 0, collector: None };
+    write_json(&mut out, Some(&source), &header, &record, &[]).unwrap();
+    let line = String::from_utf8(out).unwrap();
+    assert!(line.ends_with("}\n"));
+    assert_eq!(line.matches('\n').count(), 1);
+    let value: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(value["source"], json!({"file": "-", "offset": 0, "collector": null}));
+    assert_eq!(value["record"].get("attributes_error"), None);
+
+    // an ORIGIN running past the end of the attributes
+    let mut buffer = buffer;
+    let at = buffer.len() - 22 + 2;
+    buffer[at] = 0x30;
+    let (_, header, body) = split_mrt_record(&buffer).unwrap();
+    let record = parse_mrt_body(&header, body).unwrap();
+    let value = record_to_json(&header, &record, &[]);
+    assert_eq!(value["record"]["attributes"], json!([]));
+    assert!(value["record"]["attributes_error"].is_string());
+}
@@ -0,0 +1,65 @@
+#[macro_use]
+extern crate num_derive;
+extern crate num_traits;
+
+#[macro_use]
+extern crate nom;
+
+extern crate hex;
+extern crate regex;
+
+#[macro_use]
+extern crate serde_json;
+
+#[cfg(feature = "serde")]
+extern crate serde;
+
+#[cfg(feature = "bzip2")]
+extern crate bzip2;
+#[cfg(feature = "gzip")]
+extern crate flate2;
+#[cfg(feature = "xz")]
+extern crate xz2;
+#[cfg(feature = "zstd")]
+extern crate zstd;
+
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+
+#[cfg(feature = "arrow")]
+extern crate arrow_array;
+#[cfg(feature = "arrow")]
+extern crate arrow_schema;
+#[cfg(feature = "parquet")]
+extern crate parquet;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
+
+pub mod bgp;
+pub mod bgp4mp;
+pub mod bgpdump;
+pub mod bgpsec;
+#[cfg(feature = "arrow")]
+pub mod columnar;
+pub mod compression;
+pub mod convert;
+pub mod delimited;
+pub mod encode;
+pub mod expression;
+pub mod filter;
+#[cfg(test)]
+mod fixtures;
+pub mod index;
+pub mod json;
+pub mod merge;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod mrt;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod route;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stats;
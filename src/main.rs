@@ -1,549 +1,969 @@
-mod bgp;
-use bgp::{parse_bgp_path_attrs, BGPPathAttribute};
-
-#[macro_use]
-extern crate num_derive;
-extern crate num_traits;
-use num_traits::cast::FromPrimitive;
-
-#[macro_use]
-extern crate nom;
-use nom::{be_u128, be_u16, be_u32, be_u8, IResult};
-
+#[cfg(feature = "gzip")]
 extern crate flate2;
-use flate2::bufread::GzDecoder;
+extern crate getopts;
+extern crate glob;
+extern crate mrt_parser;
+
+#[cfg(feature = "gzip")]
+use flate2::write::GzEncoder;
+#[cfg(feature = "gzip")]
+use flate2::Compression;
+use getopts::Options;
+use mrt_parser::bgpdump;
+#[cfg(feature = "parquet")]
+use mrt_parser::columnar::ParquetRouteWriter;
+use mrt_parser::compression;
+use mrt_parser::convert::TableDumpV2Converter;
+use mrt_parser::delimited::{self, Field};
+use mrt_parser::encode::MRTWriter;
+use mrt_parser::expression::{parse_afi, parse_time};
+use mrt_parser::filter::{
+    parse_record_type, AsPathRegex, FilteredMRTWriter, PrefixFilter, PrefixMatch, RouteFilter,
+};
+use mrt_parser::index::{index_path, Index, IndexedReader};
+use mrt_parser::json;
+use mrt_parser::merge::MergedReader;
+#[cfg(feature = "mmap")]
+use mrt_parser::mmap::MappedFile;
+use mrt_parser::mrt::{HeaderFilter, MRTHeader, MRTReader, MRTRecord, PeerEntry, Provenance};
+#[cfg(feature = "rayon")]
+use mrt_parser::parallel::{self, ParallelReader};
+use mrt_parser::route::{self, Route};
+#[cfg(feature = "sqlite")]
+use mrt_parser::sqlite::SqliteExporter;
+use mrt_parser::stats::Stats;
 use std::env;
-use std::fmt;
 use std::fs::File;
-use std::io::{self, BufReader, BufWriter, Read, Stdout, Write};
-use std::mem::size_of;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::Deref;
+use std::path::Path;
+#[cfg(feature = "rayon")]
+use std::sync::mpsc;
+#[cfg(feature = "rayon")]
+use std::thread;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::result::Result;
 
-#[derive(Debug)]
-pub struct MRTHeader {
-    timestamp: u32,
-    mrt_type: u16,
-    mrt_subtype: u16,
-    length: u32,
-}
-
-#[allow(non_camel_case_types)]
-#[derive(Debug, FromPrimitive)]
-enum MRTType {
-    OSPFv2 = 11,
-    TABLE_DUMP = 12,
-    TABLE_DUMP_V2 = 13,
-    BGP4MP = 16,
-    BGP4MP_ET = 17,
-    ISIS = 32,
-    ISIS_ET = 33,
-    OSPFv3 = 48,
-    OSPFv3_ET = 49,
-}
-#[allow(non_camel_case_types)]
-#[derive(Debug, FromPrimitive)]
-enum TableDumpSubtypes {
-    AFI_IPv4 = 1,
-    AFI_IPv6 = 2,
-}
-
-#[allow(non_camel_case_types)]
-#[derive(Debug, FromPrimitive)]
-enum TableDumpV2Subtypes {
-    PEER_INDEX_TABLE = 1,
-    RIB_IPV4_UNICAST = 2,
-    RIB_IPV4_MULTICAST = 3,
-    RIB_IPV6_UNICAST = 4,
-    RIB_IPV6_MULTICAST = 5,
-    RIB_GENERIC = 6,
-}
-
-named!(pub parse_mrt_table_header<MRTHeader>,
-    do_parse!(
-        timestamp:   be_u32 >>
-        mrt_type:    be_u16 >>
-        mrt_subtype: be_u16 >>
-        length:      be_u32 >>
-        (MRTHeader { timestamp, mrt_type, mrt_subtype, length })
-    )
-);
-
-impl<'a> fmt::Display for MRTHeader {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        match MRTType::from_u16(self.mrt_type) {
-            Some(MRTType::TABLE_DUMP) => {
-                write!(fmt, "TABLE_DUMP|");
-                match TableDumpSubtypes::from_u16(self.mrt_subtype) {
-                    Some(TableDumpSubtypes::AFI_IPv4) => {
-                        write!(fmt, "AFI_IPv4");
-                    }
-                    Some(TableDumpSubtypes::AFI_IPv6) => {
-                        write!(fmt, "AFI_IPv6");
-                    }
-                    _ => {
-                        write!(fmt, "Unhandled MRT TABLE_DUMP subtype {}", self.mrt_subtype);
-                    }
-                }
-            }
-            Some(MRTType::TABLE_DUMP_V2) => {
-                write!(fmt, "TABLE_DUMP_V2|");
-                match TableDumpV2Subtypes::from_u16(self.mrt_subtype) {
-                    Some(TableDumpV2Subtypes::PEER_INDEX_TABLE) => {
-                        write!(fmt, "PEER_INDEX_TABLE");
-                    }
-                    Some(TableDumpV2Subtypes::RIB_IPV4_UNICAST) => {
-                        write!(fmt, "RIB_IPV4_UNICAST");
-                    }
-                    Some(TableDumpV2Subtypes::RIB_IPV4_MULTICAST) => {
-                        write!(fmt, "RIB_IPV4_MULTICAST");
-                    }
-                    Some(TableDumpV2Subtypes::RIB_IPV6_UNICAST) => {
-                        write!(fmt, "RIB_IPV6_UNICAST");
-                    }
-                    Some(TableDumpV2Subtypes::RIB_IPV6_MULTICAST) => {
-                        write!(fmt, "RIB_IPV6_MULTICAST");
-                    }
-                    Some(TableDumpV2Subtypes::RIB_GENERIC) => {
-                        write!(fmt, "RIB_GENERIC");
-                    }
-                    _ => {
-                        write!(
-                            fmt,
-                            "Unhandled MRT TABLE_DUMP_V2 subtype {}",
-                            self.mrt_subtype
-                        );
-                    }
-                }
-            }
-            _ => {
-                write!(fmt, "Unhandled MRT Type {}", self.mrt_type);
-            }
+// The library's test fixtures; their crate paths resolve through these.
+#[cfg(test)]
+use mrt_parser::{encode, mrt};
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "fixtures.rs"]
+mod fixtures;
+
+enum OutputMode {
+    // bgpdump
+    Verbose,
+    // bgpdump -m
+    OneLine,
+    // bgpdump -M
+    OneLineHuman,
+    // one JSON object per line
+    Json,
+    // one CSV/TSV row per route
+    Delimited(char, Vec<Field>),
+}
+
+fn write_record<W: Write>(
+    out: &mut W,
+    mode: &OutputMode,
+    source: &Provenance,
+    header: &MRTHeader,
+    record: &MRTRecord,
+    peers: &[PeerEntry],
+) -> io::Result<()> {
+    match *mode {
+        OutputMode::Verbose => bgpdump::write_verbose(out, header, record, peers),
+        OutputMode::OneLine => bgpdump::write_oneline(out, header, record, peers),
+        OutputMode::OneLineHuman => bgpdump::write_oneline_human(out, header, record, peers),
+        OutputMode::Json => json::write_json(out, Some(source), header, record, peers),
+        OutputMode::Delimited(delimiter, ref fields) => {
+            let routes = route::routes(header, record, peers).map_err(invalid_data)?;
+            delimited::write_routes(out, delimiter, fields, Some(source), &routes)
         }
-        write!(fmt, "|{}|", self.timestamp);
-        Ok(())
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub struct MRTTableDumpIPv4<'a> {
-    view_number: u16,
-    sequence_number: u16,
-    prefix: Ipv4Addr,
-    prefix_length: u8,
-    status: u8,
-    originated_time: u32,
-    peer_address: Ipv4Addr,
-    peer_asn: u16,
-    attr_length: u16,
-    as_path: &'a [u8],
-}
-
-// Mimic bgpdump output for now
-// bgpdump:
-// TABLE_DUMP|992216782|B|193.148.15.85|3257|3.0.0.0/8|3257 701 80|IGP|193.148.15.85|0|0||NAG||
-// this:
-// MRTHeader { timestamp: 992216782, mrt_type: 12, mrt_subtype: 1, length: 44 }
-//TABLE_DUMP|992207428|B|193.148.15.85|3257|3.0.0.0/8|16:[40, 01, 01, 00, 40, 02, 08, 02, 03, 0c, b9, 02, bd, 00, 50, 40, 03, 04, c1, 94, 0f, 55]|IGP|193.148.15.85|0|0||NAG||
-
-impl<'a> fmt::Display for MRTTableDumpIPv4<'a> {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        let prefix = format!("{}/{}", self.prefix, self.prefix_length);
-        let path = format!("{:02x}:{:02x?}", self.attr_length, self.as_path);
-        let str = [
-            "TABLE_DUMP",
-            &*self.originated_time.to_string(),
-            "B", // this looks hard-wired to B in bgpdump source
-            &*self.peer_address.to_string(),
-            &*self.peer_asn.to_string(),
-            &*prefix,
-            &*path,                          // as path
-            "IGP",                           // describe_origin
-            &*self.peer_address.to_string(), // next hop
-            "0",                             // npref
-            "0",                             // nmed
-            "",                              // community
-            "NAG",                           // aggregate
-            "",
-            "",
-        ];
-        fmt.write_str(&*str.join("|"));
-        Ok(())
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+// Problems with the input that do not stop it being read, counted so that
+// a summary can be given at the end.
+fn warn(message: &str) {
+    WARNINGS.fetch_add(1, Ordering::Relaxed);
+    eprintln!("warning: {}", message);
+}
+
+// Records that failed to decode, for --stats.
+static UNDECODABLE: AtomicUsize = AtomicUsize::new(0);
+
+fn invalid_data(e: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+// An input file, with the collector it was tagged with as NAME=PATTERN.
+struct Input {
+    path: String,
+    collector: Option<String>,
+}
+
+// The input files in order, and whether their records are read one file
+// after the other or merged into one timeline.
+struct Inputs {
+    files: Vec<Input>,
+    merge: bool,
+    // decode each input on the rayon thread pool, keeping the records in
+    // input order unless unordered is set
+    #[cfg(feature = "rayon")]
+    parallel: bool,
+    #[cfg(feature = "rayon")]
+    unordered: bool,
+    // read only the records that each input's index lists for these
+    // prefixes
+    seek: Option<PrefixFilter>,
+    // records to pass over undecoded
+    headers: HeaderFilter,
+}
+
+// Split a NAME= collector tag off an argument, unless the whole argument
+// names a file.
+fn split_collector(arg: &str) -> (Option<&str>, &str) {
+    match arg.split_once('=') {
+        Some((name, pattern))
+            if !name.is_empty()
+                && name.chars().all(|c| c.is_alphanumeric() || "-_.".contains(c))
+                && !Path::new(arg).exists() =>
+        {
+            (Some(name), pattern)
+        }
+        _ => (None, arg),
     }
 }
 
-//        0                   1                   2                   3
-//        0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
-//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-//       |         View Number           |       Sequence Number         |
-//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-//       |                        Prefix (variable)                      |
-//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-//       | Prefix Length |    Status     |
-//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-//       |                         Originated Time                       |
-//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-//       |                    Peer IP Address (variable)                 |
-//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-//       |           Peer AS             |       Attribute Length        |
-//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-//       |                   BGP Attribute... (variable)
-//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-//
-//                         Figure 4: TABLE_DUMP Type
-
-named!(pub parse_mrt_table_dump_ipv4<MRTTableDumpIPv4>,
-    do_parse!(
-        view_number:     be_u16 >>
-        sequence_number: be_u16 >>
-        prefix:          be_u32 >>
-        prefix_length:   be_u8  >>
-        status:          be_u8  >>
-        originated_time: be_u32 >>
-        peer_address:    be_u32 >>
-        peer_asn:        be_u16 >>
-        attr_length:     be_u16 >>
-        as_path:         take!(attr_length)        >>
-    (MRTTableDumpIPv4 {
-        view_number,
-        sequence_number,
-        prefix:          Ipv4Addr::from(prefix),
-        prefix_length,
-        status,
-        originated_time,
-        peer_address:    Ipv4Addr::from(peer_address),
-        peer_asn,
-        attr_length,
-        as_path
-    })
-    )
-);
-
-#[derive(Debug)]
-pub struct MRTTableDumpIPv6<'a> {
-    view_number: u16,
-    sequence_number: u16,
-    prefix: Ipv6Addr,
-    prefix_length: u8,
-    status: u8,
-    originated_time: u32,
-    peer_address: Ipv6Addr,
-    peer_asn: u16,
-    attr_length: u16,
-    as_path: &'a [u8],
-}
-
-named!(pub parse_mrt_table_dump_ipv6<MRTTableDumpIPv6>,
-    do_parse!(
-        view_number:     be_u16 >>
-        sequence_number: be_u16 >>
-        prefix:          be_u128 >>
-        prefix_length:   be_u8  >>
-        status:          be_u8  >>
-        originated_time: be_u32 >>
-        peer_address:    be_u128 >>
-        peer_asn:        be_u16 >>
-        attr_length:     be_u16 >>
-        as_path:         take!(attr_length)        >>
-    (MRTTableDumpIPv6 {
-        view_number,
-        sequence_number,
-        prefix:          Ipv6Addr::from(prefix),
-        prefix_length,
-        status,
-        originated_time,
-        peer_address:    Ipv6Addr::from(peer_address),
-        peer_asn,
-        attr_length,
-        as_path
-    })
-    )
-);
-
-fn parse_mrt_table_dump<'a>(
-    out: &mut BufWriter<Stdout>,
-    header: MRTHeader,
-    reader: &'a [u8],
-) -> ::std::result::Result<&'a [u8], String> {
-    match TableDumpSubtypes::from_u16(header.mrt_subtype) {
-        Some(TableDumpSubtypes::AFI_IPv4) => {
-            let result = parse_mrt_table_dump_ipv4(&reader).unwrap();
-            writeln!(out, "{}", result.1);
-            return Ok(result.0);
+// The input files named on the command line, in order: "-" is stdin,
+// arguments with glob characters are expanded (sorted by name) and anything
+// else is taken as a path. Any of these can be tagged as NAME=... with the
+// collector the files come from.
+fn expand_inputs(args: &[String]) -> Result<Vec<Input>, String> {
+    let mut inputs = Vec::new();
+    for arg in args {
+        let (collector, pattern) = split_collector(arg);
+        let input = |path: String| Input { path, collector: collector.map(String::from) };
+        if pattern == "-" || !pattern.contains(['*', '?', '[']) {
+            inputs.push(input(pattern.to_string()));
+            continue;
         }
-        Some(TableDumpSubtypes::AFI_IPv6) => {
-            let result = parse_mrt_table_dump_ipv6(&reader).unwrap();
-            writeln!(out, "{:?}", result.1);
-            return Ok(result.0);
+        let paths = glob::glob(pattern).map_err(|e| format!("Bad pattern '{}': {}", pattern, e))?;
+        let before = inputs.len();
+        for path in paths {
+            let path = path.map_err(|e| e.to_string())?;
+            inputs.push(input(path.to_string_lossy().into_owned()));
         }
-        _ => {
-            writeln!(out, "Unhandled subtype {}", header.mrt_type);
+        if inputs.len() == before {
+            return Err(format!("No files match '{}'", pattern));
         }
     }
-    Err("No match".to_string())
-}
-
-//        0                   1                   2                   3
-//        0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
-//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-//       |                      Sequence Number = 42                     |
-//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-//       | Preflen = 32  |
-//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-//       |                 Prefix  =  2001:0DB8::/32                     |
-//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-//       |    Entry Count = 1            |
-//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-//       |    Peer Index =  15           |
-//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-//       |Originated Time = 1300475700 epoch sec (2011-03-18 19:15:00)   |
-//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-//       |   Attribute Length  =  68     |
-//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-//       |   BGP Path Attributes =
-
-#[derive(Debug)]
-pub struct MRTTableDumpV2PeerIndex<'a> {
-    collector_bgp_id: u16,
-    view_name_length: u8,
-    view_name: &'a [u8],
-    peer_count: u8,
-    peer_entries: &'a [u8],
-}
-
-named!(pub parse_mrt_table_dump_v2_peer_index<MRTTableDumpV2PeerIndex>,
-    do_parse!(
-        collector_bgp_id: be_u16 >>
-        view_name_length: be_u8  >>
-        view_name:        take!(view_name_length) >>
-        peer_count:       be_u8  >>
-        peer_entries:     take!(view_name_length) >>
-    (MRTTableDumpV2PeerIndex { collector_bgp_id, view_name_length, view_name, peer_count, peer_entries })
-    )
-);
-
-// RIB Entries
-#[derive(Debug)]
-pub struct RibEntry {
-    peer_index: u16,
-    originated_timestamp: u32,
-    attr_length: u16,
-    bgp_path_attrs: Vec<BGPPathAttribute>,
-}
-
-fn parse_rib_entry(input: &[u8]) -> IResult<&[u8], RibEntry> {
-    do_parse!(
-        input,
-        peer_index: be_u16
-            >> originated_timestamp: be_u32
-            >> attr_length: be_u16
-            >> bgp_path_attrs: call!(parse_bgp_path_attrs, attr_length)
-            >> (RibEntry {
-                peer_index,
-                originated_timestamp,
-                attr_length,
-                bgp_path_attrs
-            })
-    )
+    Ok(inputs)
+}
+
+// The contents of an input: decompressed into memory or, for uncompressed
+// files with the "mmap" feature, mapped.
+enum Buffer {
+    Read(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(MappedFile),
 }
 
-impl fmt::Display for RibEntry {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        for i in &self.bgp_path_attrs {
-            write!(fmt, "|{}", i);
+impl Deref for Buffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match *self {
+            Buffer::Read(ref data) => data,
+            #[cfg(feature = "mmap")]
+            Buffer::Mapped(ref map) => map,
         }
-        Ok(())
     }
 }
 
-named_args!( parse_rib_entries(entry_count: u16)< Vec<RibEntry> >,
-    count!( parse_rib_entry, entry_count as usize )
-);
+fn read_input(path: &str) -> io::Result<Buffer> {
+    #[cfg(feature = "mmap")]
+    {
+        if path != "-" {
+            if let Some(map) = MappedFile::open(path).map_err(io::Error::other)? {
+                return Ok(Buffer::Mapped(map));
+            }
+        }
+    }
+    let input = compression::read_file(path).map_err(io::Error::other)?;
+    if let Some(ref warning) = input.warning {
+        warn(&format!("{}: {}", path, warning));
+    }
+    Ok(Buffer::Read(input.data))
+}
 
-#[derive(Debug)]
-pub struct MRTTableDumpV2IPv4Unicast<'b> {
-    sequence_number: u32,
-    prefix_length: u8,
-    prefix: &'b [u8],
-    entry_count: u16,
-    rib_entries: Vec<RibEntry>,
+// Hand one record, or the error reading it, to f. Records that fail to
+// decode, or that f finds invalid, are reported; any other error from f is
+// returned.
+fn visit<F>(
+    f: &mut F,
+    source: &Provenance,
+    entry: Result<(MRTHeader, MRTRecord), String>,
+    peers: &[PeerEntry],
+    trailing_bytes: usize,
+) -> io::Result<()>
+where
+    F: FnMut(&Provenance, &MRTHeader, MRTRecord, &[PeerEntry]) -> io::Result<()>,
+{
+    match entry {
+        Ok((header, record)) => {
+            match f(source, &header, record, peers) {
+                Err(ref e)
+                    if e.kind() == io::ErrorKind::InvalidData
+                        || e.kind() == io::ErrorKind::InvalidInput =>
+                {
+                    eprintln!("{}: {}: {}", source, header, e)
+                }
+                result => return result,
+            }
+        }
+        Err(e) => {
+            UNDECODABLE.fetch_add(1, Ordering::Relaxed);
+            if trailing_bytes > 0 {
+                warn(&format!(
+                    "{}: {} trailing bytes are not a valid MRT record: {}",
+                    source, trailing_bytes, e
+                ))
+            } else {
+                eprintln!("{}: {}", source, e)
+            }
+        }
+    }
+    Ok(())
 }
 
-fn make_addr(prefix: &[u8]) -> Ipv4Addr {
-    let mut prefix_u32: u32 = 0;
-    for i in 0..prefix.len() {
-        prefix_u32 |= (prefix[i] as u32) << (24 - 8 * i);
+fn update_peers(peers: &mut Vec<PeerEntry>, entry: &Result<(MRTHeader, MRTRecord), String>) {
+    if let Ok((_, MRTRecord::PeerIndexTable(ref index))) = *entry {
+        *peers = index.peer_entries.clone();
     }
-    let outputaddr = Ipv4Addr::from(prefix_u32);
-    outputaddr
 }
 
-impl<'a> fmt::Display for MRTTableDumpV2IPv4Unicast<'a> {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        let prefix = format!("{}/{}", make_addr(self.prefix), self.prefix_length);
+// Run f over every record of the inputs, with where it came from and the
+// peers of the latest PEER_INDEX_TABLE in the same input. Records that fail
+// to decode, or that f finds invalid, are reported and skipped; an input
+// that cannot be read, or any other error from f, stops the run.
+fn each_record<F>(inputs: &Inputs, mut f: F) -> io::Result<()>
+where
+    F: FnMut(&Provenance, &MRTHeader, MRTRecord, &[PeerEntry]) -> io::Result<()>,
+{
+    if inputs.merge {
+        return each_merged_record(inputs, f);
+    }
+    for input in &inputs.files {
+        let mut source =
+            Provenance { file: input.path.clone(), offset: 0, collector: input.collector.clone() };
+        if let Some(ref prefixes) = inputs.seek {
+            each_indexed_record(prefixes, &inputs.headers, &mut source, &mut f)?;
+            continue;
+        }
+        let buffer = read_input(&input.path)?;
+        #[cfg(feature = "rayon")]
+        {
+            if inputs.parallel {
+                each_parallel_record(&buffer, inputs, &mut source, &mut f)?;
+                continue;
+            }
+        }
+        let mut peers: Vec<PeerEntry> = Vec::new();
+        let mut reader = MRTReader::new(&buffer).filter(inputs.headers.clone());
+        while let Some(entry) = reader.next() {
+            source.offset = reader.offset();
+            update_peers(&mut peers, &entry);
+            visit(&mut f, &source, entry, &peers, reader.trailing_bytes())?;
+        }
+    }
+    Ok(())
+}
 
-        let line_prefix = format!("{:08x}|{}|", self.sequence_number, String::from(prefix));
-        for i in &self.rib_entries {
-            write!(fmt, "\n{} {}", line_prefix, i);
+// each_record's reading of one input, with the records decoded on the rayon
+// thread pool. f still runs on this thread: unordered, the records are sent
+// back to it as they are decoded.
+#[cfg(feature = "rayon")]
+fn each_parallel_record<F>(
+    buffer: &[u8],
+    inputs: &Inputs,
+    source: &mut Provenance,
+    f: &mut F,
+) -> io::Result<()>
+where
+    F: FnMut(&Provenance, &MRTHeader, MRTRecord, &[PeerEntry]) -> io::Result<()>,
+{
+    let reader = ParallelReader::new(buffer).filter(inputs.headers.clone());
+    if !inputs.unordered {
+        for decoded in reader {
+            source.offset = decoded.offset;
+            visit(f, source, decoded.record, &decoded.peers, decoded.trailing_bytes)?;
+        }
+        return Ok(());
+    }
+    let (sender, receiver) = mpsc::sync_channel(parallel::DEFAULT_BATCH_SIZE);
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            // a send fails once f has given up; the rest is just decoded
+            reader.for_each_unordered(|decoded| {
+                let _ = sender.send(decoded);
+            })
+        });
+        for decoded in receiver {
+            source.offset = decoded.offset;
+            visit(f, source, decoded.record, &decoded.peers, decoded.trailing_bytes)?;
         }
         Ok(())
+    })
+}
+
+// each_record's reading of one input through its index, seeking to the
+// records for prefixes. The index narrows the time window to the second;
+// headers decides the rest.
+fn each_indexed_record<F>(
+    prefixes: &PrefixFilter,
+    headers: &HeaderFilter,
+    source: &mut Provenance,
+    f: &mut F,
+) -> io::Result<()>
+where
+    F: FnMut(&Provenance, &MRTHeader, MRTRecord, &[PeerEntry]) -> io::Result<()>,
+{
+    let mut reader =
+        IndexedReader::open(&source.file, &index_path(&source.file)).map_err(io::Error::other)?;
+    let from = headers.from.map_or(0, |us| (us / 1_000_000) as u32);
+    let to = headers.to.map_or(u32::MAX, |us| (us / 1_000_000).min(u64::from(u32::MAX)) as u32);
+    let mut offsets: Vec<u64> = prefixes
+        .prefixes
+        .iter()
+        .flat_map(|p| reader.offsets(p, prefixes.mode, from, to))
+        .collect();
+    offsets.sort_unstable();
+    offsets.dedup();
+    let mut table = None;
+    for offset in offsets {
+        // each PEER_INDEX_TABLE goes before the first record read after it,
+        // as it would reading the whole file
+        let before = reader.index().peer_table_before(offset);
+        if before != table {
+            table = before;
+            if let Some(at) = table {
+                source.offset = at as usize;
+                match reader.read_peer_table(at) {
+                    Ok((header, record)) => visit(f, source, Ok((header, record)), &[], 0)?,
+                    Err(e) => visit(f, source, Err(e), &[], 0)?,
+                }
+            }
+        }
+        source.offset = offset as usize;
+        match reader.read_at(offset) {
+            Ok((header, _, _)) if !headers.matches(&header) => {}
+            Ok((header, record, peers)) => visit(f, source, Ok((header, record)), peers, 0)?,
+            Err(e) => visit(f, source, Err(e), &[], 0)?,
+        }
     }
+    Ok(())
 }
 
-fn prefix_octet_count(prefix_length: u8) -> u32 {
-    u32::from((prefix_length + 7) / 8)
+// The --stats profile of the inputs, read as for the other outputs. Records
+// that fail to decode are reported as usual and counted, as are those whose
+// routes the filter cannot work out.
+fn write_stats<W: Write>(out: &mut W, inputs: &Inputs, filter: &RouteFilter, json: bool) -> io::Result<()> {
+    let mut stats = Stats::new();
+    let undecodable = UNDECODABLE.load(Ordering::Relaxed);
+    each_record(inputs, |_, header, record, peers| {
+        match filter.apply(header, record, peers) {
+            Ok(Some(record)) => stats.add(header, &record, peers),
+            Ok(None) => {}
+            Err(_) => stats.add_undecodable(),
+        }
+        Ok(())
+    })?;
+    stats.undecodable += (UNDECODABLE.load(Ordering::Relaxed) - undecodable) as u64;
+    if json {
+        writeln!(out, "{}", stats.to_json())
+    } else {
+        stats.write_text(out)
+    }
 }
 
-// I had to break out of the macro here because it was grouching about lifetimes.
-//named_args!(pub parse_mrt_table_dump_v2_ipv4_unicast<'a>(header: &'a MRTHeader)<MRTTableDumpV2IPv4Unicast>,
-//
-// This is ugly but the number of bytes for this part of the message is:
-// length specific in header - sizeof(sequence_num) - sizeof(prefix_length) - prefix_octet_count - sizeof(entry_count)
-//rib_entries:     take!(header.length - (size_of::<u32>() as u32) - (size_of::<u8>() as u32) - prefix_octet_count(prefix_length) - (size_of::<u16>() as u32))  >>
-//rib_entries:     parse_rib_entry(input, header.length - (size_of::<u32>() as u32) - (size_of::<u8>() as u32) - prefix_octet_count(prefix_length) - (size_of::<u16>() as u32), entry_count) >>
-fn parse_mrt_table_dump_v2_ipv4_unicast<'a>(
-    input: &'a [u8],
-    header: &MRTHeader,
-) -> IResult<&'a [u8], MRTTableDumpV2IPv4Unicast<'a>> {
-    do_parse!(
-        input,
-        sequence_number: be_u32
-            >> prefix_length: be_u8
-            >> prefix: take!(prefix_octet_count(prefix_length))
-            >> entry_count: be_u16
-            >> rib_entries: call!(parse_rib_entries, entry_count)
-            >> (MRTTableDumpV2IPv4Unicast {
-                sequence_number,
-                prefix_length,
-                prefix,
-                entry_count,
-                rib_entries
-            })
-    )
+// Write the index of an uncompressed MRT file next to it.
+fn build_index(path: &str) -> Result<(), String> {
+    let mut magic = [0; 6];
+    let read = File::open(path)
+        .and_then(|mut file| file.read(&mut magic))
+        .map_err(|e| format!("{}: {}", path, e))?;
+    if compression::Compression::detect(&magic[..read]) != compression::Compression::None {
+        return Err(format!("{}: only uncompressed files can be indexed", path));
+    }
+    let buffer = read_input(path).map_err(|e| e.to_string())?;
+    Index::build(&buffer).write_file(&index_path(path))
 }
 
-#[derive(Debug)]
-pub struct MRTTableDumpV2IPv6Unicast<'a> {
-    sequence_number: u32,
-    prefix_length: u8,
-    prefix: &'a [u8],
-    entry_count: u16,
-    rib_entries: &'a [u8],
+// each_record with the records of all inputs merged by time, which needs
+// every input in memory at once. Untagged inputs are tagged with their path.
+fn each_merged_record<F>(inputs: &Inputs, mut f: F) -> io::Result<()>
+where
+    F: FnMut(&Provenance, &MRTHeader, MRTRecord, &[PeerEntry]) -> io::Result<()>,
+{
+    let buffers = inputs.files.iter().map(|i| read_input(&i.path)).collect::<io::Result<Vec<_>>>()?;
+    let mut merged = MergedReader::new().filter(inputs.headers.clone());
+    for (input, buffer) in inputs.files.iter().zip(&buffers) {
+        merged.add(input.collector.as_deref().unwrap_or(&input.path), buffer);
+    }
+    let mut peers: Vec<Vec<PeerEntry>> = vec![Vec::new(); buffers.len()];
+    while let Some(item) = merged.next() {
+        let input = &inputs.files[item.source];
+        let source = Provenance {
+            file: input.path.clone(),
+            offset: item.offset,
+            collector: Some(item.collector.to_string()),
+        };
+        let trailing_bytes = merged.trailing_bytes(item.source);
+        update_peers(&mut peers[item.source], &item.record);
+        visit(&mut f, &source, item.record, &peers[item.source], trailing_bytes)?;
+    }
+    Ok(())
 }
 
-//named!(pub parse_mrt_table_dump_v2_ipv6_unicast<MRTTableDumpV2IPv6Unicast>,
-fn parse_mrt_table_dump_v2_ipv6_unicast<'a>(
-    input: &'a [u8],
-    header: &MRTHeader,
-) -> IResult<&'a [u8], MRTTableDumpV2IPv6Unicast<'a>> {
-    do_parse!(
-        input,
-        sequence_number: be_u32
-            >> prefix_length: be_u8
-            >> prefix: take!(prefix_octet_count(prefix_length))
-            >> entry_count: be_u16
-            >> rib_entries:
-                take!(
-                    header.length
-                        - (size_of::<u32>() as u32)
-                        - (size_of::<u8>() as u32)
-                        - prefix_octet_count(prefix_length)
-                        - (size_of::<u16>() as u32)
-                )
-            >> (MRTTableDumpV2IPv6Unicast {
-                sequence_number,
-                prefix_length,
-                prefix,
-                entry_count,
-                rib_entries
-            })
-    )
-}
-
-fn parse_mrt_table_dump_v2<'a>(
-    out: &mut BufWriter<Stdout>,
-    header: MRTHeader,
-    reader: &'a [u8],
-) -> ::std::result::Result<&'a [u8], String> {
-    match TableDumpV2Subtypes::from_u16(header.mrt_subtype) {
-        Some(TableDumpV2Subtypes::PEER_INDEX_TABLE) => {
-            //let result = parse_mrt_table_dump_v2_peer_index(&reader)
-            let header_length = header.length as usize;
-            return Ok(&reader[header_length..]);
-        }
-        Some(TableDumpV2Subtypes::RIB_IPV4_UNICAST) => {
-            let result = parse_mrt_table_dump_v2_ipv4_unicast(&reader, &header).unwrap();
-            writeln!(out, "{}", result.1);
-            return Ok(result.0);
-        }
-        Some(TableDumpV2Subtypes::RIB_IPV4_MULTICAST) => {
-            return Err("TABLE_DUMP_V2 subtype RIB_IPV4_MULTICAST not implemented".to_string());
-        }
-        Some(TableDumpV2Subtypes::RIB_IPV6_UNICAST) => {
-            let result = parse_mrt_table_dump_v2_ipv6_unicast(&reader, &header).unwrap();
-            writeln!(out, "{:?}", result.1);
-            return Ok(result.0);
+// Run `load` over every record that passes the filter.
+#[cfg(any(feature = "parquet", feature = "sqlite"))]
+fn load_records<F>(inputs: &Inputs, filter: &RouteFilter, mut load: F) -> Result<(), String>
+where
+    F: FnMut(&MRTHeader, &MRTRecord, &[PeerEntry]) -> Result<(), String>,
+{
+    each_record(inputs, |_, header, record, peers| {
+        match filter.apply(header, record, peers).map_err(invalid_data)? {
+            Some(record) => load(header, &record, peers).map_err(invalid_data),
+            None => Ok(()),
         }
-        Some(TableDumpV2Subtypes::RIB_IPV6_MULTICAST) => {
-            return Err("TABLE_DUMP_V2 subtype RIB_IPV4_MULTICAST not implemented".to_string());
+    })
+    .map_err(|e| e.to_string())
+}
+
+// The records are filtered as for stdout first; the writer then only has to
+// leave out unused peer tables and renumber the sequence numbers.
+fn write_mrt<W: Write + ?Sized>(out: &mut W, inputs: &Inputs, filter: &RouteFilter) -> io::Result<()> {
+    let mut writer = FilteredMRTWriter::new(out, true, |_: &Route| true);
+    each_record(inputs, |_, header, record, peers| {
+        match filter.apply(header, record, peers).map_err(invalid_data)? {
+            Some(record) => writer.write(header, record),
+            None => Ok(()),
         }
-        Some(TableDumpV2Subtypes::RIB_GENERIC) => {
-            return Err("TABLE_DUMP_V2 subtype RIB_GENERIC not implemented".to_string());
+    })?;
+    writer.finish().map(|_| ())
+}
+
+// TABLE_DUMP records converted to TABLE_DUMP_V2; other records are
+// reported and dropped.
+fn write_tdv2<W: Write + ?Sized>(out: &mut W, inputs: &Inputs, filter: &RouteFilter) -> io::Result<()> {
+    let mut converter = TableDumpV2Converter::new();
+    each_record(inputs, |_, header, record, peers| {
+        match filter.apply(header, record, peers).map_err(invalid_data)? {
+            Some(record) => converter.add(header, &record).map_err(invalid_data),
+            None => Ok(()),
         }
-        _ => {
-            writeln!(out, "Unhandled subtype {}", header.mrt_type);
+    })?;
+    let mut writer = MRTWriter::new(out);
+    for (header, record) in converter.finish() {
+        writer.write_record(&header, &record)?;
+    }
+    writer.flush()
+}
+
+// Write the records left after filtering as MRT to path, or stdout for "-",
+// converting TABLE_DUMP to TABLE_DUMP_V2 if tdv2 is set.
+fn write_mrt_file(
+    path: &str,
+    gzip: bool,
+    tdv2: bool,
+    inputs: &Inputs,
+    filter: &RouteFilter,
+) -> Result<(), String> {
+    let write = |out: &mut dyn Write| {
+        if tdv2 {
+            write_tdv2(out, inputs, filter)
+        } else {
+            write_mrt(out, inputs, filter)
         }
+    };
+    let mut out: Box<dyn Write> = if path == "-" {
+        Box::new(BufWriter::new(io::stdout()))
+    } else {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        Box::new(BufWriter::new(file))
+    };
+    #[cfg(feature = "gzip")]
+    let result = if gzip {
+        let mut gz = GzEncoder::new(out, Compression::default());
+        write(&mut gz).and_then(|_| gz.finish()).and_then(|mut out| out.flush())
+    } else {
+        write(&mut out).and_then(|_| out.flush())
+    };
+    #[cfg(not(feature = "gzip"))]
+    let result = if gzip {
+        return Err("--gzip needs the \"gzip\" feature".to_string());
+    } else {
+        write(&mut out).and_then(|_| out.flush())
+    };
+    match result {
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.map_err(|e| format!("{}: {}", path, e)),
     }
-    Err("No match".to_string())
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet(path: &str, inputs: &Inputs, filter: &RouteFilter) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut writer = ParquetRouteWriter::new(BufWriter::new(file))?;
+    load_records(inputs, filter, |header, record, peers| {
+        writer.write(&route::routes(header, record, peers)?)
+    })?;
+    writer.close()
+}
+
+#[cfg(feature = "sqlite")]
+fn write_sqlite(path: &str, inputs: &Inputs, filter: &RouteFilter) -> Result<(), String> {
+    let mut exporter = SqliteExporter::create(path)?;
+    load_records(inputs, filter, |header, record, peers| exporter.add_record(header, record, peers))?;
+    exporter.finish().map(|_| ())
+}
+
+fn parse_asns(args: &[String]) -> Result<Vec<u32>, String> {
+    args.iter().map(|a| a.parse().map_err(|_| format!("Bad ASN '{}'", a))).collect()
 }
 
 fn main() -> Result<(), String> {
+    let result = run();
+    let warnings = WARNINGS.load(Ordering::Relaxed);
+    if warnings > 0 {
+        eprintln!("{} warning{}", warnings, if warnings == 1 { "" } else { "s" });
+    }
+    result
+}
+
+fn run() -> Result<(), String> {
     let args: Vec<_> = env::args().collect();
-    if args.len() < 2 {
+
+    let mut opts = Options::new();
+    opts.optflag("m", "", "one-line per entry with unix timestamps (bgpdump -m)");
+    opts.optflag("M", "", "one-line per entry with human readable timestamps (bgpdump -M)");
+    opts.optflag("j", "json", "one JSON object per record (NDJSON)");
+    opts.optflag("", "csv", "one comma separated row per route");
+    opts.optflag("", "tsv", "one tab separated row per route");
+    opts.optopt(
+        "",
+        "fields",
+        "columns for --csv/--tsv (default: all): timestamp, type, peer_ip, peer_asn, prefix, \
+         as_path, origin_asn, origin, next_hop, communities, local_pref, med; also file, offset, collector",
+        "LIST",
+    );
+    #[cfg(feature = "parquet")]
+    opts.optopt("", "parquet", "write the routes to a Parquet file instead", "OUT");
+    #[cfg(feature = "sqlite")]
+    opts.optopt("", "sqlite", "load the records into a new SQLite database instead", "OUT");
+    opts.optopt(
+        "w",
+        "write",
+        "write the records left after filtering as MRT to OUT ('-' for stdout) instead",
+        "OUT",
+    );
+    opts.optflag("z", "gzip", "gzip compress the --write output");
+    opts.optflag("", "tdv2", "with --write, convert TABLE_DUMP records to TABLE_DUMP_V2");
+    opts.optmulti("", "prefix", "keep routes for PREFIX (see --prefix-match)", "PREFIX");
+    opts.optopt(
+        "",
+        "prefix-match",
+        "how --prefix matches: exact, more (the prefix or its more-specifics, the default) \
+         or less (the prefix or its less-specifics)",
+        "MODE",
+    );
+    opts.optopt(
+        "",
+        "filter",
+        "keep routes matching EXPR, e.g. \"peer_asn == 3356 and prefix <<= 10.0.0.0/8 \
+         and not community ~ 65535:666\" (fields: prefix, afi, peer_ip, peer_asn, \
+         origin_asn, timestamp, type, as_path, community)",
+        "EXPR",
+    );
+    opts.optmulti("", "peer", "keep routes learned from the peer at ADDRESS", "ADDRESS");
+    opts.optmulti("", "peer-asn", "keep routes learned from peer ASN", "ASN");
+    opts.optopt("", "afi", "keep routes of one address family: ipv4 or ipv6", "AFI");
+    opts.optopt(
+        "",
+        "from",
+        "skip records before TIME, in seconds since the epoch (e.g. 1700000000.5)",
+        "TIME",
+    );
+    opts.optopt("", "to", "skip records at or after TIME", "TIME");
+    opts.optmulti(
+        "",
+        "type",
+        "keep records of TYPE[:SUBTYPE], by name or number, e.g. bgp4mp:state_change \
+         or 13:4 (PEER_INDEX_TABLEs are always kept)",
+        "TYPE",
+    );
+    opts.optmulti("", "origin-asn", "keep routes originated by ASN", "ASN");
+    opts.optmulti("", "path-asn", "keep routes with ASN anywhere in the AS path", "ASN");
+    opts.optmulti(
+        "",
+        "as-path",
+        "keep routes whose AS path matches the Cisco-style REGEX, '_' matching \
+         any delimiter (e.g. _3356_)",
+        "REGEX",
+    );
+    opts.optmulti(
+        "",
+        "community",
+        "keep routes carrying a community matching PATTERN, '*' matching any part: \
+         65535:666, *:666, 64496:1:*, rt:65000:*",
+        "PATTERN",
+    );
+    opts.optflag(
+        "",
+        "merge",
+        "merge the records of all inputs into one timeline, each input being in time order; \
+         tag inputs with their collector as NAME=FILE",
+    );
+    #[cfg(feature = "rayon")]
+    opts.optflag("", "parallel", "decode each input's records on all cores (not with --merge)");
+    #[cfg(feature = "rayon")]
+    opts.optflag(
+        "",
+        "unordered",
+        "with --parallel, output records as they are decoded rather than in input order",
+    );
+    opts.optflag("", "index", "write an index of each uncompressed FILE to FILE.idx instead");
+    opts.optflag(
+        "",
+        "seek",
+        "with --prefix, read only the records FILE.idx lists for the prefixes",
+    );
+    opts.optflag(
+        "",
+        "stats",
+        "print a profile of the inputs instead of their records, as JSON with -j",
+    );
+    opts.optflag("h", "help", "print this help");
+    let matches = opts.parse(&args[1..]).map_err(|e| e.to_string())?;
+
+    if matches.opt_present("h") {
+        print!("{}", opts.usage(&format!("Usage: {} [options] [NAME=]FILE|GLOB|- ...", args[0])));
+        return Ok(());
+    }
+    if matches.free.is_empty() {
         return Err("Please provide filename".to_string());
     }
 
-    let filename = &args[1];
+    let fields = match matches.opt_str("fields") {
+        Some(spec) => delimited::parse_fields(&spec)?,
+        None => delimited::default_fields(),
+    };
+
+    let prefix_match = match matches.opt_str("prefix-match") {
+        Some(name) => PrefixMatch::from_name(&name)
+            .ok_or_else(|| format!("Unknown --prefix-match '{}'", name))?,
+        None => PrefixMatch::MoreSpecific,
+    };
+    let afi = match matches.opt_str("afi") {
+        Some(name) => Some(parse_afi(&name)?),
+        None => None,
+    };
+    let headers = HeaderFilter {
+        from: matches.opt_str("from").map(|t| parse_time(&t)).transpose()?,
+        to: matches.opt_str("to").map(|t| parse_time(&t)).transpose()?,
+        types: matches
+            .opt_strs("type")
+            .iter()
+            .map(|t| parse_record_type(t))
+            .collect::<Result<_, _>>()?,
+        afi,
+    };
+    let filter = RouteFilter {
+        afi,
+        peer_addresses: matches
+            .opt_strs("peer")
+            .iter()
+            .map(|a| a.parse().map_err(|_| format!("Bad peer address '{}'", a)))
+            .collect::<Result<_, _>>()?,
+        prefixes: PrefixFilter::new(
+            matches
+                .opt_strs("prefix")
+                .iter()
+                .map(|p| p.parse())
+                .collect::<Result<_, _>>()?,
+            prefix_match,
+        ),
+        peer_asns: parse_asns(&matches.opt_strs("peer-asn"))?,
+        origin_asns: parse_asns(&matches.opt_strs("origin-asn"))?,
+        path_asns: parse_asns(&matches.opt_strs("path-asn"))?,
+        as_paths: matches
+            .opt_strs("as-path")
+            .iter()
+            .map(|p| AsPathRegex::new(p))
+            .collect::<Result<_, _>>()?,
+        communities: matches
+            .opt_strs("community")
+            .iter()
+            .map(|c| c.parse())
+            .collect::<Result<_, _>>()?,
+        expression: matches.opt_str("filter").map(|e| e.parse()).transpose()?,
+    };
+
+    let mode = if matches.opt_present("csv") {
+        OutputMode::Delimited(',', fields)
+    } else if matches.opt_present("tsv") {
+        OutputMode::Delimited('\t', fields)
+    } else if matches.opt_present("j") {
+        OutputMode::Json
+    } else if matches.opt_present("m") {
+        OutputMode::OneLine
+    } else if matches.opt_present("M") {
+        OutputMode::OneLineHuman
+    } else {
+        OutputMode::Verbose
+    };
+
+    let mut inputs = Inputs {
+        files: expand_inputs(&matches.free)?,
+        merge: matches.opt_present("merge"),
+        #[cfg(feature = "rayon")]
+        parallel: matches.opt_present("parallel"),
+        #[cfg(feature = "rayon")]
+        unordered: matches.opt_present("unordered"),
+        seek: None,
+        headers,
+    };
+    #[cfg(feature = "rayon")]
+    {
+        if inputs.parallel && inputs.merge {
+            return Err("--parallel cannot be used with --merge".to_string());
+        }
+    }
+    if matches.opt_present("seek") {
+        if filter.prefixes.is_empty() {
+            return Err("--seek needs --prefix".to_string());
+        }
+        if inputs.merge {
+            return Err("--seek cannot be used with --merge".to_string());
+        }
+        inputs.seek = Some(filter.prefixes.clone());
+    }
+
+    if matches.opt_present("index") {
+        return inputs.files.iter().try_for_each(|input| build_index(&input.path));
+    }
 
-    let f = File::open(filename).expect("Cannot open file!");
-    let mut f = GzDecoder::new(BufReader::new(f));
+    #[cfg(feature = "parquet")]
+    {
+        if let Some(path) = matches.opt_str("parquet") {
+            return write_parquet(&path, &inputs, &filter);
+        }
+    }
+    #[cfg(feature = "sqlite")]
+    {
+        if let Some(path) = matches.opt_str("sqlite") {
+            return write_sqlite(&path, &inputs, &filter);
+        }
+    }
 
-    let mut buffer = Vec::new();
-    f.read_to_end(&mut buffer).expect("Cannot read file!");
-    let mut buffer = buffer.as_slice();
+    if let Some(path) = matches.opt_str("w") {
+        let (gzip, tdv2) = (matches.opt_present("z"), matches.opt_present("tdv2"));
+        return write_mrt_file(&path, gzip, tdv2, &inputs, &filter);
+    }
 
     let a = io::stdout();
     //let mut stdout = a.lock();
     let mut stdout = BufWriter::new(a);
 
-    loop {
-        // nom returns IResults which are aliases for Result<(I, O), Err<I, E>>;
-        // I: Remaining Input, O: Output, E: Error
-        let header = parse_mrt_table_header(&buffer).unwrap();
-
-        let result = header.1;
-        writeln!(stdout, "{}", result);
+    if matches.opt_present("stats") {
+        let json = matches.opt_present("j");
+        return write_stats(&mut stdout, &inputs, &filter, json)
+            .and_then(|_| stdout.flush())
+            .map_err(|e| e.to_string());
+    }
 
-        buffer = header.0;
+    if let OutputMode::Delimited(delimiter, ref fields) = mode {
+        delimited::write_header(&mut stdout, delimiter, fields).map_err(|e| e.to_string())?;
+    }
 
-        match MRTType::from_u16(result.mrt_type) {
-            Some(MRTType::TABLE_DUMP) => match parse_mrt_table_dump(&mut stdout, result, buffer) {
-                Ok(a) => {
-                    buffer = a;
-                }
-                Err(_err) => {}
-            },
-            Some(MRTType::TABLE_DUMP_V2) => {
-                match parse_mrt_table_dump_v2(&mut stdout, result, buffer) {
-                    Ok(a) => {
-                        buffer = a;
-                    }
-                    Err(_err) => {}
-                }
-            }
-            _ => {
-                writeln!(stdout, "Unhandled type {}", result.mrt_type);
-            }
+    let result = each_record(&inputs, |source, header, record, peers| {
+        match filter.apply(header, record, peers).map_err(invalid_data)? {
+            Some(record) => write_record(&mut stdout, &mode, source, header, &record, peers),
+            None => Ok(()),
         }
+    })
+    .and_then(|_| stdout.flush());
+    match result {
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.map_err(|e| e.to_string()),
+    }
+}
+
+// A dump of a PEER_INDEX_TABLE with peers AS65000 and AS65001, RIB records
+// for 198.51.100.0/24 (from both), 203.0.113.0/24 and 192.0.2.0/24 (from
+// AS65001) and a state change of AS65000, written to a file named name in
+// the temporary directory.
+#[cfg(test)]
+fn test_dump(name: &str) -> Inputs {
+    use fixtures::{encode_records, header};
+
+    let records = [
+        (13u16, 1u16, concat!(
+            "c0000201", "0000", "0002",
+            "00", "0a000001", "c0000201", "fde8",
+            "00", "0a000002", "c0000202", "fde9"
+        )),
+        (13, 2, concat!(
+            "00000000", "18", "c63364", "0002",
+            "0000", "3b23e244", "0004", "40010100",
+            "0001", "3b23e244", "0004", "40010100"
+        )),
+        (13, 2, concat!("00000001", "18", "cb0071", "0001", "0001", "3b23e244", "0004", "40010100")),
+        (13, 2, concat!("00000002", "18", "c00002", "0001", "0001", "3b23e244", "0004", "40010100")),
+        (16, 5, concat!(
+            "0000fde8", "0000fdea", "0000", "0001", "c0000201", "c0000264", "0001", "0002"
+        )),
+    ];
+    let records: Vec<_> =
+        records.iter().map(|&(mrt_type, subtype, body)| (header(1, mrt_type, subtype), body)).collect();
+    let dump = encode_records(&records);
+    let path = env::temp_dir().join(name).to_string_lossy().into_owned();
+    File::create(&path).and_then(|mut file| file.write_all(&dump)).unwrap();
+    Inputs {
+        files: vec![Input { path, collector: None }],
+        merge: false,
+        #[cfg(feature = "rayon")]
+        parallel: false,
+        #[cfg(feature = "rayon")]
+        unordered: false,
+        seek: None,
+        headers: HeaderFilter::default(),
     }
 }
+
+// (type, subtype) of each record written, with the prefixes of the RIBs
+#[cfg(test)]
+fn written(inputs: &Inputs, filter: &RouteFilter) -> Vec<(u16, u16, Vec<String>)> {
+    let mut out = Vec::new();
+    write_mrt(&mut out, inputs, filter).unwrap();
+    let mut peers = Vec::new();
+    MRTReader::new(&out)
+        .map(|entry| {
+            let (header, record) = entry.unwrap();
+            let prefixes = route::routes(&header, &record, &peers).unwrap();
+            if let MRTRecord::PeerIndexTable(ref table) = record {
+                peers = table.peer_entries.clone();
+            }
+            let prefixes = prefixes.iter().map(|r| format!("{} AS{}", r.prefix, r.peer_asn)).collect();
+            (header.mrt_type, header.mrt_subtype, prefixes)
+        })
+        .collect()
+}
+
+#[test]
+fn write_mrt_peer_test() {
+    let inputs = test_dump("mrt_parser_write_mrt_peer_test.mrt");
+    let filter = RouteFilter { peer_asns: vec![65000], ..RouteFilter::default() };
+    assert_eq!(
+        written(&inputs, &filter),
+        vec![
+            (13, 1, vec![]),
+            (13, 2, vec!["198.51.100.0/24 AS65000".to_string()]),
+            (16, 5, vec![]),
+        ]
+    );
+}
+
+#[test]
+fn write_mrt_expression_test() {
+    let inputs = test_dump("mrt_parser_write_mrt_expression_test.mrt");
+    let expression = Some("prefix == 203.0.113.0/24".parse().unwrap());
+    let filter = RouteFilter { expression, ..RouteFilter::default() };
+    assert_eq!(
+        written(&inputs, &filter),
+        vec![(13, 1, vec![]), (13, 2, vec!["203.0.113.0/24 AS65001".to_string()])]
+    );
+
+    // records without routes go by their peer
+    let expression = Some("peer_asn == 65000".parse().unwrap());
+    let filter = RouteFilter { expression, ..RouteFilter::default() };
+    assert_eq!(
+        written(&inputs, &filter),
+        vec![
+            (13, 1, vec![]),
+            (13, 2, vec!["198.51.100.0/24 AS65000".to_string()]),
+            (16, 5, vec![]),
+        ]
+    );
+}
+
+#[test]
+fn write_mrt_seek_test() {
+    let mut inputs = test_dump("mrt_parser_write_mrt_seek_test.mrt");
+    let path = inputs.files[0].path.clone();
+    build_index(&path).unwrap();
+    let prefixes = PrefixFilter::new(vec!["198.51.100.0/24".parse().unwrap()], PrefixMatch::Exact);
+    inputs.seek = Some(prefixes.clone());
+    let filter = RouteFilter { prefixes, ..RouteFilter::default() };
+    assert_eq!(
+        written(&inputs, &filter),
+        vec![
+            (13, 1, vec![]),
+            (13, 2, vec!["198.51.100.0/24 AS65000".to_string(), "198.51.100.0/24 AS65001".to_string()]),
+        ]
+    );
+}
+
+
+#[test]
+fn write_mrt_afi_test() {
+    use mrt_parser::bgp::Afi;
+
+    let inputs = test_dump("mrt_parser_write_mrt_afi_test.mrt");
+    let filter = RouteFilter { afi: Some(Afi::IPv4), peer_asns: vec![65000], ..RouteFilter::default() };
+    assert_eq!(
+        written(&inputs, &filter),
+        vec![
+            (13, 1, vec![]),
+            (13, 2, vec!["198.51.100.0/24 AS65000".to_string()]),
+            (16, 5, vec![]),
+        ]
+    );
+    let filter = RouteFilter { afi: Some(Afi::IPv6), ..RouteFilter::default() };
+    assert_eq!(written(&inputs, &filter), vec![]);
+}
+
+#[test]
+fn write_stats_test() {
+    let mut inputs = test_dump("mrt_parser_write_stats_test.mrt");
+    let path = inputs.files[0].path.clone();
+    build_index(&path).unwrap();
+    let stats = |inputs: &Inputs, filter: &RouteFilter| {
+        let mut out = Vec::new();
+        write_stats(&mut out, inputs, filter, false).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let line = |start: &str| text.lines().find(|l| l.starts_with(start)).unwrap().to_string();
+        (line("records:"), line("routes:"), line("unique prefixes:"))
+    };
+    let prefixes = PrefixFilter::new(vec!["198.51.100.0/24".parse().unwrap()], PrefixMatch::Exact);
+    let filter = RouteFilter { prefixes: prefixes.clone(), ..RouteFilter::default() };
+    let expected = (
+        "records: 2".to_string(),
+        "routes: ipv4 2, ipv6 0".to_string(),
+        "unique prefixes: 1".to_string(),
+    );
+    assert_eq!(stats(&inputs, &filter), expected);
+
+    // the same through the index and merged
+    inputs.seek = Some(prefixes);
+    assert_eq!(stats(&inputs, &filter), expected);
+    inputs.seek = None;
+    inputs.merge = true;
+    assert_eq!(stats(&inputs, &filter), expected);
+}
@@ -0,0 +1,143 @@
+use mrt::{HeaderFilter, MRTHeader, MRTReader, MRTRecord};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+// Merges several MRT dumps, each already in time order (e.g. the update
+// files of different collectors), into one timeline. Records come out by
+// timestamp, to the microsecond for the _ET types; ties go to the source
+// added first, and the records of one source keep their order.
+//
+// Records that cannot be read have no timestamp, so they come out as soon
+// as they are found, ahead of anything still waiting.
+
+pub struct Merged<'a> {
+    // the tag given to the source, e.g. "rrc00" or "route-views2"
+    pub collector: &'a str,
+    // the index of the source, in the order added
+    pub source: usize,
+    // byte offset of the record in its source
+    pub offset: usize,
+    pub record: Result<(MRTHeader, MRTRecord<'a>), String>,
+}
+
+struct Source<'a> {
+    collector: &'a str,
+    reader: MRTReader<'a>,
+    // the next record, read ahead to know its timestamp
+    pending: Option<(usize, MRTHeader, MRTRecord<'a>)>,
+}
+
+#[derive(Default)]
+pub struct MergedReader<'a> {
+    sources: Vec<Source<'a>>,
+    // (time in microseconds, source index) of each source's pending record
+    heap: BinaryHeap<Reverse<(u64, usize)>>,
+    errors: VecDeque<Merged<'a>>,
+    headers: HeaderFilter,
+}
+
+impl<'a> MergedReader<'a> {
+    pub fn new() -> MergedReader<'a> {
+        MergedReader::default()
+    }
+
+    // As MRTReader::filter, for the sources added after.
+    pub fn filter(mut self, headers: HeaderFilter) -> MergedReader<'a> {
+        self.headers = headers;
+        self
+    }
+
+    // Add an in-memory dump to the merge, tagged with collector.
+    pub fn add(&mut self, collector: &'a str, input: &'a [u8]) {
+        let reader = MRTReader::new(input).filter(self.headers.clone());
+        self.sources.push(Source { collector, reader, pending: None });
+        self.fill(self.sources.len() - 1);
+    }
+
+    // Bytes at the end of a source that do not frame as an MRT record.
+    pub fn trailing_bytes(&self, source: usize) -> usize {
+        self.sources[source].reader.trailing_bytes()
+    }
+
+    // Read the source's next record into pending, queueing any errors met
+    // on the way.
+    fn fill(&mut self, index: usize) {
+        let source = &mut self.sources[index];
+        while let Some(entry) = source.reader.next() {
+            let offset = source.reader.offset();
+            match entry {
+                Ok((header, record)) => {
+                    self.heap.push(Reverse((header.time_us(), index)));
+                    source.pending = Some((offset, header, record));
+                    return;
+                }
+                Err(e) => self.errors.push_back(Merged {
+                    collector: source.collector,
+                    source: index,
+                    offset,
+                    record: Err(e),
+                }),
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for MergedReader<'a> {
+    type Item = Merged<'a>;
+
+    fn next(&mut self) -> Option<Merged<'a>> {
+        if let Some(error) = self.errors.pop_front() {
+            return Some(error);
+        }
+        let Reverse((_, index)) = self.heap.pop()?;
+        let source = &mut self.sources[index];
+        let (offset, header, record) = source.pending.take()?;
+        let merged = Merged {
+            collector: source.collector,
+            source: index,
+            offset,
+            record: Ok((header, record)),
+        };
+        self.fill(index);
+        Some(merged)
+    }
+}
+
+#[test]
+fn merged_reader_test() {
+    use fixtures::{encode_records, header};
+
+    // BGP4MP_ET STATE_CHANGE records at the given times
+    let dump = |times: &[(u32, u32)]| {
+        let body = "fde8fde8000000010a000001c000020100010002";
+        let records: Vec<_> = times
+            .iter()
+            .map(|&(timestamp, us)| {
+                (MRTHeader { microsecond_timestamp: Some(us), ..header(timestamp, 17, 0) }, body)
+            })
+            .collect();
+        encode_records(&records)
+    };
+    let a = dump(&[(10, 0), (10, 500), (12, 0)]);
+    let mut b = dump(&[(10, 500), (11, 0)]);
+    b.extend_from_slice(b"junk");
+
+    let mut merged = MergedReader::new();
+    merged.add("a", &a);
+    merged.add("b", &b);
+    let order: Vec<(&str, usize, Option<u64>)> = merged
+        .map(|m| (m.collector, m.offset, m.record.ok().map(|(h, _)| h.time_us())))
+        .collect();
+    assert_eq!(
+        order,
+        vec![
+            ("a", 0, Some(10_000_000)),
+            ("a", 36, Some(10_000_500)),
+            ("b", 0, Some(10_000_500)),
+            ("b", 36, Some(11_000_000)),
+            // found reading on from b's last record
+            ("b", 72, None),
+            ("a", 72, Some(12_000_000)),
+        ]
+    );
+}
@@ -0,0 +1,78 @@
+use compression::Compression;
+use memmap2::Mmap;
+use mrt::MRTReader;
+use std::fs::File;
+use std::ops::Deref;
+
+// An uncompressed MRT file mapped into memory rather than read into a
+// buffer. Records read from it borrow straight from the mapping (prefixes,
+// AS paths, attribute data), so nothing is copied and only the pages
+// touched are ever read in.
+//
+// The file must not be truncated or rewritten while mapped: reading a page
+// that is no longer there kills the process with SIGBUS.
+pub struct MappedFile {
+    map: Mmap,
+}
+
+impl MappedFile {
+    // Map the file at path, or None if it is not a regular file (a pipe
+    // such as <(zcat f.gz), a device), cannot be mapped or is compressed:
+    // those need compression::read_file instead.
+    pub fn open(path: &str) -> Result<Option<MappedFile>, String> {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        if !file.metadata().map_err(|e| format!("{}: {}", path, e))?.is_file() {
+            return Ok(None);
+        }
+        // safe as long as the file is left alone while mapped, see above
+        let map = match unsafe { Mmap::map(&file) } {
+            Ok(map) => map,
+            Err(_) => return Ok(None),
+        };
+        match Compression::detect(&map) {
+            Compression::None => Ok(Some(MappedFile { map })),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn records(&self) -> MRTReader<'_> {
+        MRTReader::new(&self.map)
+    }
+}
+
+impl Deref for MappedFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+#[test]
+fn mapped_file_test() {
+    use fixtures::table_dump;
+    use mrt::MRTRecord;
+    use std::io::Write;
+
+    let raw = table_dump();
+    let path = std::env::temp_dir().join(format!("mrt_parser_mmap_{}.mrt", std::process::id()));
+    File::create(&path).unwrap().write_all(&raw).unwrap();
+
+    let mapped = MappedFile::open(path.to_str().unwrap()).unwrap().unwrap();
+    let records: Vec<_> = mapped.records().map(|r| r.unwrap()).collect();
+    assert_eq!(records.len(), 1);
+    match records[0].1 {
+        MRTRecord::TableDumpIPv4(ref r) => {
+            // the attributes point into the mapping, not a copy
+            let attrs = r.attributes().unwrap();
+            let range = mapped.as_ptr_range();
+            assert!(range.contains(&attrs[1].data.as_ptr()));
+        }
+        _ => panic!("expected TABLE_DUMP"),
+    }
+    std::fs::remove_file(&path).unwrap();
+
+    // devices and pipes are left to compression::read_file
+    #[cfg(unix)]
+    assert!(MappedFile::open("/dev/null").unwrap().is_none());
+}
@@ -0,0 +1,1021 @@
+use bgp::{parse_bgp_path_attrs, Afi, BGPPathAttribute, PathAttributes, Prefix};
+use bgp4mp::{parse_bgp4mp, BGP4MPMessage, BGP4MPPeer, BGP4MPStateChange, BGP4MPSubtypes, BGPMessage};
+use nom::{be_u128, be_u16, be_u32, be_u8, IResult};
+use num_traits::cast::FromPrimitive;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MRTHeader {
+    pub timestamp: u32,
+    pub mrt_type: u16,
+    pub mrt_subtype: u16,
+    // as on the wire: includes the microsecond timestamp of _ET types
+    pub length: u32,
+    pub microsecond_timestamp: Option<u32>,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MRTType {
+    OSPFv2 = 11,
+    TABLE_DUMP = 12,
+    TABLE_DUMP_V2 = 13,
+    BGP4MP = 16,
+    BGP4MP_ET = 17,
+    ISIS = 32,
+    ISIS_ET = 33,
+    OSPFv3 = 48,
+    OSPFv3_ET = 49,
+}
+#[allow(non_camel_case_types)]
+#[derive(Debug, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TableDumpSubtypes {
+    AFI_IPv4 = 1,
+    AFI_IPv6 = 2,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TableDumpV2Subtypes {
+    PEER_INDEX_TABLE = 1,
+    RIB_IPV4_UNICAST = 2,
+    RIB_IPV4_MULTICAST = 3,
+    RIB_IPV6_UNICAST = 4,
+    RIB_IPV6_MULTICAST = 5,
+    RIB_GENERIC = 6,
+}
+
+// The _ET types carry an extra microsecond timestamp after the common
+// header, counted in its length field (RFC 6396, section 3).
+pub fn has_extended_timestamp(mrt_type: u16) -> bool {
+    matches!(
+        MRTType::from_u16(mrt_type),
+        Some(MRTType::BGP4MP_ET) | Some(MRTType::ISIS_ET) | Some(MRTType::OSPFv3_ET)
+    )
+}
+
+named!(pub parse_mrt_table_header<MRTHeader>,
+    do_parse!(
+        timestamp:   be_u32 >>
+        mrt_type:    be_u16 >>
+        mrt_subtype: be_u16 >>
+        length:      be_u32 >>
+        microsecond_timestamp: cond!(has_extended_timestamp(mrt_type), be_u32) >>
+        (MRTHeader { timestamp, mrt_type, mrt_subtype, length, microsecond_timestamp })
+    )
+);
+
+impl MRTHeader {
+    // The timestamp in microseconds, for ordering _ET and other records alike.
+    pub fn time_us(&self) -> u64 {
+        u64::from(self.timestamp) * 1_000_000 + u64::from(self.microsecond_timestamp.unwrap_or(0))
+    }
+
+    pub fn is_peer_index_table(&self) -> bool {
+        self.mrt_type == MRTType::TABLE_DUMP_V2 as u16
+            && self.mrt_subtype == TableDumpV2Subtypes::PEER_INDEX_TABLE as u16
+    }
+
+    // Length of the record body following the (possibly extended) header.
+    pub fn body_length(&self) -> u32 {
+        match self.microsecond_timestamp {
+            Some(_) => self.length.saturating_sub(4),
+            None => self.length,
+        }
+    }
+}
+
+// Criteria on the MRT header alone, so that readers can pass over the other
+// records without decoding their bodies. PEER_INDEX_TABLEs always match, as
+// the RIB records after them cannot be read without them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeaderFilter {
+    // microseconds since the epoch: from is inclusive, to exclusive
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    // (type, subtype), any of them; a subtype of None takes the whole type
+    pub types: Vec<(u16, Option<u16>)>,
+    // only decided here for TABLE_DUMP and TABLE_DUMP_V2, whose subtypes
+    // give the family; BGP4MP records can carry either
+    pub afi: Option<Afi>,
+}
+
+// The address family of the routes in a record, where the header tells.
+fn header_afi(header: &MRTHeader) -> Option<Afi> {
+    match MRTType::from_u16(header.mrt_type) {
+        Some(MRTType::TABLE_DUMP) => Afi::from_u16(header.mrt_subtype),
+        Some(MRTType::TABLE_DUMP_V2) => match TableDumpV2Subtypes::from_u16(header.mrt_subtype) {
+            Some(TableDumpV2Subtypes::RIB_IPV4_UNICAST)
+            | Some(TableDumpV2Subtypes::RIB_IPV4_MULTICAST) => Some(Afi::IPv4),
+            Some(TableDumpV2Subtypes::RIB_IPV6_UNICAST)
+            | Some(TableDumpV2Subtypes::RIB_IPV6_MULTICAST) => Some(Afi::IPv6),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl HeaderFilter {
+    pub fn is_empty(&self) -> bool {
+        *self == HeaderFilter::default()
+    }
+
+    pub fn matches(&self, header: &MRTHeader) -> bool {
+        if header.is_peer_index_table() {
+            return true;
+        }
+        let time = header.time_us();
+        self.from.is_none_or(|from| time >= from)
+            && self.to.is_none_or(|to| time < to)
+            && (self.types.is_empty()
+                || self.types.iter().any(|&(t, s)| {
+                    t == header.mrt_type && s.is_none_or(|s| s == header.mrt_subtype)
+                }))
+            && match (self.afi, header_afi(header)) {
+                (Some(afi), Some(family)) => afi == family,
+                _ => true,
+            }
+    }
+}
+
+
+// The names of a type and subtype as in the enums above, e.g.
+// ("BGP4MP_ET", "BGP4MP_STATE_CHANGE"), where they are known.
+pub fn type_names(mrt_type: u16, mrt_subtype: u16) -> (Option<String>, Option<String>) {
+    let mrt_type = MRTType::from_u16(mrt_type);
+    let subtype = match mrt_type {
+        Some(MRTType::TABLE_DUMP) => {
+            TableDumpSubtypes::from_u16(mrt_subtype).map(|s| format!("{:?}", s))
+        }
+        Some(MRTType::TABLE_DUMP_V2) => {
+            TableDumpV2Subtypes::from_u16(mrt_subtype).map(|s| format!("{:?}", s))
+        }
+        Some(MRTType::BGP4MP) | Some(MRTType::BGP4MP_ET) => {
+            BGP4MPSubtypes::from_u16(mrt_subtype).map(|s| format!("{:?}", s))
+        }
+        _ => None,
+    };
+    (mrt_type.map(|t| format!("{:?}", t)), subtype)
+}
+
+impl fmt::Display for MRTHeader {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match MRTType::from_u16(self.mrt_type) {
+            Some(MRTType::TABLE_DUMP) => {
+                write!(fmt, "TABLE_DUMP|")?;
+                match TableDumpSubtypes::from_u16(self.mrt_subtype) {
+                    Some(TableDumpSubtypes::AFI_IPv4) => write!(fmt, "AFI_IPv4")?,
+                    Some(TableDumpSubtypes::AFI_IPv6) => write!(fmt, "AFI_IPv6")?,
+                    _ => write!(fmt, "Unhandled MRT TABLE_DUMP subtype {}", self.mrt_subtype)?,
+                }
+            }
+            Some(MRTType::TABLE_DUMP_V2) => {
+                write!(fmt, "TABLE_DUMP_V2|")?;
+                match TableDumpV2Subtypes::from_u16(self.mrt_subtype) {
+                    Some(TableDumpV2Subtypes::PEER_INDEX_TABLE) => write!(fmt, "PEER_INDEX_TABLE")?,
+                    Some(TableDumpV2Subtypes::RIB_IPV4_UNICAST) => write!(fmt, "RIB_IPV4_UNICAST")?,
+                    Some(TableDumpV2Subtypes::RIB_IPV4_MULTICAST) => {
+                        write!(fmt, "RIB_IPV4_MULTICAST")?
+                    }
+                    Some(TableDumpV2Subtypes::RIB_IPV6_UNICAST) => write!(fmt, "RIB_IPV6_UNICAST")?,
+                    Some(TableDumpV2Subtypes::RIB_IPV6_MULTICAST) => {
+                        write!(fmt, "RIB_IPV6_MULTICAST")?
+                    }
+                    Some(TableDumpV2Subtypes::RIB_GENERIC) => write!(fmt, "RIB_GENERIC")?,
+                    _ => write!(
+                        fmt,
+                        "Unhandled MRT TABLE_DUMP_V2 subtype {}",
+                        self.mrt_subtype
+                    )?,
+                }
+            }
+            Some(MRTType::BGP4MP) | Some(MRTType::BGP4MP_ET) => {
+                match MRTType::from_u16(self.mrt_type) {
+                    Some(MRTType::BGP4MP_ET) => write!(fmt, "BGP4MP_ET|")?,
+                    _ => write!(fmt, "BGP4MP|")?,
+                }
+                match BGP4MPSubtypes::from_u16(self.mrt_subtype) {
+                    Some(subtype) => write!(fmt, "{:?}", subtype)?,
+                    None => write!(fmt, "Unhandled MRT BGP4MP subtype {}", self.mrt_subtype)?,
+                }
+            }
+            _ => write!(fmt, "Unhandled MRT Type {}", self.mrt_type)?,
+        }
+        write!(fmt, "|{}|", self.timestamp)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MRTTableDumpIPv4<'a> {
+    pub view_number: u16,
+    pub sequence_number: u16,
+    pub prefix: Ipv4Addr,
+    pub prefix_length: u8,
+    pub status: u8,
+    pub originated_time: u32,
+    pub peer_address: Ipv4Addr,
+    pub peer_asn: u16,
+    pub attr_length: u16,
+    pub as_path: Cow<'a, [u8]>,
+}
+
+// bgpdump:
+// TABLE_DUMP|992216782|B|193.148.15.85|3257|3.0.0.0/8|3257 701 80|IGP|193.148.15.85|0|0||NAG||
+// MRTHeader { timestamp: 992216782, mrt_type: 12, mrt_subtype: 1, length: 44 }
+//
+// The attributes use 2-octet ASNs; see bgpdump::write_oneline.
+
+impl<'a> MRTTableDumpIPv4<'a> {
+    pub fn prefix(&self) -> Prefix {
+        Prefix { addr: IpAddr::V4(self.prefix), len: self.prefix_length }
+    }
+
+    pub fn attributes(&self) -> Result<Vec<BGPPathAttribute<'_>>, String> {
+        parse_bgp_path_attrs(&self.as_path, self.attr_length)
+            .map(|r| r.1)
+            .map_err(|e| format!("Bad TABLE_DUMP attributes: {}", e))
+    }
+
+    // The attributes, parsed only as they are asked for.
+    pub fn path_attributes(&self) -> PathAttributes<'_> {
+        PathAttributes::new(&self.as_path)
+    }
+}
+
+//        0                   1                   2                   3
+//        0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |         View Number           |       Sequence Number         |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |                        Prefix (variable)                      |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       | Prefix Length |    Status     |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |                         Originated Time                       |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |                    Peer IP Address (variable)                 |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |           Peer AS             |       Attribute Length        |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |                   BGP Attribute... (variable)
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+//                         Figure 4: TABLE_DUMP Type
+
+named!(pub parse_mrt_table_dump_ipv4<MRTTableDumpIPv4>,
+    do_parse!(
+        view_number:     be_u16 >>
+        sequence_number: be_u16 >>
+        prefix:          be_u32 >>
+        prefix_length:   be_u8  >>
+        status:          be_u8  >>
+        originated_time: be_u32 >>
+        peer_address:    be_u32 >>
+        peer_asn:        be_u16 >>
+        attr_length:     be_u16 >>
+        as_path:         take!(attr_length)        >>
+    (MRTTableDumpIPv4 {
+        view_number,
+        sequence_number,
+        prefix:          Ipv4Addr::from(prefix),
+        prefix_length,
+        status,
+        originated_time,
+        peer_address:    Ipv4Addr::from(peer_address),
+        peer_asn,
+        attr_length,
+        as_path: Cow::Borrowed(as_path)
+    })
+    )
+);
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MRTTableDumpIPv6<'a> {
+    pub view_number: u16,
+    pub sequence_number: u16,
+    pub prefix: Ipv6Addr,
+    pub prefix_length: u8,
+    pub status: u8,
+    pub originated_time: u32,
+    pub peer_address: Ipv6Addr,
+    pub peer_asn: u16,
+    pub attr_length: u16,
+    pub as_path: Cow<'a, [u8]>,
+}
+
+named!(pub parse_mrt_table_dump_ipv6<MRTTableDumpIPv6>,
+    do_parse!(
+        view_number:     be_u16 >>
+        sequence_number: be_u16 >>
+        prefix:          be_u128 >>
+        prefix_length:   be_u8  >>
+        status:          be_u8  >>
+        originated_time: be_u32 >>
+        peer_address:    be_u128 >>
+        peer_asn:        be_u16 >>
+        attr_length:     be_u16 >>
+        as_path:         take!(attr_length)        >>
+    (MRTTableDumpIPv6 {
+        view_number,
+        sequence_number,
+        prefix:          Ipv6Addr::from(prefix),
+        prefix_length,
+        status,
+        originated_time,
+        peer_address:    Ipv6Addr::from(peer_address),
+        peer_asn,
+        attr_length,
+        as_path: Cow::Borrowed(as_path)
+    })
+    )
+);
+
+impl<'a> MRTTableDumpIPv6<'a> {
+    pub fn prefix(&self) -> Prefix {
+        Prefix { addr: IpAddr::V6(self.prefix), len: self.prefix_length }
+    }
+
+    pub fn attributes(&self) -> Result<Vec<BGPPathAttribute<'_>>, String> {
+        parse_bgp_path_attrs(&self.as_path, self.attr_length)
+            .map(|r| r.1)
+            .map_err(|e| format!("Bad TABLE_DUMP attributes: {}", e))
+    }
+
+    // The attributes, parsed only as they are asked for.
+    pub fn path_attributes(&self) -> PathAttributes<'_> {
+        PathAttributes::new(&self.as_path)
+    }
+}
+
+//        0                   1                   2                   3
+//        0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |                      Sequence Number = 42                     |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       | Preflen = 32  |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |                 Prefix  =  2001:0DB8::/32                     |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |    Entry Count = 1            |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |    Peer Index =  15           |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |Originated Time = 1300475700 epoch sec (2011-03-18 19:15:00)   |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |   Attribute Length  =  68     |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |   BGP Path Attributes =
+
+//        0                   1                   2                   3
+//        0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |                      Collector BGP ID                         |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |       View Name Length        |     View Name (variable)      |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |          Peer Count           |    Peer Entries (variable)
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+//                       Figure 5: PEER_INDEX_TABLE Subtype
+//
+//        0                   1                   2                   3
+//        0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |   Peer Type   |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |                         Peer BGP ID                           |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |                   Peer IP Address (variable)                  |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//       |                        Peer AS (variable)                     |
+//       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+//                          Figure 6: Peer Entries
+//
+// Peer Type bit 0 (0x01) selects an IPv6 peer address, bit 1 (0x02) a
+// 4-octet peer AS.
+
+pub const PEER_TYPE_IPV6: u8 = 0x01;
+pub const PEER_TYPE_AS4: u8 = 0x02;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PeerEntry {
+    pub peer_type: u8,
+    pub peer_bgp_id: Ipv4Addr,
+    pub peer_address: IpAddr,
+    pub peer_asn: u32,
+}
+
+named!(parse_peer_entry<PeerEntry>,
+    do_parse!(
+        peer_type:    be_u8  >>
+        peer_bgp_id:  be_u32 >>
+        peer_address: switch!(value!(peer_type & PEER_TYPE_IPV6 != 0),
+                          true  => map!(be_u128, |a| IpAddr::V6(Ipv6Addr::from(a))) |
+                          false => map!(be_u32, |a| IpAddr::V4(Ipv4Addr::from(a)))) >>
+        peer_asn:     switch!(value!(peer_type & PEER_TYPE_AS4 != 0),
+                          true  => call!(be_u32) |
+                          false => map!(be_u16, u32::from)) >>
+    (PeerEntry { peer_type, peer_bgp_id: Ipv4Addr::from(peer_bgp_id), peer_address, peer_asn })
+    )
+);
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MRTTableDumpV2PeerIndex<'a> {
+    pub collector_bgp_id: Ipv4Addr,
+    pub view_name_length: u16,
+    pub view_name: Cow<'a, [u8]>,
+    pub peer_count: u16,
+    pub peer_entries: Vec<PeerEntry>,
+}
+
+named!(pub parse_mrt_table_dump_v2_peer_index<MRTTableDumpV2PeerIndex>,
+    do_parse!(
+        collector_bgp_id: be_u32 >>
+        view_name_length: be_u16 >>
+        view_name:        take!(view_name_length) >>
+        peer_count:       be_u16 >>
+        peer_entries:     count!(parse_peer_entry, peer_count as usize) >>
+    (MRTTableDumpV2PeerIndex {
+        collector_bgp_id: Ipv4Addr::from(collector_bgp_id),
+        view_name_length,
+        view_name: Cow::Borrowed(view_name),
+        peer_count,
+        peer_entries
+    })
+    )
+);
+
+// RIB Entries
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RibEntry<'a> {
+    pub peer_index: u16,
+    pub originated_timestamp: u32,
+    pub attr_length: u16,
+    pub bgp_path_attrs: Vec<BGPPathAttribute<'a>>,
+}
+
+impl<'a> RibEntry<'a> {
+    pub fn into_owned(self) -> RibEntry<'static> {
+        RibEntry {
+            bgp_path_attrs: self.bgp_path_attrs.into_iter().map(|a| a.into_owned()).collect(),
+            ..self
+        }
+    }
+}
+
+pub fn parse_rib_entry(input: &[u8]) -> IResult<&[u8], RibEntry<'_>> {
+    do_parse!(
+        input,
+        peer_index: be_u16
+            >> originated_timestamp: be_u32
+            >> attr_length: be_u16
+            >> bgp_path_attrs: call!(parse_bgp_path_attrs, attr_length)
+            >> (RibEntry {
+                peer_index,
+                originated_timestamp,
+                attr_length,
+                bgp_path_attrs
+            })
+    )
+}
+
+fn parse_rib_entries(input: &[u8], entry_count: u16) -> IResult<&[u8], Vec<RibEntry<'_>>> {
+    count!(input, parse_rib_entry, entry_count as usize)
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MRTTableDumpV2IPv4Unicast<'b> {
+    pub sequence_number: u32,
+    pub prefix_length: u8,
+    pub prefix: Cow<'b, [u8]>,
+    pub entry_count: u16,
+    pub rib_entries: Vec<RibEntry<'b>>,
+}
+
+pub fn make_addr(prefix: &[u8]) -> Ipv4Addr {
+    let mut prefix_u32: u32 = 0;
+    for (i, octet) in prefix.iter().enumerate() {
+        prefix_u32 |= u32::from(*octet) << (24 - 8 * i);
+    }
+    Ipv4Addr::from(prefix_u32)
+}
+
+impl<'a> MRTTableDumpV2IPv4Unicast<'a> {
+    pub fn prefix(&self) -> Prefix {
+        Prefix { addr: IpAddr::V4(make_addr(&self.prefix)), len: self.prefix_length }
+    }
+}
+
+pub fn prefix_octet_count(prefix_length: u8) -> u32 {
+    u32::from(prefix_length.div_ceil(8))
+}
+
+// I had to break out of the macro here because it was grouching about lifetimes.
+//named_args!(pub parse_mrt_table_dump_v2_ipv4_unicast<'a>(header: &'a MRTHeader)<MRTTableDumpV2IPv4Unicast>,
+//
+// This is ugly but the number of bytes for this part of the message is:
+// length specific in header - sizeof(sequence_num) - sizeof(prefix_length) - prefix_octet_count - sizeof(entry_count)
+//rib_entries:     take!(header.length - (size_of::<u32>() as u32) - (size_of::<u8>() as u32) - prefix_octet_count(prefix_length) - (size_of::<u16>() as u32))  >>
+//rib_entries:     parse_rib_entry(input, header.length - (size_of::<u32>() as u32) - (size_of::<u8>() as u32) - prefix_octet_count(prefix_length) - (size_of::<u16>() as u32), entry_count) >>
+pub fn parse_mrt_table_dump_v2_ipv4_unicast(
+    input: &[u8],
+) -> IResult<&[u8], MRTTableDumpV2IPv4Unicast<'_>> {
+    do_parse!(
+        input,
+        sequence_number: be_u32
+            >> prefix_length: verify!(be_u8, |l: u8| l <= 32)
+            >> prefix: take!(prefix_octet_count(prefix_length))
+            >> entry_count: be_u16
+            >> rib_entries: call!(parse_rib_entries, entry_count)
+            >> (MRTTableDumpV2IPv4Unicast {
+                sequence_number,
+                prefix_length,
+                prefix: Cow::Borrowed(prefix),
+                entry_count,
+                rib_entries
+            })
+    )
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MRTTableDumpV2IPv6Unicast<'a> {
+    pub sequence_number: u32,
+    pub prefix_length: u8,
+    pub prefix: Cow<'a, [u8]>,
+    pub entry_count: u16,
+    pub rib_entries: Vec<RibEntry<'a>>,
+}
+
+impl<'a> MRTTableDumpV2IPv6Unicast<'a> {
+    pub fn prefix(&self) -> Prefix {
+        Prefix::from_octets(Afi::IPv6, &self.prefix, self.prefix_length)
+            .unwrap_or(Prefix { addr: IpAddr::V6(Ipv6Addr::from(0)), len: self.prefix_length })
+    }
+}
+
+pub fn parse_mrt_table_dump_v2_ipv6_unicast(
+    input: &[u8],
+) -> IResult<&[u8], MRTTableDumpV2IPv6Unicast<'_>> {
+    do_parse!(
+        input,
+        sequence_number: be_u32
+            >> prefix_length: verify!(be_u8, |l: u8| l <= 128)
+            >> prefix: take!(prefix_octet_count(prefix_length))
+            >> entry_count: be_u16
+            >> rib_entries: call!(parse_rib_entries, entry_count)
+            >> (MRTTableDumpV2IPv6Unicast {
+                sequence_number,
+                prefix_length,
+                prefix: Cow::Borrowed(prefix),
+                entry_count,
+                rib_entries
+            })
+    )
+}
+
+// A RIB_IPV4_UNICAST or RIB_IPV6_UNICAST record decoded only as far as its
+// prefix: the entries are framed as they are iterated, and each one's
+// attributes left as a PathAttributes view. Going through a full table for,
+// say, the origin of every route then decodes the AS_PATHs and nothing else,
+// and builds no attribute list per entry.
+#[derive(Debug, Clone, Copy)]
+pub struct RibView<'a> {
+    pub sequence_number: u32,
+    pub prefix: Prefix,
+    pub entry_count: u16,
+    entries: &'a [u8],
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RibEntryView<'a> {
+    pub peer_index: u16,
+    pub originated_timestamp: u32,
+    pub attributes: PathAttributes<'a>,
+}
+
+pub struct RibEntryViews<'a> {
+    input: &'a [u8],
+    remaining: u16,
+}
+
+fn parse_rib_entry_view(input: &[u8]) -> IResult<&[u8], RibEntryView<'_>> {
+    do_parse!(
+        input,
+        peer_index: be_u16
+            >> originated_timestamp: be_u32
+            >> attr_length: be_u16
+            >> attributes: take!(attr_length)
+            >> (RibEntryView {
+                peer_index,
+                originated_timestamp,
+                attributes: PathAttributes::new(attributes)
+            })
+    )
+}
+
+impl<'a> Iterator for RibEntryViews<'a> {
+    type Item = Result<RibEntryView<'a>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        match parse_rib_entry_view(self.input) {
+            Ok((rest, entry)) => {
+                self.input = rest;
+                Some(Ok(entry))
+            }
+            Err(e) => {
+                self.remaining = 0;
+                Some(Err(format!("Bad RIB entry: {}", e)))
+            }
+        }
+    }
+}
+
+impl<'a> RibView<'a> {
+    pub fn entries(&self) -> RibEntryViews<'a> {
+        RibEntryViews { input: self.entries, remaining: self.entry_count }
+    }
+}
+
+fn parse_rib_view_body(input: &[u8], afi: Afi) -> IResult<&[u8], RibView<'_>> {
+    let max_length = if afi == Afi::IPv4 { 32 } else { 128 };
+    do_parse!(
+        input,
+        sequence_number: be_u32
+            >> prefix_length: verify!(be_u8, |l: u8| l <= max_length)
+            >> prefix: map_opt!(
+                take!(prefix_octet_count(prefix_length)),
+                |octets| Prefix::from_octets(afi, octets, prefix_length)
+            )
+            >> entry_count: be_u16
+            >> entries: call!(nom::rest)
+            >> (RibView { sequence_number, prefix, entry_count, entries })
+    )
+}
+
+// The RibView of a record body, or None if the record is not a
+// RIB_IPV4_UNICAST or RIB_IPV6_UNICAST one.
+pub fn parse_rib_view<'a>(header: &MRTHeader, body: &'a [u8]) -> Result<Option<RibView<'a>>, String> {
+    if header.mrt_type != MRTType::TABLE_DUMP_V2 as u16 {
+        return Ok(None);
+    }
+    let afi = match TableDumpV2Subtypes::from_u16(header.mrt_subtype) {
+        Some(TableDumpV2Subtypes::RIB_IPV4_UNICAST) => Afi::IPv4,
+        Some(TableDumpV2Subtypes::RIB_IPV6_UNICAST) => Afi::IPv6,
+        _ => return Ok(None),
+    };
+    parse_rib_view_body(body, afi)
+        .map(|r| Some(r.1))
+        .map_err(|e| format!("{}: {}", header, e))
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MRTRecord<'a> {
+    TableDumpIPv4(MRTTableDumpIPv4<'a>),
+    TableDumpIPv6(MRTTableDumpIPv6<'a>),
+    PeerIndexTable(MRTTableDumpV2PeerIndex<'a>),
+    RibIPv4Unicast(MRTTableDumpV2IPv4Unicast<'a>),
+    RibIPv6Unicast(MRTTableDumpV2IPv6Unicast<'a>),
+    BGP4MPStateChange(BGP4MPStateChange),
+    BGP4MPMessage(BGP4MPMessage<'a>),
+    // A type or subtype this crate does not decode; the body is skipped.
+    Unsupported,
+}
+
+impl<'a> MRTRecord<'a> {
+    // The session a BGP4MP record was seen on.
+    pub fn bgp4mp_peer(&self) -> Option<&BGP4MPPeer> {
+        match *self {
+            MRTRecord::BGP4MPStateChange(ref r) => Some(&r.peer),
+            MRTRecord::BGP4MPMessage(ref r) => Some(&r.peer),
+            _ => None,
+        }
+    }
+
+    // Whether it is a BGP4MP record that cannot carry routes: a state change
+    // or a message other than an UPDATE.
+    pub fn is_routeless(&self) -> bool {
+        match *self {
+            MRTRecord::BGP4MPStateChange(_) => true,
+            MRTRecord::BGP4MPMessage(ref m) => !matches!(m.message, BGPMessage::Update(_)),
+            _ => false,
+        }
+    }
+
+    // A copy of the record that no longer borrows the input buffer, e.g. to
+    // keep it past the buffer's lifetime or to deserialize into.
+    pub fn into_owned(self) -> MRTRecord<'static> {
+        match self {
+            MRTRecord::TableDumpIPv4(r) => MRTRecord::TableDumpIPv4(MRTTableDumpIPv4 {
+                as_path: Cow::Owned(r.as_path.into_owned()),
+                ..r
+            }),
+            MRTRecord::TableDumpIPv6(r) => MRTRecord::TableDumpIPv6(MRTTableDumpIPv6 {
+                as_path: Cow::Owned(r.as_path.into_owned()),
+                ..r
+            }),
+            MRTRecord::PeerIndexTable(r) => MRTRecord::PeerIndexTable(MRTTableDumpV2PeerIndex {
+                view_name: Cow::Owned(r.view_name.into_owned()),
+                ..r
+            }),
+            MRTRecord::RibIPv4Unicast(r) => MRTRecord::RibIPv4Unicast(MRTTableDumpV2IPv4Unicast {
+                prefix: Cow::Owned(r.prefix.into_owned()),
+                rib_entries: r.rib_entries.into_iter().map(|e| e.into_owned()).collect(),
+                ..r
+            }),
+            MRTRecord::RibIPv6Unicast(r) => MRTRecord::RibIPv6Unicast(MRTTableDumpV2IPv6Unicast {
+                prefix: Cow::Owned(r.prefix.into_owned()),
+                rib_entries: r.rib_entries.into_iter().map(|e| e.into_owned()).collect(),
+                ..r
+            }),
+            MRTRecord::BGP4MPStateChange(r) => MRTRecord::BGP4MPStateChange(r),
+            MRTRecord::BGP4MPMessage(r) => MRTRecord::BGP4MPMessage(r.into_owned()),
+            MRTRecord::Unsupported => MRTRecord::Unsupported,
+        }
+    }
+}
+
+fn parse_mrt_table_dump<'a>(header: &MRTHeader, body: &'a [u8]) -> Result<MRTRecord<'a>, String> {
+    match TableDumpSubtypes::from_u16(header.mrt_subtype) {
+        Some(TableDumpSubtypes::AFI_IPv4) => parse_mrt_table_dump_ipv4(body)
+            .map(|r| MRTRecord::TableDumpIPv4(r.1))
+            .map_err(|e| e.to_string()),
+        Some(TableDumpSubtypes::AFI_IPv6) => parse_mrt_table_dump_ipv6(body)
+            .map(|r| MRTRecord::TableDumpIPv6(r.1))
+            .map_err(|e| e.to_string()),
+        _ => Ok(MRTRecord::Unsupported),
+    }
+}
+
+fn parse_mrt_table_dump_v2<'a>(header: &MRTHeader, body: &'a [u8]) -> Result<MRTRecord<'a>, String> {
+    match TableDumpV2Subtypes::from_u16(header.mrt_subtype) {
+        Some(TableDumpV2Subtypes::PEER_INDEX_TABLE) => parse_mrt_table_dump_v2_peer_index(body)
+            .map(|r| MRTRecord::PeerIndexTable(r.1))
+            .map_err(|e| e.to_string()),
+        Some(TableDumpV2Subtypes::RIB_IPV4_UNICAST) => parse_mrt_table_dump_v2_ipv4_unicast(body)
+            .map(|r| MRTRecord::RibIPv4Unicast(r.1))
+            .map_err(|e| e.to_string()),
+        Some(TableDumpV2Subtypes::RIB_IPV6_UNICAST) => parse_mrt_table_dump_v2_ipv6_unicast(body)
+            .map(|r| MRTRecord::RibIPv6Unicast(r.1))
+            .map_err(|e| e.to_string()),
+        // RIB_IPV4_MULTICAST, RIB_IPV6_MULTICAST and RIB_GENERIC not implemented
+        _ => Ok(MRTRecord::Unsupported),
+    }
+}
+
+// Decode the body of a record whose header has already been read. The body
+// must be exactly header.body_length() bytes.
+pub fn parse_mrt_body<'a>(header: &MRTHeader, body: &'a [u8]) -> Result<MRTRecord<'a>, String> {
+    let result = match MRTType::from_u16(header.mrt_type) {
+        Some(MRTType::TABLE_DUMP) => parse_mrt_table_dump(header, body),
+        Some(MRTType::TABLE_DUMP_V2) => parse_mrt_table_dump_v2(header, body),
+        Some(MRTType::BGP4MP) | Some(MRTType::BGP4MP_ET) => parse_bgp4mp(header.mrt_subtype, body),
+        _ => Ok(MRTRecord::Unsupported),
+    };
+    result.map_err(|e| format!("{}: {}", header, e))
+}
+
+// Split one record off the front of the input, returning the remaining
+// input, the header and the undecoded body. Framing only relies on the header
+// length, so a body that fails to decode does not stop the caller from
+// carrying on with the next record.
+pub fn split_mrt_record(input: &[u8]) -> Result<(&[u8], MRTHeader, &[u8]), String> {
+    let (rest, header) =
+        parse_mrt_table_header(input).map_err(|_| "Truncated MRT header".to_string())?;
+    let body_length = header.body_length() as usize;
+    if rest.len() < body_length {
+        return Err(format!("{}: truncated record body", header));
+    }
+    let (body, rest) = rest.split_at(body_length);
+    Ok((rest, header, body))
+}
+
+// Where a record came from: the name of its input ("-" for stdin), the
+// byte offset of its header in the decompressed input and, when merging
+// collectors, the collector the input was tagged with.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Provenance {
+    pub file: String,
+    pub offset: usize,
+    pub collector: Option<String>,
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}:{}", self.file, self.offset)
+    }
+}
+
+// Iterates over the records in an in-memory MRT dump.
+pub struct MRTReader<'a> {
+    input: &'a [u8],
+    consumed: usize,
+    offset: usize,
+    trailing_bytes: usize,
+    headers: HeaderFilter,
+}
+
+impl<'a> MRTReader<'a> {
+    pub fn new(input: &'a [u8]) -> MRTReader<'a> {
+        MRTReader { input, consumed: 0, offset: 0, trailing_bytes: 0, headers: HeaderFilter::default() }
+    }
+
+    // Skip the records whose headers do not match, without decoding them.
+    pub fn filter(mut self, headers: HeaderFilter) -> MRTReader<'a> {
+        self.headers = headers;
+        self
+    }
+
+    // Byte offset in the input of the record (or error) last returned.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    // The bytes left at the end of the input that do not make up a whole
+    // record (a truncated file, junk appended to it), once the reader has
+    // come to them.
+    pub fn trailing_bytes(&self) -> usize {
+        self.trailing_bytes
+    }
+}
+
+impl<'a> Iterator for MRTReader<'a> {
+    type Item = Result<(MRTHeader, MRTRecord<'a>), String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.input.is_empty() {
+            self.offset = self.consumed;
+            match split_mrt_record(self.input) {
+                Ok((rest, header, body)) => {
+                    self.consumed += self.input.len() - rest.len();
+                    self.input = rest;
+                    if self.headers.matches(&header) {
+                        return Some(parse_mrt_body(&header, body).map(|r| (header, r)));
+                    }
+                }
+                Err(e) => {
+                    // framing is lost, nothing more can be read
+                    self.trailing_bytes = self.input.len();
+                    self.input = &[];
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[test]
+fn parse_peer_index_test() {
+    let buffer = hex::decode(concat!(
+        "c0000201", "0004", "74657374", "0002",
+        "00", "0a000001", "c0000202", "0cb9",
+        "03", "0a000002", "20010db8000000000000000000000001", "0001fbf0"
+    ))
+    .unwrap();
+
+    let (rest, index) = parse_mrt_table_dump_v2_peer_index(&buffer).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(&index.view_name[..], b"test");
+    assert_eq!(index.peer_entries.len(), 2);
+    assert_eq!(index.peer_entries[0].peer_address, "192.0.2.2".parse::<IpAddr>().unwrap());
+    assert_eq!(index.peer_entries[0].peer_asn, 3257);
+    assert_eq!(index.peer_entries[1].peer_address, "2001:db8::1".parse::<IpAddr>().unwrap());
+    assert_eq!(index.peer_entries[1].peer_asn, 130032);
+}
+
+#[test]
+fn reader_trailing_bytes_test() {
+    use fixtures::table_dump;
+
+    // a KEEPALIVE-sized BGP4MP header with no body, after a whole record
+    let mut buffer = table_dump();
+    buffer.extend(hex::decode(concat!("3b2406ce", "0010", "0004", "00000020")).unwrap());
+
+    let mut reader = MRTReader::new(&buffer);
+    assert!(reader.next().unwrap().is_ok());
+    assert_eq!(reader.offset(), 0);
+    assert_eq!(reader.trailing_bytes(), 0);
+    assert!(reader.next().unwrap().is_err());
+    assert_eq!(reader.offset(), 56);
+    assert_eq!(reader.trailing_bytes(), 12);
+    assert!(reader.next().is_none());
+}
+
+#[test]
+fn rib_view_test() {
+    use bgp::AsnSize;
+
+    // 198.51.100.0/24 from peers 0 (AS_PATH 65000) and 2 (ORIGIN only)
+    let buffer = hex::decode(concat!(
+        "3b2406ce", "000d", "0002", "0000002b",
+        "00000007", "18", "c63364", "0002",
+        "0000", "3b23e244", "000d", "40010100", "40020602010000fde8",
+        "0002", "3b23e244", "0004", "40010100"
+    ))
+    .unwrap();
+    let (_, header, body) = split_mrt_record(&buffer).unwrap();
+
+    let view = parse_rib_view(&header, body).unwrap().unwrap();
+    assert_eq!(view.sequence_number, 7);
+    assert_eq!(view.prefix.to_string(), "198.51.100.0/24");
+    let entries: Vec<RibEntryView> = view.entries().map(|e| e.unwrap()).collect();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].attributes.as_path(AsnSize::Four).unwrap().origin_asn(), Some(65000));
+    assert_eq!(entries[1].peer_index, 2);
+    assert!(entries[1].attributes.as_path(AsnSize::Four).is_none());
+
+    // the same attributes as a full decode
+    match parse_mrt_body(&header, body).unwrap() {
+        MRTRecord::RibIPv4Unicast(r) => {
+            for (entry, view) in r.rib_entries.iter().zip(&entries) {
+                assert_eq!(entry.bgp_path_attrs, view.attributes.to_vec().unwrap());
+            }
+        }
+        _ => panic!("expected RIB_IPV4_UNICAST"),
+    }
+
+    // a record claiming one entry more than it has
+    let mut short = body.to_vec();
+    short[9] = 3;
+    let view = parse_rib_view(&header, &short).unwrap().unwrap();
+    assert!(view.entries().last().unwrap().is_err());
+    assert!(parse_rib_view(&MRTHeader { mrt_subtype: 1, ..header }, body).unwrap().is_none());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_round_trip_test() {
+    use bgp::{AsnSize, PathAttributeValue};
+    use fixtures::table_dump;
+
+    let buffer = table_dump();
+
+    let (_, header, body) = split_mrt_record(&buffer).unwrap();
+    let record = parse_mrt_body(&header, body).unwrap();
+
+    let encoded = serde_json::to_string(&(&header, &record)).unwrap();
+    let (decoded_header, decoded): (MRTHeader, MRTRecord<'static>) =
+        serde_json::from_str(&encoded).unwrap();
+    assert_eq!(decoded_header, header);
+    assert_eq!(decoded, record.into_owned());
+
+    let attrs = match decoded {
+        MRTRecord::TableDumpIPv4(ref r) => r.attributes().unwrap(),
+        _ => panic!("expected a TABLE_DUMP record"),
+    };
+    let as_path = attrs[1].value(AsnSize::Two).unwrap();
+    let encoded = serde_json::to_string(&as_path).unwrap();
+    assert_eq!(serde_json::from_str::<PathAttributeValue>(&encoded).unwrap(), as_path);
+}
+
+#[test]
+fn header_filter_test() {
+    use fixtures::{encode_records, header};
+
+    // BGP4MP_ET state changes at 10.0, 10.5 and 11.0 seconds, then a
+    // BGP4MP_ET record with a body that would not decode
+    let body = "fde8fde8000000010a000001c000020100010002";
+    let records: Vec<_> = [(10, 0, 0), (10, 500_000, 0), (11, 0, 0), (11, 0, 1)]
+        .iter()
+        .map(|&(timestamp, us, subtype)| {
+            (MRTHeader { microsecond_timestamp: Some(us), ..header(timestamp, 17, subtype) }, body)
+        })
+        .collect();
+    let dump = encode_records(&records);
+    let times = |headers: HeaderFilter| -> Vec<Result<u64, String>> {
+        MRTReader::new(&dump).filter(headers).map(|r| r.map(|(h, _)| h.time_us())).collect()
+    };
+    let window = HeaderFilter { from: Some(10_500_000), to: Some(11_000_000), ..Default::default() };
+    assert_eq!(times(window), vec![Ok(10_500_000)]);
+    let state_changes = HeaderFilter { types: vec![(17, Some(0))], ..Default::default() };
+    assert_eq!(times(state_changes).len(), 3);
+    assert!(times(HeaderFilter::default())[3].is_err());
+
+    let ipv6 = HeaderFilter { afi: Some(Afi::IPv6), ..Default::default() };
+    let rib = |subtype| header(0, 13, subtype);
+    assert!(!ipv6.matches(&rib(2)));
+    assert!(ipv6.matches(&rib(4)));
+    assert!(ipv6.matches(&rib(1)));
+}
@@ -0,0 +1,210 @@
+use mrt::{parse_mrt_body, split_mrt_record, HeaderFilter, MRTHeader, MRTRecord, PeerEntry};
+use rayon::prelude::*;
+use std::sync::Arc;
+
+// Decoding the records of an in-memory dump on the rayon thread pool. A
+// full TABLE_DUMP_V2 table is millions of RIB records that only depend on
+// the PEER_INDEX_TABLE before them, so the work splits in two: framing, which
+// is cheap and done in order on the calling thread, and decoding, done on
+// batches of framed records in parallel. Each record comes with the peer
+// table in force where it was found, shared between threads.
+//
+// A PEER_INDEX_TABLE closes the batch it is in, so that the records after it
+// are paired with it.
+
+pub const DEFAULT_BATCH_SIZE: usize = 4096;
+
+pub struct Decoded<'a> {
+    // byte offset of the record in the input
+    pub offset: usize,
+    // the peers of the latest PEER_INDEX_TABLE
+    pub peers: Arc<Vec<PeerEntry>>,
+    pub record: Result<(MRTHeader, MRTRecord<'a>), String>,
+    // where framing was lost, the bytes from here on; otherwise 0
+    pub trailing_bytes: usize,
+}
+
+struct Frame<'a> {
+    offset: usize,
+    peers: Arc<Vec<PeerEntry>>,
+    record: Result<(MRTHeader, &'a [u8]), String>,
+    trailing_bytes: usize,
+}
+
+impl<'a> Frame<'a> {
+    fn decode(self) -> Decoded<'a> {
+        Decoded {
+            offset: self.offset,
+            peers: self.peers,
+            record: self.record.and_then(|(h, body)| parse_mrt_body(&h, body).map(|r| (h, r))),
+            trailing_bytes: self.trailing_bytes,
+        }
+    }
+}
+
+// Iterates over the decoded records in input order, decoding a batch at a
+// time in parallel.
+pub struct ParallelReader<'a> {
+    input: &'a [u8],
+    consumed: usize,
+    trailing_bytes: usize,
+    peers: Arc<Vec<PeerEntry>>,
+    batch_size: usize,
+    headers: HeaderFilter,
+    decoded: std::vec::IntoIter<Decoded<'a>>,
+}
+
+impl<'a> ParallelReader<'a> {
+    pub fn new(input: &'a [u8]) -> ParallelReader<'a> {
+        ParallelReader {
+            input,
+            consumed: 0,
+            trailing_bytes: 0,
+            peers: Arc::new(Vec::new()),
+            batch_size: DEFAULT_BATCH_SIZE,
+            headers: HeaderFilter::default(),
+            decoded: Vec::new().into_iter(),
+        }
+    }
+
+    // Records framed before a batch is handed to the workers.
+    pub fn batch_size(mut self, batch_size: usize) -> ParallelReader<'a> {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    // As MRTReader::filter; the records left out are not handed to the
+    // workers at all.
+    pub fn filter(mut self, headers: HeaderFilter) -> ParallelReader<'a> {
+        self.headers = headers;
+        self
+    }
+
+    // As MRTReader::trailing_bytes, once framing has been lost.
+    pub fn trailing_bytes(&self) -> usize {
+        self.trailing_bytes
+    }
+
+    fn next_batch(&mut self) -> Vec<Frame<'a>> {
+        let mut batch = Vec::with_capacity(self.batch_size);
+        while batch.len() < self.batch_size && !self.input.is_empty() {
+            let offset = self.consumed;
+            match split_mrt_record(self.input) {
+                Ok((rest, header, body)) => {
+                    self.consumed += self.input.len() - rest.len();
+                    self.input = rest;
+                    if !self.headers.matches(&header) {
+                        continue;
+                    }
+                    let mut index = None;
+                    if header.is_peer_index_table() {
+                        if let Ok(MRTRecord::PeerIndexTable(table)) = parse_mrt_body(&header, body) {
+                            index = Some(Arc::new(table.peer_entries));
+                        }
+                    }
+                    let peers = index.as_ref().unwrap_or(&self.peers).clone();
+                    batch.push(Frame { offset, peers, record: Ok((header, body)), trailing_bytes: 0 });
+                    if let Some(index) = index {
+                        self.peers = index;
+                        break;
+                    }
+                }
+                Err(e) => {
+                    // framing is lost, nothing more can be read
+                    self.trailing_bytes = self.input.len();
+                    self.input = &[];
+                    batch.push(Frame {
+                        offset,
+                        peers: self.peers.clone(),
+                        record: Err(e),
+                        trailing_bytes: self.trailing_bytes,
+                    });
+                }
+            }
+        }
+        batch
+    }
+
+    // Hand every record to f on the worker threads as soon as it is decoded,
+    // in no particular order. Framing carries on while earlier batches are
+    // being decoded.
+    pub fn for_each_unordered<F>(mut self, f: F) -> usize
+    where
+        F: Fn(Decoded<'a>) + Sync,
+    {
+        let f = &f;
+        rayon::scope(|scope| loop {
+            let batch = self.next_batch();
+            if batch.is_empty() {
+                break;
+            }
+            scope.spawn(move |_| batch.into_par_iter().for_each(|frame| f(frame.decode())));
+        });
+        self.trailing_bytes
+    }
+}
+
+impl<'a> Iterator for ParallelReader<'a> {
+    type Item = Decoded<'a>;
+
+    fn next(&mut self) -> Option<Decoded<'a>> {
+        if let Some(decoded) = self.decoded.next() {
+            return Some(decoded);
+        }
+        let batch = self.next_batch();
+        if batch.is_empty() {
+            return None;
+        }
+        let decoded: Vec<Decoded<'a>> = batch.into_par_iter().map(Frame::decode).collect();
+        self.decoded = decoded.into_iter();
+        self.decoded.next()
+    }
+}
+
+#[test]
+fn parallel_reader_test() {
+    use fixtures::{encode_records, header};
+    use mrt::MRTReader;
+    use std::sync::Mutex;
+
+    // a PEER_INDEX_TABLE with two peers, then RIB records for both, twice
+    let peer_index = concat!(
+        "c0000201", "0000", "0002",
+        "00", "0a000001", "c0000201", "0001",
+        "00", "0a000002", "c0000202", "0002"
+    );
+    let mut records = Vec::new();
+    for table in 0..2 {
+        records.push((header(table, 13, 1), peer_index.to_string()));
+        for sequence in 0..10u8 {
+            let rib = format!("000000{:02x}18c633640001{:04x}000000000000", sequence, sequence % 2);
+            records.push((header(table, 13, 2), rib));
+        }
+    }
+    let mut input = encode_records(&records);
+    input.extend_from_slice(b"junk");
+
+    // the same records as MRTReader, in the same order
+    let expected: Vec<_> = MRTReader::new(&input).map(|r| r.ok()).collect();
+    let mut reader = ParallelReader::new(&input).batch_size(3);
+    let decoded: Vec<Decoded> = reader.by_ref().collect();
+    assert_eq!(decoded.len(), expected.len());
+    assert!(decoded[..decoded.len() - 1].iter().all(|d| d.record.is_ok()));
+    for (d, e) in decoded.iter().zip(&expected) {
+        assert_eq!(d.record.as_ref().ok().map(|r| &r.1), e.as_ref().map(|r| &r.1));
+    }
+    assert_eq!(decoded[5].peers.len(), 2);
+    assert_eq!(decoded.last().unwrap().offset, input.len() - 4);
+    assert_eq!(decoded.last().unwrap().trailing_bytes, 4);
+    assert_eq!(reader.trailing_bytes(), 4);
+
+    // unordered, every record once
+    let seen = Mutex::new(Vec::new());
+    let trailing_bytes = ParallelReader::new(&input)
+        .batch_size(3)
+        .for_each_unordered(|d| seen.lock().unwrap().push(d.offset));
+    let mut seen = seen.into_inner().unwrap();
+    seen.sort();
+    assert_eq!(seen, decoded.iter().map(|d| d.offset).collect::<Vec<_>>());
+    assert_eq!(trailing_bytes, 4);
+}
@@ -0,0 +1,188 @@
+use bgp::{AsnSize, BGPPathAttribute, Prefix, RouteAttributes};
+use bgp4mp::{BGP4MPMessage, BGPMessage};
+use mrt::{MRTHeader, MRTRecord, PeerEntry, RibEntry};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// A flat view of the routes in a record, one per RIB entry or per prefix
+// announced or withdrawn in a BGP4MP UPDATE. The tabular output formats
+// write one row per Route.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RouteKind {
+    // from a TABLE_DUMP or TABLE_DUMP_V2 RIB
+    Rib,
+    Announce,
+    Withdraw,
+}
+
+impl RouteKind {
+    // bgpdump's code for the kind: B, A or W.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            RouteKind::Rib => "B",
+            RouteKind::Announce => "A",
+            RouteKind::Withdraw => "W",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Route {
+    pub timestamp: u32,
+    pub microsecond_timestamp: Option<u32>,
+    pub kind: RouteKind,
+    pub peer_address: IpAddr,
+    pub peer_asn: u32,
+    pub prefix: Prefix,
+    // empty for withdrawals
+    pub attributes: RouteAttributes,
+}
+
+impl Route {
+    // NEXT_HOP for IPv4 routes, the MP_REACH_NLRI global next hop otherwise.
+    pub fn next_hop(&self) -> Option<IpAddr> {
+        let route = &self.attributes;
+        let mp_next_hop = route.mp_reach_nlri.as_ref().map(|mp| mp.next_hop);
+        let next_hop = route.next_hop.map(IpAddr::V4);
+        match self.prefix.addr {
+            IpAddr::V4(_) => next_hop.or(mp_next_hop),
+            IpAddr::V6(_) => mp_next_hop.or(next_hop),
+        }
+    }
+
+    // As next_hop, with the unspecified address of the prefix's family when
+    // there is none.
+    pub fn next_hop_or_unspecified(&self) -> IpAddr {
+        match (self.next_hop(), self.prefix.addr) {
+            (Some(addr), _) => addr,
+            (None, IpAddr::V4(_)) => IpAddr::V4(Ipv4Addr::from(0)),
+            (None, IpAddr::V6(_)) => IpAddr::V6(Ipv6Addr::from(0)),
+        }
+    }
+
+    pub fn origin_asn(&self) -> Option<u32> {
+        self.attributes.as_path.as_ref().and_then(|path| path.origin_asn())
+    }
+
+    // Standard then large communities, numerically: "65535:666", "64496:1:2".
+    pub fn communities(&self) -> Vec<String> {
+        let route = &self.attributes;
+        let mut out: Vec<String> =
+            route.communities.iter().map(|c| format!("{}:{}", c.asn(), c.value())).collect();
+        out.extend(route.large_communities.iter().map(|c| c.to_string()));
+        out
+    }
+}
+
+fn rib_routes(
+    header: &MRTHeader,
+    prefix: Prefix,
+    entries: &[RibEntry],
+    peers: &[PeerEntry],
+) -> Result<Vec<Route>, String> {
+    entries
+        .iter()
+        .map(|entry| {
+            let peer = peers
+                .get(entry.peer_index as usize)
+                .ok_or_else(|| format!("peer index {} not in PEER_INDEX_TABLE", entry.peer_index))?;
+            Ok(Route {
+                timestamp: header.timestamp,
+                microsecond_timestamp: header.microsecond_timestamp,
+                kind: RouteKind::Rib,
+                peer_address: peer.peer_address,
+                peer_asn: peer.peer_asn,
+                prefix,
+                attributes: RouteAttributes::new(&entry.bgp_path_attrs, AsnSize::Four),
+            })
+        })
+        .collect()
+}
+
+fn table_dump_route(
+    header: &MRTHeader,
+    peer_address: IpAddr,
+    peer_asn: u16,
+    prefix: Prefix,
+    attrs: Result<Vec<BGPPathAttribute>, String>,
+) -> Result<Vec<Route>, String> {
+    Ok(vec![Route {
+        timestamp: header.timestamp,
+        microsecond_timestamp: header.microsecond_timestamp,
+        kind: RouteKind::Rib,
+        peer_address,
+        peer_asn: u32::from(peer_asn),
+        prefix,
+        attributes: RouteAttributes::new(&attrs?, AsnSize::Two),
+    }])
+}
+
+// Withdrawals first, from the message and MP_UNREACH_NLRI, then
+// announcements from the message and MP_REACH_NLRI, as bgpdump orders them.
+pub fn update_routes(header: &MRTHeader, msg: &BGP4MPMessage) -> Vec<Route> {
+    let update = match msg.message {
+        BGPMessage::Update(ref update) => update,
+        _ => return Vec::new(),
+    };
+    let attributes = RouteAttributes::new(&update.path_attributes, msg.asn_size);
+    let route = |kind, prefix: &Prefix, attributes: RouteAttributes| Route {
+        timestamp: header.timestamp,
+        microsecond_timestamp: header.microsecond_timestamp,
+        kind,
+        peer_address: msg.peer.peer_address,
+        peer_asn: msg.peer.peer_asn,
+        prefix: *prefix,
+        attributes,
+    };
+
+    let mut withdrawn: Vec<&Prefix> = update.withdrawn_routes.iter().collect();
+    if let Some(ref mp) = attributes.mp_unreach_nlri {
+        withdrawn.extend(mp.withdrawn_routes.iter());
+    }
+    let mut announced: Vec<&Prefix> = update.nlri.iter().collect();
+    if let Some(ref mp) = attributes.mp_reach_nlri {
+        announced.extend(mp.nlri.iter());
+    }
+
+    let mut routes: Vec<Route> = withdrawn
+        .into_iter()
+        .map(|p| route(RouteKind::Withdraw, p, RouteAttributes::default()))
+        .collect();
+    routes.extend(announced.into_iter().map(|p| route(RouteKind::Announce, p, attributes.clone())));
+    routes
+}
+
+// The routes in one record. TABLE_DUMP_V2 RIB records need the peers from
+// the preceding PEER_INDEX_TABLE; records without routes give none.
+pub fn routes(
+    header: &MRTHeader,
+    record: &MRTRecord,
+    peers: &[PeerEntry],
+) -> Result<Vec<Route>, String> {
+    match *record {
+        MRTRecord::TableDumpIPv4(ref r) => table_dump_route(
+            header,
+            IpAddr::V4(r.peer_address),
+            r.peer_asn,
+            r.prefix(),
+            r.attributes(),
+        ),
+        MRTRecord::TableDumpIPv6(ref r) => table_dump_route(
+            header,
+            IpAddr::V6(r.peer_address),
+            r.peer_asn,
+            r.prefix(),
+            r.attributes(),
+        ),
+        MRTRecord::RibIPv4Unicast(ref r) => rib_routes(header, r.prefix(), &r.rib_entries, peers),
+        MRTRecord::RibIPv6Unicast(ref r) => rib_routes(header, r.prefix(), &r.rib_entries, peers),
+        MRTRecord::BGP4MPMessage(ref msg) => Ok(update_routes(header, msg)),
+        MRTRecord::PeerIndexTable(_)
+        | MRTRecord::BGP4MPStateChange(_)
+        | MRTRecord::Unsupported => Ok(Vec::new()),
+    }
+}
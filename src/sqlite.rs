@@ -0,0 +1,377 @@
+use bgp::Prefix;
+use mrt::{MRTHeader, MRTRecord, PeerEntry};
+use route::{routes, Route, RouteKind};
+use rusqlite::types::ToSql;
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+
+// Loads records into SQLite with one row per distinct peer, prefix, AS path,
+// attribute set and community, referenced by the RIB entries, updates and
+// state changes. The `routes` view joins it all back together:
+//
+//   SELECT prefix, as_path FROM routes WHERE origin_asn = 13335;
+//
+// Prefixes and origin ASNs are indexed; the indexes are built by finish(),
+// after the bulk load.
+
+const SCHEMA: &str = "
+CREATE TABLE peers (
+    id INTEGER PRIMARY KEY,
+    address TEXT NOT NULL,
+    asn INTEGER NOT NULL,
+    bgp_id TEXT,
+    UNIQUE (address, asn)
+);
+CREATE TABLE prefixes (
+    id INTEGER PRIMARY KEY,
+    prefix TEXT NOT NULL,
+    afi INTEGER NOT NULL,
+    length INTEGER NOT NULL
+);
+CREATE TABLE paths (
+    id INTEGER PRIMARY KEY,
+    as_path TEXT NOT NULL,
+    origin_asn INTEGER,
+    hop_count INTEGER NOT NULL
+);
+CREATE TABLE attributes (
+    id INTEGER PRIMARY KEY,
+    origin TEXT,
+    next_hop TEXT,
+    local_pref INTEGER,
+    med INTEGER,
+    atomic_aggregate INTEGER NOT NULL,
+    aggregator_asn INTEGER,
+    aggregator_address TEXT
+);
+CREATE TABLE communities (
+    id INTEGER PRIMARY KEY,
+    community TEXT NOT NULL UNIQUE
+);
+CREATE TABLE attribute_communities (
+    attributes_id INTEGER NOT NULL REFERENCES attributes (id),
+    community_id INTEGER NOT NULL REFERENCES communities (id)
+);
+CREATE TABLE rib_entries (
+    id INTEGER PRIMARY KEY,
+    timestamp INTEGER NOT NULL,
+    peer_id INTEGER NOT NULL REFERENCES peers (id),
+    prefix_id INTEGER NOT NULL REFERENCES prefixes (id),
+    path_id INTEGER REFERENCES paths (id),
+    attributes_id INTEGER NOT NULL REFERENCES attributes (id)
+);
+CREATE TABLE updates (
+    id INTEGER PRIMARY KEY,
+    timestamp INTEGER NOT NULL,
+    microsecond_timestamp INTEGER,
+    type TEXT NOT NULL,
+    peer_id INTEGER NOT NULL REFERENCES peers (id),
+    prefix_id INTEGER NOT NULL REFERENCES prefixes (id),
+    path_id INTEGER REFERENCES paths (id),
+    attributes_id INTEGER REFERENCES attributes (id)
+);
+CREATE TABLE state_changes (
+    id INTEGER PRIMARY KEY,
+    timestamp INTEGER NOT NULL,
+    microsecond_timestamp INTEGER,
+    peer_id INTEGER NOT NULL REFERENCES peers (id),
+    old_state INTEGER NOT NULL,
+    new_state INTEGER NOT NULL
+);
+CREATE VIEW routes AS
+    SELECT 'B' AS type, r.timestamp, NULL AS microsecond_timestamp, pe.address AS peer_ip,
+           pe.asn AS peer_asn, pr.prefix, pa.as_path, pa.origin_asn, r.attributes_id
+    FROM rib_entries r
+    JOIN peers pe ON pe.id = r.peer_id
+    JOIN prefixes pr ON pr.id = r.prefix_id
+    LEFT JOIN paths pa ON pa.id = r.path_id
+    UNION ALL
+    SELECT u.type, u.timestamp, u.microsecond_timestamp, pe.address, pe.asn, pr.prefix,
+           pa.as_path, pa.origin_asn, u.attributes_id
+    FROM updates u
+    JOIN peers pe ON pe.id = u.peer_id
+    JOIN prefixes pr ON pr.id = u.prefix_id
+    LEFT JOIN paths pa ON pa.id = u.path_id;
+";
+
+const INDEXES: &str = "
+CREATE UNIQUE INDEX prefixes_prefix ON prefixes (prefix);
+CREATE INDEX paths_origin_asn ON paths (origin_asn);
+CREATE INDEX rib_entries_prefix ON rib_entries (prefix_id);
+CREATE INDEX rib_entries_path ON rib_entries (path_id);
+CREATE INDEX updates_prefix ON updates (prefix_id);
+CREATE INDEX updates_path ON updates (path_id);
+CREATE INDEX attribute_communities_community ON attribute_communities (community_id);
+";
+
+// Everything stored in the attributes table and its communities; rows are
+// shared between routes with equal keys.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct AttributeKey {
+    origin: Option<String>,
+    next_hop: Option<String>,
+    local_pref: Option<u32>,
+    med: Option<u32>,
+    atomic_aggregate: bool,
+    aggregator: Option<(u32, String)>,
+    communities: Vec<String>,
+}
+
+impl AttributeKey {
+    fn new(route: &Route) -> AttributeKey {
+        let attrs = &route.attributes;
+        AttributeKey {
+            origin: attrs.origin.map(|o| o.to_string()),
+            next_hop: route.next_hop().map(|a| a.to_string()),
+            local_pref: attrs.local_pref,
+            med: attrs.med,
+            atomic_aggregate: attrs.atomic_aggregate,
+            aggregator: attrs.aggregator.map(|a| (a.asn, a.address.to_string())),
+            communities: route.communities(),
+        }
+    }
+}
+
+fn sql_error(e: rusqlite::Error) -> String {
+    format!("SQLite: {}", e)
+}
+
+pub struct SqliteExporter {
+    conn: Connection,
+    peers: HashMap<(IpAddr, u32), i64>,
+    prefixes: HashMap<Prefix, i64>,
+    paths: HashMap<String, i64>,
+    attributes: HashMap<AttributeKey, i64>,
+    communities: HashMap<String, i64>,
+}
+
+impl SqliteExporter {
+    // Create the database file, which must not already hold these tables.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<SqliteExporter, String> {
+        SqliteExporter::new(Connection::open(path).map_err(sql_error)?)
+    }
+
+    pub fn new(conn: Connection) -> Result<SqliteExporter, String> {
+        conn.execute_batch(SCHEMA).map_err(sql_error)?;
+        conn.execute_batch("BEGIN").map_err(sql_error)?;
+        Ok(SqliteExporter {
+            conn,
+            peers: HashMap::new(),
+            prefixes: HashMap::new(),
+            paths: HashMap::new(),
+            attributes: HashMap::new(),
+            communities: HashMap::new(),
+        })
+    }
+
+    fn insert(&self, sql: &str, params: &[&dyn ToSql]) -> Result<i64, String> {
+        let mut stmt = self.conn.prepare_cached(sql).map_err(sql_error)?;
+        stmt.execute(params).map_err(sql_error)?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    fn peer_id(&mut self, address: IpAddr, asn: u32, bgp_id: Option<String>) -> Result<i64, String> {
+        if let Some(&id) = self.peers.get(&(address, asn)) {
+            return Ok(id);
+        }
+        let id = self.insert(
+            "INSERT INTO peers (address, asn, bgp_id) VALUES (?1, ?2, ?3)",
+            &[&address.to_string(), &asn, &bgp_id],
+        )?;
+        self.peers.insert((address, asn), id);
+        Ok(id)
+    }
+
+    fn prefix_id(&mut self, prefix: &Prefix) -> Result<i64, String> {
+        if let Some(&id) = self.prefixes.get(prefix) {
+            return Ok(id);
+        }
+        let afi = match prefix.addr {
+            IpAddr::V4(_) => 1,
+            IpAddr::V6(_) => 2,
+        };
+        let id = self.insert(
+            "INSERT INTO prefixes (prefix, afi, length) VALUES (?1, ?2, ?3)",
+            &[&prefix.to_string(), &afi, &prefix.len],
+        )?;
+        self.prefixes.insert(*prefix, id);
+        Ok(id)
+    }
+
+    fn path_id(&mut self, route: &Route) -> Result<Option<i64>, String> {
+        let path = match route.attributes.as_path {
+            Some(ref path) => path,
+            None => return Ok(None),
+        };
+        let text = path.to_string();
+        if let Some(&id) = self.paths.get(&text) {
+            return Ok(Some(id));
+        }
+        let id = self.insert(
+            "INSERT INTO paths (as_path, origin_asn, hop_count) VALUES (?1, ?2, ?3)",
+            &[&text, &path.origin_asn(), &(path.hop_count() as i64)],
+        )?;
+        self.paths.insert(text, id);
+        Ok(Some(id))
+    }
+
+    fn community_id(&mut self, community: &str) -> Result<i64, String> {
+        if let Some(&id) = self.communities.get(community) {
+            return Ok(id);
+        }
+        let id = self.insert("INSERT INTO communities (community) VALUES (?1)", &[&community])?;
+        self.communities.insert(community.to_string(), id);
+        Ok(id)
+    }
+
+    fn attributes_id(&mut self, route: &Route) -> Result<i64, String> {
+        let key = AttributeKey::new(route);
+        if let Some(&id) = self.attributes.get(&key) {
+            return Ok(id);
+        }
+        let (aggregator_asn, aggregator_address) = match key.aggregator {
+            Some((asn, ref address)) => (Some(asn), Some(address.clone())),
+            None => (None, None),
+        };
+        let id = self.insert(
+            "INSERT INTO attributes (origin, next_hop, local_pref, med, atomic_aggregate, \
+             aggregator_asn, aggregator_address) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            &[
+                &key.origin,
+                &key.next_hop,
+                &key.local_pref,
+                &key.med,
+                &key.atomic_aggregate,
+                &aggregator_asn,
+                &aggregator_address,
+            ],
+        )?;
+        for community in &key.communities {
+            let community_id = self.community_id(community)?;
+            self.insert(
+                "INSERT INTO attribute_communities (attributes_id, community_id) VALUES (?1, ?2)",
+                &[&id, &community_id],
+            )?;
+        }
+        self.attributes.insert(key, id);
+        Ok(id)
+    }
+
+    fn add_route(&mut self, route: &Route) -> Result<(), String> {
+        let peer_id = self.peer_id(route.peer_address, route.peer_asn, None)?;
+        let prefix_id = self.prefix_id(&route.prefix)?;
+        let path_id = self.path_id(route)?;
+        match route.kind {
+            RouteKind::Rib => {
+                let attributes_id = self.attributes_id(route)?;
+                self.insert(
+                    "INSERT INTO rib_entries (timestamp, peer_id, prefix_id, path_id, \
+                     attributes_id) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    &[&route.timestamp, &peer_id, &prefix_id, &path_id, &attributes_id],
+                )?;
+            }
+            RouteKind::Announce | RouteKind::Withdraw => {
+                let attributes_id = match route.kind {
+                    RouteKind::Announce => Some(self.attributes_id(route)?),
+                    _ => None,
+                };
+                self.insert(
+                    "INSERT INTO updates (timestamp, microsecond_timestamp, type, peer_id, \
+                     prefix_id, path_id, attributes_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    &[
+                        &route.timestamp,
+                        &route.microsecond_timestamp,
+                        &route.kind.code(),
+                        &peer_id,
+                        &prefix_id,
+                        &path_id,
+                        &attributes_id,
+                    ],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    // Load one record. TABLE_DUMP_V2 RIB records need the peers from the
+    // preceding PEER_INDEX_TABLE.
+    pub fn add_record(
+        &mut self,
+        header: &MRTHeader,
+        record: &MRTRecord,
+        peers: &[PeerEntry],
+    ) -> Result<(), String> {
+        match *record {
+            MRTRecord::PeerIndexTable(ref index) => {
+                for peer in &index.peer_entries {
+                    let bgp_id = Some(peer.peer_bgp_id.to_string());
+                    self.peer_id(peer.peer_address, peer.peer_asn, bgp_id)?;
+                }
+                Ok(())
+            }
+            MRTRecord::BGP4MPStateChange(ref state) => {
+                let peer_id = self.peer_id(state.peer.peer_address, state.peer.peer_asn, None)?;
+                self.insert(
+                    "INSERT INTO state_changes (timestamp, microsecond_timestamp, peer_id, \
+                     old_state, new_state) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    &[
+                        &header.timestamp,
+                        &header.microsecond_timestamp,
+                        &peer_id,
+                        &state.old_state,
+                        &state.new_state,
+                    ],
+                )?;
+                Ok(())
+            }
+            _ => {
+                for route in routes(header, record, peers)? {
+                    self.add_route(&route)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Commit the load and build the indexes.
+    pub fn finish(self) -> Result<Connection, String> {
+        self.conn.execute_batch("COMMIT").map_err(sql_error)?;
+        self.conn.execute_batch(INDEXES).map_err(sql_error)?;
+        Ok(self.conn)
+    }
+}
+
+#[test]
+fn add_record_test() {
+    use fixtures::table_dump;
+    use mrt::{parse_mrt_body, split_mrt_record};
+
+    let buffer = table_dump();
+    let (_, header, body) = split_mrt_record(&buffer).unwrap();
+    let record = parse_mrt_body(&header, body).unwrap();
+
+    let mut exporter = SqliteExporter::new(Connection::open_in_memory().unwrap()).unwrap();
+    exporter.add_record(&header, &record, &[]).unwrap();
+    exporter.add_record(&header, &record, &[]).unwrap();
+    let conn = exporter.finish().unwrap();
+
+    let count = |table: &str| -> i64 {
+        conn.query_row(&format!("SELECT count(*) FROM {}", table), [], |row| row.get(0))
+            .unwrap()
+    };
+    assert_eq!(count("rib_entries"), 2);
+    assert_eq!(count("peers"), 1);
+    assert_eq!(count("prefixes"), 1);
+    assert_eq!(count("paths"), 1);
+    assert_eq!(count("attributes"), 1);
+
+    let (prefix, as_path): (String, String) = conn
+        .query_row("SELECT prefix, as_path FROM routes WHERE origin_asn = 80", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .unwrap();
+    assert_eq!(prefix, "3.0.0.0/8");
+    assert_eq!(as_path, "3257 701 80");
+}
@@ -0,0 +1,240 @@
+use bgp::{path_attribute_name, Afi, BGPPathAttribute, Prefix};
+use bgp4mp::BGPMessage;
+use bgpdump::format_time;
+use mrt::{type_names, MRTHeader, MRTRecord, PeerEntry};
+use route;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, Write};
+use std::net::IpAddr;
+
+// A profile of a dump built in one pass over its records: what types of
+// record it holds, which peers and address families the routes come from,
+// how many distinct prefixes and origins there are, which path attributes
+// are used, the time covered and how much of it could not be decoded.
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub records: u64,
+    // records that failed to decode, including trailing bytes that do not
+    // frame as one
+    pub undecodable: u64,
+    // microseconds, of the earliest and latest records
+    pub first: Option<u64>,
+    pub last: Option<u64>,
+    // records per (type, subtype)
+    pub types: BTreeMap<(u16, u16), u64>,
+    // routes per (peer address, peer ASN); the peers of PEER_INDEX_TABLEs
+    // and of BGP4MP records are listed even without routes
+    pub peers: BTreeMap<(IpAddr, u32), u64>,
+    pub ipv4_routes: u64,
+    pub ipv6_routes: u64,
+    pub prefixes: HashSet<Prefix>,
+    pub origin_asns: HashSet<u32>,
+    // occurrences per path attribute type code, once per route for RIBs
+    pub attributes: BTreeMap<u8, u64>,
+}
+
+fn count_attributes(attributes: &mut BTreeMap<u8, u64>, attrs: &[BGPPathAttribute]) {
+    for attr in attrs {
+        *attributes.entry(attr.code).or_insert(0) += 1;
+    }
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats::default()
+    }
+
+    pub fn add(&mut self, header: &MRTHeader, record: &MRTRecord, peers: &[PeerEntry]) {
+        let routes = match route::routes(header, record, peers) {
+            Ok(routes) => routes,
+            Err(_) => return self.add_undecodable(),
+        };
+        self.records += 1;
+        let time = header.time_us();
+        self.first = Some(self.first.map_or(time, |first| first.min(time)));
+        self.last = Some(self.last.map_or(time, |last| last.max(time)));
+        *self.types.entry((header.mrt_type, header.mrt_subtype)).or_insert(0) += 1;
+
+        if let Some(peer) = record.bgp4mp_peer() {
+            self.peers.entry((peer.peer_address, peer.peer_asn)).or_insert(0);
+        }
+        for route in &routes {
+            *self.peers.entry((route.peer_address, route.peer_asn)).or_insert(0) += 1;
+            match route.prefix.afi() {
+                Afi::IPv4 => self.ipv4_routes += 1,
+                Afi::IPv6 => self.ipv6_routes += 1,
+            }
+            self.prefixes.insert(route.prefix);
+            if let Some(asn) = route.origin_asn() {
+                self.origin_asns.insert(asn);
+            }
+        }
+
+        match *record {
+            MRTRecord::PeerIndexTable(ref table) => {
+                for peer in &table.peer_entries {
+                    self.peers.entry((peer.peer_address, peer.peer_asn)).or_insert(0);
+                }
+            }
+            MRTRecord::TableDumpIPv4(ref r) => {
+                count_attributes(&mut self.attributes, &r.attributes().unwrap_or_default())
+            }
+            MRTRecord::TableDumpIPv6(ref r) => {
+                count_attributes(&mut self.attributes, &r.attributes().unwrap_or_default())
+            }
+            MRTRecord::RibIPv4Unicast(ref r) => {
+                for entry in &r.rib_entries {
+                    count_attributes(&mut self.attributes, &entry.bgp_path_attrs);
+                }
+            }
+            MRTRecord::RibIPv6Unicast(ref r) => {
+                for entry in &r.rib_entries {
+                    count_attributes(&mut self.attributes, &entry.bgp_path_attrs);
+                }
+            }
+            MRTRecord::BGP4MPMessage(ref m) => {
+                if let BGPMessage::Update(ref update) = m.message {
+                    count_attributes(&mut self.attributes, &update.path_attributes);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn add_undecodable(&mut self) {
+        self.undecodable += 1;
+    }
+
+    pub fn write_text<W: Write + ?Sized>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "records: {}", self.records)?;
+        writeln!(out, "undecodable: {}", self.undecodable)?;
+        if let (Some(first), Some(last)) = (self.first, self.last) {
+            writeln!(
+                out,
+                "time: {} - {} ({}.{:06} - {}.{:06})",
+                format_time((first / 1_000_000) as u32),
+                format_time((last / 1_000_000) as u32),
+                first / 1_000_000,
+                first % 1_000_000,
+                last / 1_000_000,
+                last % 1_000_000
+            )?;
+        }
+        writeln!(out, "types:")?;
+        for (&(mrt_type, subtype), count) in &self.types {
+            let (type_name, subtype_name) = type_names(mrt_type, subtype);
+            writeln!(
+                out,
+                "  {}/{}: {}",
+                type_name.unwrap_or_else(|| mrt_type.to_string()),
+                subtype_name.unwrap_or_else(|| subtype.to_string()),
+                count
+            )?;
+        }
+        writeln!(out, "peers:")?;
+        for (&(address, asn), routes) in &self.peers {
+            writeln!(out, "  {} AS{}: {}", address, asn, routes)?;
+        }
+        writeln!(out, "routes: ipv4 {}, ipv6 {}", self.ipv4_routes, self.ipv6_routes)?;
+        writeln!(out, "unique prefixes: {}", self.prefixes.len())?;
+        writeln!(out, "unique origin ASNs: {}", self.origin_asns.len())?;
+        writeln!(out, "attributes:")?;
+        for (&code, count) in &self.attributes {
+            match path_attribute_name(code) {
+                Some(name) => writeln!(out, "  {} ({}): {}", name, code, count)?,
+                None => writeln!(out, "  {}: {}", code, count)?,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Value {
+        let types: Vec<Value> = self
+            .types
+            .iter()
+            .map(|(&(mrt_type, subtype), count)| {
+                let (type_name, subtype_name) = type_names(mrt_type, subtype);
+                json!({
+                    "type": mrt_type,
+                    "subtype": subtype,
+                    "type_name": type_name,
+                    "subtype_name": subtype_name,
+                    "count": count,
+                })
+            })
+            .collect();
+        let peers: Vec<Value> = self
+            .peers
+            .iter()
+            .map(|(&(address, asn), routes)| {
+                json!({"address": address.to_string(), "asn": asn, "routes": routes})
+            })
+            .collect();
+        let attributes: Vec<Value> = self
+            .attributes
+            .iter()
+            .map(|(&code, count)| json!({"code": code, "name": path_attribute_name(code), "count": count}))
+            .collect();
+        json!({
+            "records": self.records,
+            "undecodable": self.undecodable,
+            "first_timestamp": self.first.map(|us| us as f64 / 1e6),
+            "last_timestamp": self.last.map(|us| us as f64 / 1e6),
+            "types": types,
+            "peers": peers,
+            "routes": {"ipv4": self.ipv4_routes, "ipv6": self.ipv6_routes},
+            "unique_prefixes": self.prefixes.len(),
+            "unique_origin_asns": self.origin_asns.len(),
+            "attributes": attributes,
+        })
+    }
+}
+
+#[test]
+fn stats_test() {
+    use mrt::MRTReader;
+
+    // a PEER_INDEX_TABLE with two peers, a RIB record for 198.51.100.0/24
+    // with an entry from the first (ORIGIN, AS_PATH 64496), then junk
+    let buffer = hex::decode(concat!(
+        "00000001", "000d", "0001", "00000022",
+        "c0000201", "0000", "0002",
+        "02", "0a000001", "c0000201", "0000fbf0",
+        "02", "0a000002", "c0000202", "0000fbf1",
+        "00000002", "000d", "0002", "0000001f",
+        "00000000", "18c63364", "0001",
+        "0000", "00000002", "000d", "40010100", "400206020100 00fbf0",
+        "ffff"
+    ).replace(' ', ""))
+    .unwrap();
+
+    let mut stats = Stats::new();
+    let mut peers = Vec::new();
+    for entry in MRTReader::new(&buffer) {
+        match entry {
+            Ok((header, record)) => {
+                stats.add(&header, &record, &peers);
+                if let MRTRecord::PeerIndexTable(table) = record {
+                    peers = table.peer_entries;
+                }
+            }
+            Err(_) => stats.add_undecodable(),
+        }
+    }
+    assert_eq!((stats.records, stats.undecodable), (2, 1));
+    assert_eq!((stats.first, stats.last), (Some(1_000_000), Some(2_000_000)));
+    assert_eq!(stats.types.get(&(13, 2)), Some(&1));
+    assert_eq!(stats.peers.len(), 2);
+    assert_eq!(stats.peers.get(&("192.0.2.1".parse().unwrap(), 64496)), Some(&1));
+    assert_eq!((stats.ipv4_routes, stats.prefixes.len(), stats.origin_asns.len()), (1, 1, 1));
+    assert_eq!(stats.attributes.get(&2), Some(&1));
+
+    let json = stats.to_json();
+    assert_eq!(json["types"][1]["subtype_name"], "RIB_IPV4_UNICAST");
+    assert_eq!(json["unique_prefixes"], 1);
+    let mut text = Vec::new();
+    stats.write_text(&mut text).unwrap();
+    assert!(String::from_utf8(text).unwrap().contains("AS_PATH (2): 1"));
+}